@@ -5,13 +5,17 @@ use reqwest::{
 use reqwest_websocket::{RequestBuilderExt, UpgradeResponse};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::{fmt, time::Duration};
+use std::{fmt, sync::Arc, time::Duration};
+use tokio::sync::OwnedSemaphorePermit;
 
-#[derive(Debug)]
+use super::throttle::Throttle;
+
+#[derive(Debug, Clone)]
 pub struct JupyterLabClient {
   client: Client,
   base_url: Url,
   auth_header: Option<HeaderValue>,
+  throttle: Option<Arc<Throttle>>,
 }
 
 #[derive(Debug)]
@@ -19,6 +23,7 @@ pub struct JupyterLabClientBuilder {
   base_url: Url,
   client_builder: ClientBuilder,
   auth_header: Option<HeaderValue>,
+  throttle: Option<Arc<Throttle>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -31,7 +36,9 @@ pub enum ClientError {
   InvalidBaseUrl(String),
   InvalidInput(String),
   Http(reqwest::Error),
-  Websocket(reqwest_websocket::Error),
+  Decode(serde_json::Error),
+  /// Boxed, as the websocket error is several times larger than the other variants.
+  Websocket(Box<reqwest_websocket::Error>),
   Api { status: StatusCode, message: String },
   InvalidHeader(String),
 }
@@ -42,6 +49,7 @@ impl fmt::Display for ClientError {
       ClientError::InvalidBaseUrl(msg) => write!(f, "invalid base url: {msg}"),
       ClientError::InvalidInput(msg) => write!(f, "invalid input: {msg}"),
       ClientError::Http(err) => write!(f, "http error: {err}"),
+      ClientError::Decode(err) => write!(f, "invalid response body: {err}"),
       ClientError::Websocket(err) => write!(f, "websocket error: {err}"),
       ClientError::Api { status, message } => {
         if message.is_empty() {
@@ -87,6 +95,7 @@ impl JupyterLabClient {
       client,
       base_url,
       auth_header,
+      throttle: None,
     })
   }
 
@@ -98,6 +107,34 @@ impl JupyterLabClient {
     &self.client
   }
 
+  /// A copy of this client that authenticates with `token` instead.
+  ///
+  /// The HTTP connection pool and any throttle are shared with `self`.
+  pub fn for_token(&self, token: impl AsRef<str>) -> Result<Self, ClientError> {
+    let header = build_token_header(token.as_ref())?;
+    Ok(Self { auth_header: Some(header), ..self.clone() })
+  }
+
+  /// Limits shared by this client and its clones, if any were configured.
+  pub fn throttle(&self) -> Option<&Arc<Throttle>> {
+    self.throttle.as_ref()
+  }
+
+  /// Wait for a request slot when the number of in-flight requests is limited.
+  pub(crate) async fn acquire_slot(&self) -> Option<OwnedSemaphorePermit> {
+    match &self.throttle {
+      Some(throttle) => throttle.acquire().await,
+      None => None,
+    }
+  }
+
+  /// Charge `bytes` against the rate limit, sleeping when it has been exceeded.
+  pub(crate) async fn consume(&self, bytes: u64) {
+    if let Some(throttle) = &self.throttle {
+      throttle.consume(bytes).await;
+    }
+  }
+
   pub(super) fn request(&self, method: Method, url: Url) -> RequestBuilder {
     let request = self.client.request(method, url);
     match &self.auth_header {
@@ -110,8 +147,11 @@ impl JupyterLabClient {
   where
     T: DeserializeOwned,
   {
-    let response = self.send(request).await?;
-    response.json::<T>().await.map_err(ClientError::Http)
+    let _slot = self.acquire_slot().await;
+    let response = self.execute(request).await?;
+    let body = response.bytes().await.map_err(ClientError::Http)?;
+    self.consume(body.len() as u64).await;
+    serde_json::from_slice(&body).map_err(ClientError::Decode)
   }
 
   pub(super) async fn send_empty(&self, request: RequestBuilder) -> Result<(), ClientError> {
@@ -129,7 +169,7 @@ impl JupyterLabClient {
       _ => Err(()),
     }.map_err(|_| ClientError::InvalidBaseUrl(format!("could not set_scheme from {url}")))?;
     let request = RequestBuilder::from_parts(client, request);
-    let response = request.upgrade().send().await.map_err(ClientError::Websocket)?;
+    let response = request.upgrade().send().await.map_err(|err| ClientError::Websocket(Box::new(err)))?;
     let status = response.status();
     if status != StatusCode::SWITCHING_PROTOCOLS {
       let message = response.into_inner().text().await.unwrap_or_default();
@@ -138,8 +178,22 @@ impl JupyterLabClient {
     Ok(response)
   }
 
+  /// Send a request, holding a request slot until the response headers arrive.
+  ///
+  /// Streamed response bodies are not covered by the slot; callers charge them
+  /// against the rate limit with [`JupyterLabClient::consume`].
   pub(super) async fn send(&self, request: RequestBuilder) -> Result<Response, ClientError> {
-    let response = request.send().await.map_err(ClientError::Http)?;
+    let _slot = self.acquire_slot().await;
+    self.execute(request).await
+  }
+
+  async fn execute(&self, request: RequestBuilder) -> Result<Response, ClientError> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    if let Some(body) = request.body().and_then(|body| body.as_bytes()) {
+      self.consume(body.len() as u64).await;
+    }
+    let response = client.execute(request).await.map_err(ClientError::Http)?;
     if response.status().is_success() {
       Ok(response)
     } else {
@@ -193,6 +247,7 @@ impl JupyterLabClientBuilder {
       base_url,
       client_builder: Client::builder(),
       auth_header: None,
+      throttle: None,
     })
   }
 
@@ -235,12 +290,19 @@ impl JupyterLabClientBuilder {
     self
   }
 
+  /// Apply bandwidth and concurrency limits to every HTTP request of the built client.
+  pub fn throttle(mut self, throttle: Arc<Throttle>) -> Self {
+    self.throttle = (!throttle.is_noop()).then_some(throttle);
+    self
+  }
+
   pub fn build(self) -> Result<JupyterLabClient, ClientError> {
     let client = self.client_builder.build().map_err(ClientError::Http)?;
     Ok(JupyterLabClient {
       client,
       base_url: self.base_url,
       auth_header: self.auth_header,
+      throttle: self.throttle,
     })
   }
 }
@@ -400,7 +400,7 @@ impl JupyterApi for JupyterLabClient {
     ])?;
     let request = self.request(Method::GET, url);
     let resp = self.send_ws(request).await?;
-    resp.into_websocket().await.map_err(ClientError::Websocket)
+    resp.into_websocket().await.map_err(|err| ClientError::Websocket(Box::new(err)))
   }
 
   async fn delete_terminal(&self, terminal_id: &str) -> Result<(), ClientError> {
@@ -473,6 +473,13 @@ impl JupyterLabApi for JupyterLabClient {
     self.send(request).await
   }
 
+  async fn get_files(&self, path: &str, range: Option<(u64, Option<u64>)>) -> Result<Vec<u8>, ClientError> {
+    let response = self.get_files_stream(path, range).await?;
+    let bytes = response.bytes().await.map_err(ClientError::Http)?;
+    self.consume(bytes.len() as u64).await;
+    Ok(bytes.to_vec())
+  }
+
   async fn list_workspaces(&self) -> Result<Workspaces, ClientError> {
     let url = self.build_url(&[
       Segment::literal("lab"),
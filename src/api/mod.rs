@@ -1,4 +1,5 @@
 pub mod client;
 pub mod param;
 pub mod resp;
+pub mod throttle;
 pub mod jupyter;
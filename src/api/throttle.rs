@@ -0,0 +1,120 @@
+use std::{
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Shared bandwidth and concurrency limits applied to every HTTP request made by a
+/// [`JupyterLabClient`](super::client::JupyterLabClient) and its clones.
+///
+/// Terminal WebSocket traffic is not throttled.
+#[derive(Debug, Default)]
+pub struct Throttle {
+  bucket: Option<Mutex<TokenBucket>>,
+  slots: Option<Arc<Semaphore>>,
+}
+
+impl Throttle {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Limit request and response bodies to `bytes_per_sec` on average, allowing one second of burst.
+  pub fn limit_rate(mut self, bytes_per_sec: u64) -> Self {
+    self.bucket = (bytes_per_sec > 0).then(|| Mutex::new(TokenBucket::new(bytes_per_sec)));
+    self
+  }
+
+  /// Allow at most `jobs` requests in flight at once.
+  pub fn max_in_flight(mut self, jobs: usize) -> Self {
+    self.slots = (jobs > 0).then(|| Arc::new(Semaphore::new(jobs)));
+    self
+  }
+
+  pub fn is_noop(&self) -> bool {
+    self.bucket.is_none() && self.slots.is_none()
+  }
+
+  /// Wait for a request slot; the slot is released when the permit is dropped.
+  pub async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+    match &self.slots {
+      Some(slots) => slots.clone().acquire_owned().await.ok(),
+      None => None,
+    }
+  }
+
+  /// Account for `bytes` of transferred data, sleeping if the rate limit is exceeded.
+  pub async fn consume(&self, bytes: u64) {
+    let Some(bucket) = &self.bucket else {
+      return;
+    };
+    let wait = bucket.lock().take(bytes, Instant::now());
+    if !wait.is_zero() {
+      trace!(bytes, wait_ms = wait.as_millis() as u64, "rate limit reached; pausing");
+      tokio::time::sleep(wait).await;
+    }
+  }
+}
+
+/// Token bucket that lets the balance go negative, so a single large body is paid
+/// for by waiting instead of being rejected.
+#[derive(Debug)]
+struct TokenBucket {
+  rate: f64,
+  tokens: f64,
+  updated: Instant,
+}
+
+impl TokenBucket {
+  fn new(bytes_per_sec: u64) -> Self {
+    Self {
+      rate: bytes_per_sec as f64,
+      tokens: bytes_per_sec as f64,
+      updated: Instant::now(),
+    }
+  }
+
+  /// Withdraw `bytes` and return how long the caller must wait to stay within the rate.
+  fn take(&mut self, bytes: u64, now: Instant) -> Duration {
+    let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+    self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+    self.updated = now;
+    self.tokens -= bytes as f64;
+    if self.tokens >= 0.0 {
+      Duration::ZERO
+    } else {
+      Duration::from_secs_f64(-self.tokens / self.rate)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn token_bucket_charges_debt_as_waiting_time() {
+    let start = Instant::now();
+    let mut bucket = TokenBucket::new(1000);
+    bucket.updated = start;
+    assert_eq!(bucket.take(500, start), Duration::ZERO);
+    assert_eq!(bucket.take(1500, start), Duration::from_secs(1));
+    // Half a second refills 500 bytes, which only pays back half the debt.
+    assert_eq!(bucket.take(0, start + Duration::from_millis(500)), Duration::from_millis(500));
+    assert_eq!(bucket.take(0, start + Duration::from_secs(5)), Duration::ZERO);
+    assert_eq!(bucket.tokens, 1000.0);
+  }
+
+  #[tokio::test]
+  async fn max_in_flight_bounds_permits() {
+    let throttle = Throttle::new().max_in_flight(1);
+    let first = throttle.acquire().await;
+    assert!(first.is_some());
+    assert!(tokio::time::timeout(Duration::from_millis(20), throttle.acquire()).await.is_err());
+    drop(first);
+    assert!(throttle.acquire().await.is_some());
+    assert!(Throttle::new().acquire().await.is_none());
+  }
+}
@@ -0,0 +1,115 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::Context;
+use clap::{value_parser, ArgAction, Args, Subcommand, ValueHint};
+use jupyter_shell::fs::FsService;
+use reqwest::Url;
+use tracing::info;
+
+use crate::cli::{DEFAULT_JUPYTER_URL, TokenArgs};
+
+#[derive(Args, Debug)]
+#[command(about = "List, create, restore and prune Jupyter checkpoints")]
+pub struct CheckpointArgs {
+  #[arg(long = "endpoint", value_name = "JUPYTER_URL", default_value = DEFAULT_JUPYTER_URL, help = "Full Jupyter URL (supports ?token=<value>)")]
+  endpoint_url: Url,
+  #[arg(long, value_name = "TOKEN", env = "JUPYTER_TOKEN", help = "Override the token provided in the Jupyter URL")]
+  token: Option<String>,
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, env = "JUPYTER_TOKEN_FILE", conflicts_with = "token", help = "Load the API token from a file")]
+  token_file: Option<PathBuf>,
+
+  #[arg(long = "timeout", value_name = "SECONDS", env = "JUPYTER_SHELL_HTTP_TIMEOUT", value_parser = value_parser!(u64).range(1..=3600), help = "HTTP client timeout in seconds")]
+  http_timeout_secs: Option<u64>,
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_ACCEPT_INVALID_CERTS", help = "Disable TLS certificate verification for the Jupyter endpoint")]
+  accept_invalid_certs: bool,
+  #[arg(long, value_name = "PATH", env = "JUPYTER_SHELL_API_BASE_PATH", help = "Override the API base path instead of auto-detecting it")]
+  api_base_path: Option<String>,
+
+  #[command(subcommand)]
+  action: CheckpointAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum CheckpointAction {
+  #[command(about = "List the checkpoints of a remote file")]
+  List {
+    #[arg(value_name = "REMOTE_PATH")]
+    path: String,
+  },
+  #[command(about = "Snapshot the current contents of a remote file")]
+  Create {
+    #[arg(value_name = "REMOTE_PATH")]
+    path: String,
+  },
+  #[command(about = "Roll a remote file back to a checkpoint (the latest by default)")]
+  Restore {
+    #[arg(value_name = "REMOTE_PATH")]
+    path: String,
+    #[arg(long, value_name = "CHECKPOINT_ID", help = "Checkpoint to restore instead of the latest one")]
+    id: Option<String>,
+  },
+  #[command(about = "Delete old checkpoints of a remote file")]
+  Prune {
+    #[arg(value_name = "REMOTE_PATH")]
+    path: String,
+    #[arg(long, value_name = "COUNT", default_value_t = 1, help = "Number of most recent checkpoints to keep")]
+    keep: usize,
+  },
+}
+
+pub(crate) async fn run(args: CheckpointArgs) -> anyhow::Result<()> {
+  let token_args = TokenArgs {
+    endpoint_url: args.endpoint_url,
+    token: args.token,
+    token_file: args.token_file,
+    api_base_path: args.api_base_path,
+    http_timeout_secs: args.http_timeout_secs,
+    accept_invalid_certs: args.accept_invalid_certs,
+  };
+  let client = token_args.build_client()?;
+  let fs = FsService::new(Arc::new(client));
+
+  match args.action {
+    CheckpointAction::List { path } => {
+      let checkpoints = fs
+        .checkpoints(&path)
+        .await
+        .with_context(|| format!("failed to list checkpoints for {path}"))?;
+      if checkpoints.is_empty() {
+        println!("no checkpoints for {path}");
+      }
+      for checkpoint in checkpoints {
+        println!("{}  {}", checkpoint.id, checkpoint.last_modified.to_rfc3339());
+      }
+    }
+    CheckpointAction::Create { path } => {
+      let checkpoint = fs
+        .snapshot(&path)
+        .await
+        .with_context(|| format!("failed to create a checkpoint for {path}"))?;
+      println!("{}  {}", checkpoint.id, checkpoint.last_modified.to_rfc3339());
+    }
+    CheckpointAction::Restore { path, id: Some(id) } => {
+      fs
+        .restore(&path, &id)
+        .await
+        .with_context(|| format!("failed to restore {path} to checkpoint {id}"))?;
+      info!(%path, checkpoint = %id, "Restored checkpoint");
+    }
+    CheckpointAction::Restore { path, id: None } => {
+      let checkpoint = fs
+        .restore_latest(&path)
+        .await
+        .with_context(|| format!("failed to restore the latest checkpoint of {path}"))?;
+      info!(%path, checkpoint = %checkpoint.id, "Restored latest checkpoint");
+    }
+    CheckpointAction::Prune { path, keep } => {
+      let removed = fs
+        .prune(&path, keep)
+        .await
+        .with_context(|| format!("failed to prune checkpoints for {path}"))?;
+      info!(%path, removed, keep, "Pruned checkpoints");
+    }
+  }
+  Ok(())
+}
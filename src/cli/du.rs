@@ -0,0 +1,92 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::Context;
+use clap::{value_parser, ArgAction, Args, ValueHint};
+use jupyter_shell::fs::FsService;
+use reqwest::Url;
+
+use crate::cli::{DEFAULT_JUPYTER_URL, TokenArgs};
+
+#[derive(Args, Debug)]
+#[command(about = "Summarize disk usage of a remote directory")]
+pub struct DuArgs {
+  #[arg(long = "endpoint", value_name = "JUPYTER_URL", default_value = DEFAULT_JUPYTER_URL, help = "Full Jupyter URL (supports ?token=<value>)")]
+  endpoint_url: Url,
+  #[arg(long, value_name = "TOKEN", env = "JUPYTER_TOKEN", help = "Override the token provided in the Jupyter URL")]
+  token: Option<String>,
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, env = "JUPYTER_TOKEN_FILE", conflicts_with = "token", help = "Load the API token from a file")]
+  token_file: Option<PathBuf>,
+
+  #[arg(long = "timeout", value_name = "SECONDS", env = "JUPYTER_SHELL_HTTP_TIMEOUT", value_parser = value_parser!(u64).range(1..=3600), help = "HTTP client timeout in seconds")]
+  http_timeout_secs: Option<u64>,
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_ACCEPT_INVALID_CERTS", help = "Disable TLS certificate verification for the Jupyter endpoint")]
+  accept_invalid_certs: bool,
+  #[arg(long, value_name = "PATH", env = "JUPYTER_SHELL_API_BASE_PATH", help = "Override the API base path instead of auto-detecting it")]
+  api_base_path: Option<String>,
+
+  #[arg(value_name = "REMOTE_PATH", default_value = "/")]
+  path: String,
+  #[arg(short = 'd', long, value_name = "DEPTH", default_value_t = 1, help = "Print directories at most this many levels below REMOTE_PATH")]
+  max_depth: usize,
+  #[arg(long, value_name = "COUNT", help = "Also print the COUNT largest files and directories")]
+  top: Option<usize>,
+  #[arg(short = 'b', long, action = ArgAction::SetTrue, help = "Print sizes in bytes instead of human-readable units")]
+  bytes: bool,
+}
+
+pub(crate) async fn run(args: DuArgs) -> anyhow::Result<()> {
+  let token_args = TokenArgs {
+    endpoint_url: args.endpoint_url,
+    token: args.token,
+    token_file: args.token_file,
+    api_base_path: args.api_base_path,
+    http_timeout_secs: args.http_timeout_secs,
+    accept_invalid_certs: args.accept_invalid_certs,
+  };
+  let client = token_args.build_client()?;
+  let fs = FsService::new(Arc::new(client));
+
+  let usage = fs
+    .du(&args.path)
+    .await
+    .with_context(|| format!("failed to summarize disk usage of {}", args.path))?;
+  let format = |size: u64| if args.bytes { size.to_string() } else { human_size(size) };
+  let root = args.path.trim_matches('/');
+  let display = |relative: &str| {
+    let parts: Vec<&str> = [root, relative].into_iter().filter(|part| !part.is_empty()).collect();
+    format!("/{}", parts.join("/"))
+  };
+
+  for (path, size) in usage.directories_within(args.max_depth).filter(|(path, _)| !path.is_empty()) {
+    println!("{:>8}  {}", format(size), display(path));
+  }
+  println!("{:>8}  {} ({} files)", format(usage.total_bytes), display(""), usage.files);
+
+  if let Some(count) = args.top {
+    println!();
+    println!("largest entries:");
+    for (path, size) in usage.largest(count) {
+      println!("{:>8}  {}", format(size), display(path));
+    }
+  }
+  Ok(())
+}
+
+/// Format `bytes` with binary units the way `du -h` does (e.g. `512`, `1.5K`, `20M`).
+fn human_size(bytes: u64) -> String {
+  const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+  if bytes < 1024 {
+    return bytes.to_string();
+  }
+  let mut value = bytes as f64 / 1024.0;
+  let mut unit = 0;
+  while value >= 1024.0 && unit < UNITS.len() - 1 {
+    value /= 1024.0;
+    unit += 1;
+  }
+  if value < 10.0 {
+    format!("{:.1}{}", value, UNITS[unit])
+  } else {
+    format!("{:.0}{}", value, UNITS[unit])
+  }
+}
@@ -1,15 +1,18 @@
 use std::{
   net::SocketAddr,
-  path::PathBuf,
+  ops::RangeInclusive,
+  path::{Path, PathBuf},
   sync::Arc,
+  time::Duration,
 };
 
-use clap::{value_parser, ArgAction, Args, ValueHint};
-use jupyter_shell::{fs::FsService, ftp};
+use anyhow::{bail, Context};
+use clap::{builder::RangedU64ValueParser, value_parser, ArgAction, Args, ValueHint};
+use jupyter_shell::{fs::FsService, ftp::{self, FtpAuthenticator, FtpsOptions, SessionLimit}};
 use reqwest::Url;
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::cli::{DEFAULT_JUPYTER_URL, TokenArgs};
+use crate::cli::{build_throttle, parse_rate, DEFAULT_JUPYTER_URL, TokenArgs};
 
 const FTP_BIND_ADDR: &str = "0.0.0.0:8021";
 
@@ -24,10 +27,61 @@ pub(crate) async fn run(args: FtpArgs) -> anyhow::Result<()> {
   };
   let base_url = token_args.derive_base_url()?;
 
-  let client = token_args.build_client()?;
+  let throttle = build_throttle(args.limit_rate, args.jobs);
+  let client = if args.token_auth {
+    token_args.build_client_with_optional_token(throttle)?
+  } else {
+    token_args.build_throttled_client(throttle)?
+  };
 
-  let fs = FsService::new(Arc::new(client));
-  let server = ftp::server_builder(fs).build()?;
+  let mut fs = FsService::new(Arc::new(client));
+  if let Some(ttl) = args.cache_ttl_secs.filter(|ttl| *ttl > 0) {
+    fs = fs.with_cache(Duration::from_secs(ttl));
+  }
+  if args.shell_metadata {
+    fs = fs.with_shell_fallback();
+  }
+  let authenticator = build_authenticator(
+    args.user.as_deref(),
+    args.password.as_deref(),
+    args.password_file.as_deref(),
+    args.token_auth,
+    &fs,
+  )?;
+  let storage = ftp::FsStorage::new(fs)
+    .recursive_rmdir(args.recursive_rmd)
+    .atomic_uploads(!args.no_atomic_uploads)
+    .checkpoint_uploads(args.checkpoint)
+    .shell_metadata(args.shell_metadata)
+    .read_only(args.read_only);
+  let storage = match &args.root {
+    Some(root) => storage.root(root),
+    None => storage,
+  };
+  let mut builder = ftp::server_builder_with(storage);
+  builder = match args.max_connections {
+    Some(max) => SessionLimit::new(max).apply(builder, authenticator),
+    None => builder.authenticator(Arc::new(authenticator)),
+  };
+  if let Some(ports) = args.passive_ports.clone() {
+    builder = builder.passive_ports(ports);
+  }
+  if let Some(secs) = args.idle_timeout_secs {
+    builder = builder.idle_session_timeout(secs);
+  }
+  if let Some(greeting) = args.greeting.clone() {
+    // libunftp keeps the greeting for the lifetime of the server.
+    builder = builder.greeting(Box::leak(greeting.into_boxed_str()));
+  }
+  let tls = match (&args.tls_cert, &args.tls_key) {
+    (Some(cert), Some(key)) => {
+      let tls = FtpsOptions::new(cert, key).required(args.require_tls);
+      builder = tls.apply(builder);
+      true
+    }
+    _ => false,
+  };
+  let server = builder.build().context("failed to configure the FTP server")?;
 
   let bind = if let Some(port) = args.bind_port {
     SocketAddr::new(args.bind.ip(), port)
@@ -38,6 +92,10 @@ pub(crate) async fn run(args: FtpArgs) -> anyhow::Result<()> {
     %base_url,
     %bind,
     tls_verification_disabled = args.accept_invalid_certs,
+    ftps = tls,
+    ftps_required = args.require_tls,
+    read_only = args.read_only,
+    root = args.root.as_deref().unwrap_or("/"),
     "Serving Jupyter over FTP"
   );
 
@@ -46,6 +104,58 @@ pub(crate) async fn run(args: FtpArgs) -> anyhow::Result<()> {
   Ok(())
 }
 
+/// Parse a passive port range such as `50000-50100`.
+fn parse_port_range(value: &str) -> Result<RangeInclusive<u16>, String> {
+  let (start, end) = value
+    .split_once('-')
+    .ok_or_else(|| format!("invalid port range {value:?}; expected START-END such as 50000-50100"))?;
+  let parse = |port: &str| {
+    port
+      .trim()
+      .parse::<u16>()
+      .ok()
+      .filter(|port| *port > 0)
+      .ok_or_else(|| format!("invalid port {port:?} in range {value:?}"))
+  };
+  let (start, end) = (parse(start)?, parse(end)?);
+  if start > end {
+    return Err(format!("port range {value:?} starts after it ends"));
+  }
+  Ok(start..=end)
+}
+
+/// Pick how FTP logins are checked from `--user`/`--password` or `--token-auth`.
+fn build_authenticator(
+  user: Option<&str>,
+  password: Option<&str>,
+  password_file: Option<&Path>,
+  token_auth: bool,
+  fs: &FsService,
+) -> anyhow::Result<FtpAuthenticator> {
+  if token_auth {
+    info!("FTP logins authenticate with the user's Jupyter token as the password");
+    return Ok(FtpAuthenticator::Token { fs: fs.clone() });
+  }
+  let password = match (password, password_file) {
+    (Some(password), _) => Some(password.to_string()),
+    (None, Some(path)) => {
+      let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read password file {}", path.display()))?;
+      Some(contents.trim_end_matches(['\r', '\n']).to_string())
+    }
+    (None, None) => None,
+  };
+  match (user, password) {
+    (Some(username), Some(password)) => Ok(FtpAuthenticator::Password { username: username.to_string(), password }),
+    (Some(_), None) => bail!("--user requires --password or --password-file"),
+    (None, Some(_)) => bail!("--password requires --user"),
+    (None, None) => {
+      warn!("FTP gateway accepts anonymous logins; use --user/--password or --token-auth to require credentials");
+      Ok(FtpAuthenticator::Anonymous)
+    }
+  }
+}
+
 #[derive(Args, Debug)]
 #[command(about = "Expose a Jupyter deployment over FTP")]
 pub struct FtpArgs {
@@ -67,4 +177,48 @@ pub struct FtpArgs {
   bind: SocketAddr,
   #[arg(short = 'p', long, value_name = "PORT", env = "JUPYTER_SHELL_BIND_PORT", help = "Port to bind the FTP server to (overrides --bind)")]
   bind_port: Option<u16>,
+
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_FTP_RECURSIVE_RMD", help = "Allow RMD to remove non-empty directories recursively")]
+  recursive_rmd: bool,
+  #[arg(long = "cache-ttl", value_name = "SECONDS", env = "JUPYTER_SHELL_FTP_CACHE_TTL", value_parser = value_parser!(u64).range(0..=3600), help = "Cache file metadata and directory listings for this many seconds (0 disables)")]
+  cache_ttl_secs: Option<u64>,
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_FTP_NO_ATOMIC_UPLOADS", help = "Write uploads directly to the destination instead of a temporary file that is renamed into place")]
+  no_atomic_uploads: bool,
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_FTP_CHECKPOINT", help = "Create a Jupyter checkpoint before overwriting an existing file")]
+  checkpoint: bool,
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_FTP_SHELL_METADATA", help = "Report real permissions, owners and symlinks by running helpers in a Jupyter terminal")]
+  shell_metadata: bool,
+  #[arg(long, value_name = "RATE", env = "JUPYTER_SHELL_FTP_LIMIT_RATE", value_parser = parse_rate, help = "Limit bandwidth to the Jupyter server, shared by all sessions, to RATE bytes per second (accepts K, M and G suffixes)")]
+  limit_rate: Option<u64>,
+
+  #[arg(long, value_name = "NAME", env = "JUPYTER_SHELL_FTP_USER", help = "Require this FTP username (together with --password or --password-file)")]
+  user: Option<String>,
+  #[arg(long, value_name = "PASSWORD", env = "JUPYTER_SHELL_FTP_PASSWORD", hide_env_values = true, help = "Password required for --user")]
+  password: Option<String>,
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, env = "JUPYTER_SHELL_FTP_PASSWORD_FILE", conflicts_with = "password", help = "Load the password required for --user from a file")]
+  password_file: Option<PathBuf>,
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_FTP_TOKEN_AUTH", conflicts_with_all = ["user", "password", "password_file"], help = "Treat the FTP password as a Jupyter token and give each login its own client")]
+  token_auth: bool,
+
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, env = "JUPYTER_SHELL_FTP_TLS_CERT", requires = "tls_key", help = "PEM certificate chain; enables explicit FTPS (AUTH TLS)")]
+  tls_cert: Option<PathBuf>,
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, env = "JUPYTER_SHELL_FTP_TLS_KEY", requires = "tls_cert", help = "PEM private key for --tls-cert")]
+  tls_key: Option<PathBuf>,
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_FTP_REQUIRE_TLS", requires = "tls_cert", help = "Refuse clients that do not use TLS for both the control and data channels")]
+  require_tls: bool,
+  #[arg(long, value_name = "N", env = "JUPYTER_SHELL_FTP_JOBS", value_parser = RangedU64ValueParser::<usize>::new().range(1..), help = "Allow at most N HTTP requests to the Jupyter server in flight at once across all sessions")]
+  jobs: Option<usize>,
+
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_FTP_READ_ONLY", help = "Reject uploads, deletes, renames and directory changes (STOR, DELE, RNFR, MKD, RMD)")]
+  read_only: bool,
+  #[arg(long, value_name = "REMOTE_DIR", env = "JUPYTER_SHELL_FTP_ROOT", help = "Confine clients to this remote directory, which they see as /")]
+  root: Option<String>,
+  #[arg(long, value_name = "START-END", env = "JUPYTER_SHELL_FTP_PASSIVE_PORTS", value_parser = parse_port_range, help = "Port range for passive data connections, e.g. 50000-50100")]
+  passive_ports: Option<RangeInclusive<u16>>,
+  #[arg(long, value_name = "TEXT", env = "JUPYTER_SHELL_FTP_GREETING", help = "Message sent to clients when they connect")]
+  greeting: Option<String>,
+  #[arg(long = "idle-timeout", value_name = "SECONDS", env = "JUPYTER_SHELL_FTP_IDLE_TIMEOUT", value_parser = value_parser!(u64).range(1..), help = "Disconnect sessions that stay idle for this many seconds")]
+  idle_timeout_secs: Option<u64>,
+  #[arg(long, value_name = "N", env = "JUPYTER_SHELL_FTP_MAX_CONNECTIONS", value_parser = RangedU64ValueParser::<usize>::new().range(1..), help = "Refuse logins while N sessions are already logged in")]
+  max_connections: Option<usize>,
 }
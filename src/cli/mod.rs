@@ -1,15 +1,35 @@
-use std::{fs, path::PathBuf, time::Duration};
+#[cfg(feature = "sshd")]
+use std::net::SocketAddr;
+use std::{fs, path::PathBuf, sync::Arc, time::Duration};
 
 use anyhow::{Context, bail};
 use clap::{Parser, Subcommand};
-use jupyter_shell::api::client::JupyterLabClient;
+use jupyter_shell::api::{client::JupyterLabClient, throttle::Throttle};
+#[cfg(feature = "sshd")]
+use jupyter_shell::sshd::{HostKey, PublicKey, ServerConfig, SessionHandler};
 use reqwest::Url;
+#[cfg(feature = "sshd")]
+use tokio::net::TcpListener;
+#[cfg(feature = "sshd")]
+use tracing::debug;
 use tracing::{info, warn};
 
+pub mod checkpoint;
+pub mod du;
 #[cfg(feature = "ftp")]
 pub mod ftp;
+#[cfg(feature = "s3")]
+pub mod s3;
 pub mod scp;
+#[cfg(feature = "serve")]
+pub mod serve;
+#[cfg(feature = "sftp")]
+pub mod sftp;
 pub mod ssh;
+#[cfg(feature = "sshd")]
+pub mod sshd;
+#[cfg(feature = "webdav")]
+pub mod webdav;
 
 pub(crate) const APP_USER_AGENT: &str = concat!("jupyter-shell/", env!("CARGO_PKG_VERSION"));
 pub(crate) const DEFAULT_JUPYTER_URL: &str = "http://localhost:8888/";
@@ -25,11 +45,30 @@ pub struct Cli {
 pub enum Command {
   #[cfg(feature = "ftp")]
   #[command(about = "Expose a Jupyter deployment over FTP")]
-  Ftp(ftp::FtpArgs),
+  Ftp(Box<ftp::FtpArgs>),
+  #[cfg(feature = "s3")]
+  #[command(about = "Expose a Jupyter deployment through an S3-compatible API")]
+  S3(s3::S3Args),
   #[command(about = "Expose a Jupyter deployment over SCP")]
   Scp(scp::ScpArgs),
+  #[cfg(feature = "serve")]
+  #[command(about = "Serve a Jupyter deployment as a local HTTP file server")]
+  Serve(serve::ServeArgs),
+  #[cfg(feature = "sftp")]
+  #[command(about = "Serve a Jupyter deployment as an SFTP subsystem")]
+  Sftp(sftp::SftpArgs),
   #[command(about = "Open a terminal session over WebSockets (interactive or one-shot command)")]
   Ssh(ssh::SshArgs),
+  #[cfg(feature = "sshd")]
+  #[command(about = "Serve Jupyter terminals to SSH clients")]
+  Sshd(sshd::SshdArgs),
+  #[cfg(feature = "webdav")]
+  #[command(about = "Expose a Jupyter deployment over WebDAV")]
+  Webdav(webdav::WebdavArgs),
+  #[command(about = "List, create, restore and prune Jupyter checkpoints")]
+  Checkpoint(checkpoint::CheckpointArgs),
+  #[command(about = "Summarize disk usage of a remote directory")]
+  Du(du::DuArgs),
 }
 
 #[derive(Debug)]
@@ -85,8 +124,24 @@ impl TokenArgs {
   }
 
   pub fn build_client(&self) -> anyhow::Result<JupyterLabClient> {
-    let base_url = self.derive_base_url()?;
+    self.build_throttled_client(Throttle::new())
+  }
+
+  /// Build a client whose HTTP requests share the bandwidth and concurrency limits of `throttle`.
+  pub fn build_throttled_client(&self, throttle: Throttle) -> anyhow::Result<JupyterLabClient> {
     let token = self.resolve_token()?;
+    self.build_with_token(Some(&token), throttle)
+  }
+
+  /// Like [`TokenArgs::build_throttled_client`], but without a token the client is
+  /// built unauthenticated; for gateways where each user supplies their own token.
+  pub fn build_client_with_optional_token(&self, throttle: Throttle) -> anyhow::Result<JupyterLabClient> {
+    let token = self.resolve_token().ok();
+    self.build_with_token(token.as_deref(), throttle)
+  }
+
+  fn build_with_token(&self, token: Option<&str>, throttle: Throttle) -> anyhow::Result<JupyterLabClient> {
+    let base_url = self.derive_base_url()?;
 
     let mut builder = JupyterLabClient::builder(base_url.as_str())?;
     if let Some(timeout_secs) = self.http_timeout_secs {
@@ -99,13 +154,130 @@ impl TokenArgs {
       warn!("TLS certificate verification disabled for Jupyter endpoint");
     }
 
-    builder = builder.token(&token)?;
+    if let Some(token) = token {
+      builder = builder.token(token)?;
+    }
     builder
+      .throttle(Arc::new(throttle))
       .build()
       .context("failed to build Jupyter client")
   }
 }
 
+/// Host key and credentials of the embedded SSH server, as given on the command line.
+#[cfg(feature = "sshd")]
+#[derive(Debug)]
+pub struct SshServerArgs {
+  host_key: Option<PathBuf>,
+  authorized_keys: Option<PathBuf>,
+  password_file: Option<PathBuf>,
+  user: Option<String>,
+}
+
+#[cfg(feature = "sshd")]
+impl SshServerArgs {
+  fn build_config(&self) -> anyhow::Result<ServerConfig> {
+    if self.authorized_keys.is_none() && self.password_file.is_none() {
+      bail!("nobody could log in; pass --authorized-keys, --password-file or both");
+    }
+    let host_key = match &self.host_key {
+      Some(path) => HostKey::load_or_generate(path)
+        .with_context(|| format!("failed to load host key {}", path.display()))?,
+      None => {
+        warn!("no --host-key given; clients will see a new host key every time the server starts");
+        HostKey::generate()?
+      }
+    };
+    info!(fingerprint = %host_key.fingerprint(), "SSH host key");
+
+    let mut config = ServerConfig::new(host_key);
+    if let Some(path) = &self.authorized_keys {
+      let text = fs::read_to_string(path)
+        .with_context(|| format!("failed to read authorized keys {}", path.display()))?;
+      let keys = PublicKey::parse_authorized_keys(&text)
+        .with_context(|| format!("failed to parse authorized keys {}", path.display()))?;
+      info!(keys = keys.len(), "loaded authorized keys");
+      config = config.with_authorized_keys(keys);
+    }
+    if let Some(path) = &self.password_file {
+      let contents = fs::read_to_string(path)
+        .with_context(|| format!("failed to read password file {}", path.display()))?;
+      let password = contents.trim_end_matches(['\r', '\n']);
+      if password.is_empty() {
+        bail!("password file {} was empty", path.display());
+      }
+      config = config.with_password(password.to_string());
+    }
+    if let Some(user) = &self.user {
+      config = config.with_user(user.clone());
+    }
+    if !config.has_credentials() {
+      bail!("no usable keys in the authorized keys file");
+    }
+    Ok(config)
+  }
+}
+
+/// Accept SSH connections on `listen` forever, handing sessions to `handler`.
+#[cfg(feature = "sshd")]
+pub(crate) async fn serve_ssh<H: SessionHandler>(
+  listen: SocketAddr,
+  args: &SshServerArgs,
+  handler: H,
+) -> anyhow::Result<()> {
+  let config = Arc::new(args.build_config()?);
+  let handler = Arc::new(handler);
+  let listener = TcpListener::bind(listen)
+    .await
+    .with_context(|| format!("failed to listen on {listen}"))?;
+  info!(%listen, "SSH server listening");
+  loop {
+    let (stream, peer) = listener.accept().await?;
+    stream.set_nodelay(true).ok();
+    debug!(%peer, "SSH client connected");
+    let (config, handler) = (config.clone(), handler.clone());
+    tokio::spawn(async move {
+      match jupyter_shell::sshd::serve(stream, config, handler).await {
+        Ok(()) => debug!(%peer, "SSH client disconnected"),
+        Err(error) => warn!(%peer, %error, "SSH connection failed"),
+      }
+    });
+  }
+}
+
+/// Build the limits selected by `--limit-rate` and `--jobs`.
+pub(crate) fn build_throttle(limit_rate: Option<u64>, jobs: Option<usize>) -> Throttle {
+  let mut throttle = Throttle::new();
+  if let Some(rate) = limit_rate {
+    info!(bytes_per_sec = rate, "limiting transfer rate");
+    throttle = throttle.limit_rate(rate);
+  }
+  if let Some(jobs) = jobs {
+    throttle = throttle.max_in_flight(jobs);
+  }
+  throttle
+}
+
+/// Parse a transfer rate in bytes per second, with an optional `K`, `M` or `G` (binary) suffix.
+pub(crate) fn parse_rate(value: &str) -> Result<u64, String> {
+  let trimmed = value.trim();
+  let digits = trimmed.trim_end_matches(['b', 'B']);
+  let (number, multiplier) = match digits.chars().last().map(|unit| unit.to_ascii_uppercase()) {
+    Some('K') => (&digits[..digits.len() - 1], 1u64 << 10),
+    Some('M') => (&digits[..digits.len() - 1], 1u64 << 20),
+    Some('G') => (&digits[..digits.len() - 1], 1u64 << 30),
+    _ => (digits, 1),
+  };
+  let rate: f64 = number
+    .trim()
+    .parse()
+    .map_err(|_| format!("invalid rate {value:?}; expected a number such as 500K or 2M"))?;
+  if !rate.is_finite() || rate <= 0.0 {
+    return Err(format!("rate {value:?} must be greater than zero"));
+  }
+  Ok(((rate * multiplier as f64) as u64).max(1))
+}
+
 fn extract_token_from_url(url: &Url) -> Option<String> {
   url
     .query_pairs()
@@ -0,0 +1,167 @@
+use std::{convert::Infallible, fs, net::SocketAddr, path::{Path, PathBuf}, sync::Arc, time::Duration};
+
+use anyhow::{bail, Context};
+use clap::{builder::RangedU64ValueParser, value_parser, ArgAction, Args, ValueHint};
+use hyper::{server::conn::http1, service::service_fn};
+use hyper_util::rt::TokioIo;
+use jupyter_shell::{fs::FsService, s3::{Credentials, S3Gateway}};
+use reqwest::Url;
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+
+use crate::cli::{build_throttle, parse_rate, DEFAULT_JUPYTER_URL, TokenArgs};
+
+const S3_BIND_ADDR: &str = "127.0.0.1:9000";
+const DEFAULT_BUCKET: &str = "jupyter";
+
+pub(crate) async fn run(args: S3Args) -> anyhow::Result<()> {
+  let credentials = load_credentials(
+    args.access_key.as_deref(),
+    args.secret_key.as_deref(),
+    args.secret_key_file.as_deref(),
+    args.credentials_file.as_deref(),
+  )?;
+  let token_args = TokenArgs {
+    endpoint_url: args.endpoint_url,
+    token: args.token,
+    token_file: args.token_file,
+    api_base_path: args.api_base_path,
+    http_timeout_secs: args.http_timeout_secs,
+    accept_invalid_certs: args.accept_invalid_certs,
+  };
+  let base_url = token_args.derive_base_url()?;
+  let client = token_args.build_throttled_client(build_throttle(args.limit_rate, args.jobs))?;
+
+  let mut fs = FsService::new(Arc::new(client));
+  if let Some(ttl) = args.cache_ttl_secs.filter(|ttl| *ttl > 0) {
+    fs = fs.with_cache(Duration::from_secs(ttl));
+  }
+  let key_count = credentials.len();
+  let gateway = S3Gateway::new(fs, &args.bucket, credentials).read_only(args.read_only);
+  let gateway = match &args.root {
+    Some(root) => gateway.root(root),
+    None => gateway,
+  };
+
+  if !args.bind.ip().is_loopback() {
+    warn!(bind = %args.bind, "the S3 gateway does not use TLS; signatures protect requests from tampering but not from being read");
+  }
+  let listener = TcpListener::bind(args.bind).await?;
+  info!(
+    %base_url,
+    bind = %args.bind,
+    bucket = %args.bucket,
+    root = args.root.as_deref().unwrap_or("/"),
+    access_keys = key_count,
+    read_only = args.read_only,
+    "Serving Jupyter over the S3 API"
+  );
+  loop {
+    let (stream, peer) = listener.accept().await?;
+    debug!(%peer, "S3 client connected");
+    let gateway = gateway.clone();
+    tokio::spawn(async move {
+      let service = service_fn(move |req| {
+        let gateway = gateway.clone();
+        async move { Ok::<_, Infallible>(gateway.handle(req).await) }
+      });
+      if let Err(err) = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await {
+        debug!(%peer, error = %err, "S3 connection ended with an error");
+      }
+    });
+  }
+}
+
+/// Collect the access keys from `--access-key`/`--secret-key` and `--credentials-file`.
+fn load_credentials(
+  access_key: Option<&str>,
+  secret_key: Option<&str>,
+  secret_key_file: Option<&Path>,
+  credentials_file: Option<&Path>,
+) -> anyhow::Result<Credentials> {
+  let mut credentials = Credentials::new();
+  if let Some(access_key) = access_key {
+    let secret_key = match (secret_key, secret_key_file) {
+      (Some(secret_key), _) => secret_key.to_string(),
+      (None, Some(path)) => fs::read_to_string(path)
+        .with_context(|| format!("failed to read secret key file {}", path.display()))?
+        .trim()
+        .to_string(),
+      (None, None) => bail!("--access-key requires --secret-key or --secret-key-file"),
+    };
+    if secret_key.is_empty() {
+      bail!("the secret key for {access_key} is empty");
+    }
+    credentials.insert(access_key, secret_key);
+  }
+  if let Some(path) = credentials_file {
+    let contents =
+      fs::read_to_string(path).with_context(|| format!("failed to read credentials file {}", path.display()))?;
+    for (number, line) in contents.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let Some((access_key, secret_key)) = line.split_once(':').or_else(|| line.split_once(char::is_whitespace)) else {
+        bail!("{}:{}: expected ACCESS_KEY:SECRET_KEY", path.display(), number + 1);
+      };
+      credentials.insert(access_key.trim(), secret_key.trim());
+    }
+  }
+  if credentials.is_empty() {
+    bail!("no access keys configured; use --access-key with --secret-key, or --credentials-file");
+  }
+  Ok(credentials)
+}
+
+#[derive(Args, Debug)]
+#[command(
+  about = "Expose a Jupyter deployment through an S3-compatible API",
+  long_about = "Expose a Jupyter deployment through an S3-compatible API.\n\n\
+    One bucket (--bucket) is served, backed by the Jupyter contents below --root; `/` in object keys \
+    separates directories. Requests must be signed (AWS Signature Version 4) with a configured access key, \
+    e.g. `aws --endpoint-url http://127.0.0.1:9000 s3 cp data.csv s3://jupyter/data/`. \
+    ListObjects, GetObject (with Range), HeadObject, PutObject, CopyObject, DeleteObject(s) and multipart \
+    uploads are supported. Multipart parts are appended in order with chunked saves; parts that arrive \
+    before their predecessors are held in memory (up to 1 GiB in total, after which the gateway answers \
+    SlowDown), so lower the client's upload concurrency for very large parts. Uploads that receive no part for \
+    an hour are aborted."
+)]
+pub struct S3Args {
+  #[arg(value_name = "JUPYTER_URL", default_value = DEFAULT_JUPYTER_URL, help = "Full Jupyter URL (supports ?token=<value>)")]
+  endpoint_url: Url,
+  #[arg(long, value_name = "TOKEN", env = "JUPYTER_TOKEN", help = "Override the token provided in the Jupyter URL")]
+  token: Option<String>,
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, env = "JUPYTER_TOKEN_FILE", conflicts_with = "token", help = "Load the API token from a file")]
+  token_file: Option<PathBuf>,
+
+  #[arg(long = "timeout", value_name = "SECONDS", env = "JUPYTER_SHELL_HTTP_TIMEOUT", value_parser = value_parser!(u64).range(1..=3600), help = "HTTP client timeout in seconds")]
+  http_timeout_secs: Option<u64>,
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_ACCEPT_INVALID_CERTS", help = "Disable TLS certificate verification for the Jupyter endpoint")]
+  accept_invalid_certs: bool,
+  #[arg(long, value_name = "PATH", env = "JUPYTER_SHELL_API_BASE_PATH", help = "Override the API base path instead of auto-detecting it")]
+  api_base_path: Option<String>,
+
+  #[arg(long, value_name = "IP:PORT", default_value = S3_BIND_ADDR, env = "JUPYTER_SHELL_S3_BIND", help = "Address the S3 gateway listens on")]
+  bind: SocketAddr,
+  #[arg(long, value_name = "NAME", default_value = DEFAULT_BUCKET, env = "JUPYTER_SHELL_S3_BUCKET", help = "Name of the bucket clients address")]
+  bucket: String,
+  #[arg(long, value_name = "REMOTE_DIR", env = "JUPYTER_SHELL_S3_ROOT", help = "Serve this remote directory as the bucket instead of the server root")]
+  root: Option<String>,
+  #[arg(long, value_name = "KEY_ID", env = "JUPYTER_SHELL_S3_ACCESS_KEY", help = "Access key id clients sign requests with")]
+  access_key: Option<String>,
+  #[arg(long, value_name = "SECRET", env = "JUPYTER_SHELL_S3_SECRET_KEY", hide_env_values = true, requires = "access_key", help = "Secret key for --access-key")]
+  secret_key: Option<String>,
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, env = "JUPYTER_SHELL_S3_SECRET_KEY_FILE", requires = "access_key", conflicts_with = "secret_key", help = "Load the secret key for --access-key from a file")]
+  secret_key_file: Option<PathBuf>,
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, env = "JUPYTER_SHELL_S3_CREDENTIALS_FILE", help = "Accept every ACCESS_KEY:SECRET_KEY pair listed in this file, one per line")]
+  credentials_file: Option<PathBuf>,
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_S3_READ_ONLY", help = "Reject uploads, copies and deletes")]
+  read_only: bool,
+  #[arg(long = "cache-ttl", value_name = "SECONDS", env = "JUPYTER_SHELL_S3_CACHE_TTL", value_parser = value_parser!(u64).range(0..=3600), help = "Cache file metadata and directory listings for this many seconds (0 disables)")]
+  cache_ttl_secs: Option<u64>,
+  #[arg(long, value_name = "RATE", env = "JUPYTER_SHELL_S3_LIMIT_RATE", value_parser = parse_rate, help = "Limit bandwidth to the Jupyter server to RATE bytes per second (accepts K, M and G suffixes)")]
+  limit_rate: Option<u64>,
+  #[arg(long, value_name = "N", env = "JUPYTER_SHELL_S3_JOBS", value_parser = RangedU64ValueParser::<usize>::new().range(1..), help = "Allow at most N HTTP requests to the Jupyter server in flight at once")]
+  jobs: Option<usize>,
+}
@@ -1,20 +1,27 @@
 use std::{
   io::ErrorKind,
   path::{Path, PathBuf},
-  sync::Arc,
+  sync::{Arc, Mutex},
 };
 
 use anyhow::{anyhow, bail, Context};
-use clap::{value_parser, ArgAction, Args, ValueHint};
+use chrono::{DateTime, Utc};
+use clap::{builder::RangedU64ValueParser, value_parser, ArgAction, Args, ValueHint};
+use futures_util::{stream, StreamExt, TryStreamExt};
 use jupyter_shell::{
   api::client::ClientError,
-  fs::{Entry, FsError, FsService},
+  fs::{is_hidden_path, Entry, FsError, FsService},
+  walk::has_wildcard,
 };
 use reqwest::{StatusCode, Url};
-use tokio::fs;
+use sha2::{Digest, Sha256};
+use tokio::{
+  fs,
+  io::{AsyncReadExt, AsyncWriteExt},
+};
 use tracing::{debug, info, warn};
 
-use crate::cli::{DEFAULT_JUPYTER_URL, TokenArgs};
+use crate::cli::{build_throttle, parse_rate, DEFAULT_JUPYTER_URL, TokenArgs};
 
 #[derive(Args, Debug)]
 #[command(about = "Expose a Jupyter deployment over SCP")]
@@ -37,8 +44,28 @@ pub struct ScpArgs {
   paths: Vec<String>,
   #[arg(short = 'r', long, action = ArgAction::SetTrue, help = "Recursively copy entire directories")]
   recursive: bool,
+  #[arg(short = 'p', long, action = ArgAction::SetTrue, help = "Preserve modification times (uploads set them through a Jupyter terminal)")]
+  preserve: bool,
+  #[arg(long, action = ArgAction::SetTrue, help = "Create missing parent directories of the remote destination")]
+  parents: bool,
+  #[arg(long, action = ArgAction::SetTrue, help = "Write directly to the destination instead of a temporary file that is renamed into place")]
+  no_atomic: bool,
+  #[arg(long, action = ArgAction::SetTrue, help = "Create a Jupyter checkpoint before overwriting an existing remote file")]
+  checkpoint: bool,
+  #[arg(long, action = ArgAction::SetTrue, help = "Compare SHA-256 digests after each transfer and retry a mismatched file once")]
+  verify: bool,
+  #[arg(long, value_name = "FILES", env = "JUPYTER_SHELL_SCP_BULK_THRESHOLD", default_value_t = DEFAULT_BULK_THRESHOLD, help = "Copy directories with at least this many files as one tar stream over a terminal (0 disables; ignored with --limit-rate)")]
+  bulk_threshold: usize,
+  #[arg(long, value_name = "RATE", env = "JUPYTER_SHELL_SCP_LIMIT_RATE", value_parser = parse_rate, help = "Limit bandwidth to RATE bytes per second (accepts K, M and G suffixes)")]
+  limit_rate: Option<u64>,
+  #[arg(short = 'j', long, value_name = "N", env = "JUPYTER_SHELL_SCP_JOBS", value_parser = RangedU64ValueParser::<usize>::new().range(1..), help = "Copy up to N files at once, with at most N HTTP requests to the Jupyter server in flight")]
+  jobs: Option<usize>,
+  #[arg(long, action = ArgAction::SetTrue, help = "Stop at the first file that fails instead of copying the rest and reporting all failures")]
+  fail_fast: bool,
 }
 
+const DEFAULT_BULK_THRESHOLD: usize = 500;
+
 pub(crate) async fn run(args: ScpArgs) -> anyhow::Result<()> {
   let token_args = TokenArgs {
     endpoint_url: args.endpoint_url,
@@ -50,25 +77,260 @@ pub(crate) async fn run(args: ScpArgs) -> anyhow::Result<()> {
   };
 
   let base_url = token_args.derive_base_url()?;
-  let client = token_args.build_client()?;
+  let client = token_args.build_throttled_client(build_throttle(args.limit_rate, args.jobs))?;
 
-  let fs = FsService::new(Arc::new(client));
+  let mut fs = FsService::new(Arc::new(client));
+  if args.preserve {
+    fs = fs.with_shell_fallback();
+  }
   let (source_ops, dest_op) = parse_operands(&args.paths)?;
   let plan = determine_transfer_plan(&base_url, source_ops, dest_op)?;
 
-  info!(mode = plan.label(), source_count = plan.source_count(), recursive = args.recursive, "Starting SCP transfer");
+  let options = TransferOptions {
+    recursive: args.recursive,
+    preserve: args.preserve,
+    parents: args.parents,
+    atomic: !args.no_atomic,
+    checkpoint: args.checkpoint,
+    verify: args.verify,
+    // Terminal traffic bypasses the HTTP throttle, so `--limit-rate` keeps every transfer per-file.
+    bulk_threshold: (args.bulk_threshold > 0 && args.limit_rate.is_none()).then_some(args.bulk_threshold),
+    jobs: args.jobs.unwrap_or(1),
+    fail_fast: args.fail_fast,
+    report: Arc::default(),
+  };
+
+  info!(mode = plan.label(), source_count = plan.source_count(), recursive = options.recursive, "Starting SCP transfer");
   match plan {
     TransferPlan::Upload { sources, destination } => {
-      upload_paths(&fs, &sources, &destination, args.recursive).await?;
+      upload_paths(&fs, &sources, &destination, &options).await?;
     }
     TransferPlan::Download { sources, destination } => {
-      download_paths(&fs, &sources, &destination, args.recursive).await?;
+      let sources = expand_remote_wildcards(&fs, sources).await?;
+      download_paths(&fs, &sources, &destination, &options).await?;
+    }
+  }
+  if options.preserve {
+    apply_remote_mtimes(&fs, &options).await;
+  }
+  options.report.log();
+  if options.verify {
+    let failed = options.report.log_verification();
+    if failed > 0 {
+      bail!("{} file(s) failed verification", failed);
     }
   }
   info!("SCP transfer completed");
   Ok(())
 }
 
+/// Flags shared by every file copied in one scp invocation.
+#[derive(Debug, Clone)]
+struct TransferOptions {
+  recursive: bool,
+  preserve: bool,
+  parents: bool,
+  atomic: bool,
+  checkpoint: bool,
+  verify: bool,
+  /// Directories with at least this many files go through [`FsService::bulk_upload`]/`bulk_download`.
+  bulk_threshold: Option<usize>,
+  /// Files copied concurrently within a directory.
+  jobs: usize,
+  fail_fast: bool,
+  report: Arc<TransferReport>,
+}
+
+/// Paths a transfer left out, logged once it finishes instead of dropping them silently.
+#[derive(Debug, Default)]
+struct TransferReport {
+  /// Local paths the server refused because it does not allow hidden files.
+  skipped_hidden: Mutex<Vec<String>>,
+  /// Remote directories whose hidden entries the server does not list.
+  unlisted_hidden: Mutex<Vec<String>>,
+  verification: Mutex<VerificationReport>,
+  /// Modification times to apply to uploaded paths once all files are in place.
+  remote_mtimes: Mutex<Vec<(String, DateTime<Utc>)>>,
+}
+
+/// Outcome of `--verify` checks across a transfer.
+#[derive(Debug, Default)]
+struct VerificationReport {
+  verified: usize,
+  /// Files that matched only after being transferred a second time.
+  retried: Vec<String>,
+  failed: Vec<String>,
+  /// Files the server could not provide a SHA-256 digest for.
+  unverified: Vec<String>,
+}
+
+/// Result of comparing one transferred file against its source.
+enum Check {
+  Match,
+  Mismatch { expected: String, actual: String },
+  Unavailable,
+}
+
+impl TransferReport {
+  fn skip_hidden(&self, path: &Path) {
+    warn!(path = %path.display(), "Skipping hidden path rejected by the server");
+    self.skipped_hidden.lock().unwrap().push(path.display().to_string());
+  }
+
+  fn log_verification(&self) -> usize {
+    let report = self.verification.lock().unwrap();
+    info!(
+      verified = report.verified,
+      retried = report.retried.len(),
+      unverified = report.unverified.len(),
+      failed = report.failed.len(),
+      "Verification report"
+    );
+    if !report.retried.is_empty() {
+      info!(paths = %report.retried.join(", "), "Verified after one retry");
+    }
+    if !report.unverified.is_empty() {
+      warn!(paths = %report.unverified.join(", "), "No server SHA-256 was available to verify these downloads");
+    }
+    if !report.failed.is_empty() {
+      warn!(paths = %report.failed.join(", "), "Checksums still differed after a retry");
+    }
+    report.failed.len()
+  }
+
+  fn log(&self) {
+    let skipped = self.skipped_hidden.lock().unwrap();
+    if !skipped.is_empty() {
+      warn!(
+        count = skipped.len(),
+        paths = %skipped.join(", "),
+        "Hidden files were not copied because the server has ContentsManager.allow_hidden disabled"
+      );
+    }
+    let unlisted = self.unlisted_hidden.lock().unwrap();
+    if !unlisted.is_empty() {
+      warn!(
+        directories = %unlisted.join(", "),
+        "The server hides dotfiles, so hidden entries in these directories were not downloaded"
+      );
+    }
+  }
+}
+
+/// Apply the local modification times recorded during an upload with `-p`.
+///
+/// Failing to set them (e.g. because terminals are disabled) is reported but does
+/// not fail the transfer, since every file has already been copied.
+async fn apply_remote_mtimes(fs: &FsService, options: &TransferOptions) {
+  let stamps = std::mem::take(&mut *options.report.remote_mtimes.lock().unwrap());
+  if stamps.is_empty() {
+    return;
+  }
+  match fs.touch(&stamps).await {
+    Ok(()) => debug!(count = stamps.len(), "Preserved modification times"),
+    Err(err) => warn!(error = %err, count = stamps.len(), "Could not preserve modification times on the server"),
+  }
+}
+
+/// Remember the modification time of `local_path` so it can be applied to `remote_path`.
+async fn record_mtime(options: &TransferOptions, local_path: &Path, remote_path: &str) -> anyhow::Result<()> {
+  let modified = fs::metadata(local_path)
+    .await
+    .and_then(|metadata| metadata.modified())
+    .with_context(|| format!("failed to read the modification time of {}", local_path.display()))?;
+  options.report.remote_mtimes.lock().unwrap().push((remote_path.to_string(), DateTime::from(modified)));
+  Ok(())
+}
+
+/// Set the modification time of a downloaded file or directory.
+///
+/// Goes through `filetime`, as `File::set_modified` needs a handle opened for
+/// writing on Windows, where directories cannot be opened that way.
+async fn set_local_mtime(local_path: &Path, stamp: DateTime<Utc>) -> anyhow::Result<()> {
+  let target = local_path.to_path_buf();
+  let mtime = filetime::FileTime::from_system_time(stamp.into());
+  tokio::task::spawn_blocking(move || filetime::set_file_mtime(&target, mtime))
+    .await?
+    .with_context(|| format!("failed to set the modification time of {}", local_path.display()))
+}
+
+/// Run `transfer` and check the result, transferring once more on a checksum mismatch.
+async fn verified_transfer(
+  options: &TransferOptions,
+  label: &str,
+  transfer: impl AsyncFn() -> anyhow::Result<Check>,
+) -> anyhow::Result<()> {
+  let (check, retried) = match transfer().await? {
+    Check::Mismatch { expected, actual } => {
+      warn!(path = label, %expected, %actual, "Checksum mismatch; retrying transfer");
+      (transfer().await?, true)
+    }
+    check => (check, false),
+  };
+  let mut report = options.report.verification.lock().unwrap();
+  match check {
+    Check::Match => {
+      report.verified += 1;
+      if retried {
+        report.retried.push(label.to_string());
+      }
+    }
+    Check::Mismatch { expected, actual } => {
+      warn!(path = label, %expected, %actual, "Checksum mismatch persisted after retry");
+      report.failed.push(label.to_string());
+    }
+    Check::Unavailable => report.unverified.push(label.to_string()),
+  }
+  Ok(())
+}
+
+/// Run `transfer` for every item, up to `options.jobs` at a time.
+///
+/// Unless `--fail-fast` is set, a failing item does not stop the others; every
+/// failure is logged and they are returned together once all items have run.
+async fn transfer_all<T>(
+  options: &TransferOptions,
+  items: Vec<T>,
+  transfer: impl AsyncFn(T) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+  let total = items.len();
+  let mut results = stream::iter(items).map(|item| transfer(item)).buffer_unordered(options.jobs.max(1));
+  let mut failures = Vec::new();
+  while let Some(result) = results.next().await {
+    if let Err(err) = result {
+      if options.fail_fast {
+        return Err(err);
+      }
+      warn!("{:#}", err);
+      failures.push(err);
+    }
+  }
+  match failures.len() {
+    0 => Ok(()),
+    1 => Err(failures.remove(0)),
+    count => {
+      let details: Vec<String> = failures.iter().map(|err| format!("{err:#}")).collect();
+      bail!("{} of {} files failed to transfer:\n  {}", count, total, details.join("\n  "))
+    }
+  }
+}
+
+/// Whether `err` is the server refusing `remote_path` because it is hidden.
+///
+/// Uploads also write hidden temporary and backup files next to visible ones, so a
+/// rejection only counts as a skip when the user's own path is a dotfile; anything
+/// else is a real failure.
+fn is_hidden_rejection(err: &anyhow::Error, remote_path: &str) -> bool {
+  is_hidden_path(remote_path) && matches!(err.downcast_ref::<FsError>(), Some(FsError::HiddenNotAllowed(_)))
+}
+
+impl TransferOptions {
+  /// Bulk transfers bypass per-file checkpoints, so they are only used without `--checkpoint`.
+  fn bulk_threshold(&self) -> Option<usize> {
+    self.bulk_threshold.filter(|_| !self.checkpoint)
+  }
+}
+
 #[derive(Debug)]
 enum TransferPlan {
   Upload {
@@ -272,7 +534,7 @@ async fn upload_paths(
   fs: &FsService,
   sources: &[LocalOperand],
   dest: &RemoteOperand,
-  recursive: bool,
+  options: &TransferOptions,
 ) -> anyhow::Result<()> {
   if sources.is_empty() {
     bail!("no local sources were provided");
@@ -295,7 +557,9 @@ async fn upload_paths(
   }
 
   if dest_is_dir && dest_entry.is_none() {
-    ensure_remote_directory(fs, &dest.normalized).await?;
+    ensure_remote_directory(fs, &dest.normalized, options.parents).await?;
+  } else if options.parents && dest_entry.is_none() && let Some(parent) = remote_parent(&dest.normalized) {
+    ensure_remote_directory(fs, parent, true).await?;
   }
 
   for source in sources {
@@ -310,12 +574,18 @@ async fn upload_paths(
     };
 
     if metadata.is_dir() {
-      if !recursive {
+      if !options.recursive {
         bail!("{} is a directory (use --recursive to enable directory copies)", source.raw);
       }
-      upload_directory(fs, &source.path, &target_path).await?;
+      let bulk = match options.bulk_threshold() {
+        Some(threshold) => count_local_files(&source.path, threshold).await? >= threshold,
+        None => false,
+      };
+      if !bulk || !bulk_upload(fs, &source.path, &target_path, options).await? {
+        upload_directory(fs, &source.path, &target_path, options).await?;
+      }
     } else if metadata.is_file() {
-      upload_file(fs, &source.path, &target_path).await?;
+      upload_file(fs, &source.path, &target_path, options).await?;
     } else {
       bail!("{} is neither a file nor a directory", source.raw);
     }
@@ -328,7 +598,7 @@ async fn download_paths(
   fs: &FsService,
   sources: &[RemoteOperand],
   dest: &LocalOperand,
-  recursive: bool,
+  options: &TransferOptions,
 ) -> anyhow::Result<()> {
   if sources.is_empty() {
     bail!("no remote sources were provided");
@@ -368,19 +638,29 @@ async fn download_paths(
     if dest_is_dir {
       target_path.push(&entry.name);
     }
-    if entry.kind.is_directory() && !recursive {
+    if entry.kind.is_directory() && !options.recursive {
       bail!("{} is a directory (use --recursive to enable directory copies)", remote.raw);
     }
-    download_entry(fs, entry, &remote.normalized, &target_path, recursive).await?;
+    download_entry(fs, entry, &remote.normalized, &target_path, options).await?;
   }
 
   Ok(())
 }
 
-async fn upload_directory(fs: &FsService, local_dir: &Path, remote_dir: &str) -> anyhow::Result<()> {
+/// Create the remote directory tree in order, then upload its files concurrently.
+async fn upload_directory(fs: &FsService, local_dir: &Path, remote_dir: &str, options: &TransferOptions) -> anyhow::Result<()> {
+  let mut files = Vec::new();
+  let mut directories = Vec::new();
   let mut stack = vec![(local_dir.to_path_buf(), remote_dir.to_string())];
   while let Some((current_local, current_remote)) = stack.pop() {
-    ensure_remote_directory(fs, &current_remote).await?;
+    match ensure_remote_directory(fs, &current_remote, false).await {
+      Ok(()) => directories.push((current_local.clone(), current_remote.clone())),
+      Err(err) if is_hidden_rejection(&err, &current_remote) => {
+        options.report.skip_hidden(&current_local);
+        continue;
+      }
+      Err(err) => return Err(err),
+    }
     let mut entries = fs::read_dir(&current_local)
       .await
       .with_context(|| format!("failed to list directory {}", current_local.display()))?;
@@ -393,74 +673,281 @@ async fn upload_directory(fs: &FsService, local_dir: &Path, remote_dir: &str) ->
       let name = entry.file_name();
       let child = name.to_string_lossy().into_owned();
       let remote_child = join_remote_paths(&current_remote, &child);
-      let metadata = entry
-        .metadata()
+      let metadata = fs::metadata(&path)
         .await
         .with_context(|| format!("failed to read metadata for {}", path.display()))?;
       if metadata.is_dir() {
         stack.push((path, remote_child));
       } else if metadata.is_file() {
-        upload_file(fs, &path, &remote_child).await?;
+        files.push((path, remote_child));
       } else {
         bail!("{} is neither a file nor a directory", path.display());
       }
     }
   }
+  transfer_all(options, files, async |(path, remote): (PathBuf, String)| {
+    match upload_file(fs, &path, &remote, options).await {
+      Err(err) if is_hidden_rejection(&err, &remote) => {
+        options.report.skip_hidden(&path);
+        Ok(())
+      }
+      other => other,
+    }
+  })
+  .await?;
+  if options.preserve {
+    // Children before parents, so stamping a directory is the last change inside it.
+    for (local, remote) in directories.iter().rev() {
+      record_mtime(options, local, remote).await?;
+    }
+  }
   Ok(())
 }
 
-async fn upload_file(fs: &FsService, local_path: &Path, remote_path: &str) -> anyhow::Result<()> {
+/// Upload `local_dir` as a single tar stream, returning `false` when the server offers no terminals.
+async fn bulk_upload(fs: &FsService, local_dir: &Path, remote_dir: &str, options: &TransferOptions) -> anyhow::Result<bool> {
+  // The stream itself creates missing parents, so check them the way a per-file upload would.
+  ensure_remote_directory(fs, remote_dir, false).await?;
+  match fs.bulk_upload(local_dir, remote_dir, options.atomic).await {
+    Ok(report) => {
+      info!(files = report.files, bytes = report.bytes, remote = %remote_dir, "Uploaded directory as a tar stream");
+      // Bulk transfers always compare every file against a remote sha256sum.
+      options.report.verification.lock().unwrap().verified += report.files;
+      Ok(true)
+    }
+    Err(err) if err.is_terminal_unavailable() => {
+      warn!(error = %err, remote = %remote_dir, "Terminals are unavailable; uploading files one by one");
+      Ok(false)
+    }
+    Err(err) => Err(err).with_context(|| format!("failed to bulk upload {} to {}", local_dir.display(), remote_dir)),
+  }
+}
+
+/// Count regular files below `local_dir`, stopping early once `limit` is reached.
+async fn count_local_files(local_dir: &Path, limit: usize) -> anyhow::Result<usize> {
+  let mut count = 0usize;
+  let mut stack = vec![local_dir.to_path_buf()];
+  while let Some(current) = stack.pop() {
+    let mut entries = fs::read_dir(&current)
+      .await
+      .with_context(|| format!("failed to list directory {}", current.display()))?;
+    while let Some(entry) = entries
+      .next_entry()
+      .await
+      .with_context(|| format!("failed to iterate directory {}", current.display()))?
+    {
+      // Follow symlinks, as `upload_directory` and the bulk packer do.
+      let metadata = fs::metadata(entry.path())
+        .await
+        .with_context(|| format!("failed to read metadata for {}", entry.path().display()))?;
+      if metadata.is_dir() {
+        stack.push(entry.path());
+      } else {
+        count += 1;
+        if count >= limit {
+          return Ok(count);
+        }
+      }
+    }
+  }
+  Ok(count)
+}
+
+async fn upload_file(fs: &FsService, local_path: &Path, remote_path: &str, options: &TransferOptions) -> anyhow::Result<()> {
   let bytes = fs::read(local_path)
     .await
     .with_context(|| format!("failed to read {}", local_path.display()))?;
-  fs
-    .upload(remote_path, &bytes)
-    .await
-    .with_context(|| format!("failed to upload {} to {}", local_path.display(), remote_path))?;
+  if !options.verify {
+    upload_bytes(fs, local_path, &bytes, remote_path, options).await?;
+  } else {
+    let expected = format!("{:x}", Sha256::digest(&bytes));
+    verified_transfer(options, remote_path, async || {
+      if let Err(err) = upload_bytes(fs, local_path, &bytes, remote_path, options).await {
+        // An atomic upload checks the digest itself and leaves the old file in place on a mismatch.
+        return match err.downcast_ref::<FsError>() {
+          Some(FsError::ChecksumMismatch { actual, .. }) => {
+            Ok(Check::Mismatch { expected: expected.clone(), actual: actual.clone() })
+          }
+          _ => Err(err),
+        };
+      }
+      let actual = fs
+        .sha256sum(remote_path)
+        .await
+        .with_context(|| format!("failed to hash {}", remote_path))?;
+      Ok(if actual.eq_ignore_ascii_case(&expected) {
+        Check::Match
+      } else {
+        Check::Mismatch { expected: expected.clone(), actual }
+      })
+    })
+    .await?;
+  }
+  if options.preserve {
+    record_mtime(options, local_path, remote_path).await?;
+  }
+  Ok(())
+}
+
+async fn upload_bytes(
+  fs: &FsService,
+  local_path: &Path,
+  bytes: &[u8],
+  remote_path: &str,
+  options: &TransferOptions,
+) -> anyhow::Result<()> {
+  let uploaded = if options.checkpoint {
+    fs.upload_with_checkpoint(remote_path, &bytes, None).await.map(|(entry, _)| entry)
+  } else if options.atomic {
+    fs.upload_atomic(remote_path, &bytes, None).await
+  } else {
+    fs.upload(remote_path, &bytes).await
+  };
+  uploaded.with_context(|| format!("failed to upload {} to {}", local_path.display(), remote_path))?;
   debug!(local = %local_path.display(), remote = remote_path, bytes = bytes.len(), "Uploaded file");
   Ok(())
 }
 
+async fn expand_remote_wildcards(fs: &FsService, sources: Vec<RemoteOperand>) -> anyhow::Result<Vec<RemoteOperand>> {
+  let mut expanded = Vec::with_capacity(sources.len());
+  for remote in sources {
+    if !has_wildcard(&remote.normalized) {
+      expanded.push(remote);
+      continue;
+    }
+    let matches = fs
+      .glob(&remote.normalized)
+      .await
+      .with_context(|| format!("failed to expand remote pattern {}", remote.raw))?;
+    if matches.is_empty() {
+      bail!("remote pattern '{}' did not match any files", remote.raw);
+    }
+    debug!(pattern = %remote.raw, matches = matches.len(), "Expanded remote wildcard");
+    for entry in matches {
+      expanded.push(RemoteOperand {
+        raw: entry.path.clone(),
+        host: remote.host.clone(),
+        normalized: normalize_remote_path(&entry.path),
+        explicit_dir: false,
+      });
+    }
+  }
+  Ok(expanded)
+}
+
 async fn download_entry(
   fs: &FsService,
   entry: Entry,
   remote_path: &str,
   local_path: &Path,
-  recursive: bool,
+  options: &TransferOptions,
 ) -> anyhow::Result<()> {
   if !entry.kind.is_directory() {
-    return download_file(fs, remote_path, local_path).await;
+    download_file(fs, remote_path, local_path, options).await?;
+    if options.preserve && let Some(stamp) = entry.last_modified {
+      set_local_mtime(local_path, stamp).await?;
+    }
+    return Ok(());
   }
-  if !recursive {
+  if !options.recursive {
     bail!("{} is a directory (use --recursive to enable directory copies)", remote_path);
   }
-  let mut stack = vec![(entry, remote_path.to_string(), local_path.to_path_buf())];
-  while let Some((current_entry, current_remote, current_local)) = stack.pop() {
-    if current_entry.kind.is_directory() {
-      fs::create_dir_all(&current_local)
-        .await
-        .with_context(|| format!("failed to create directory {}", current_local.display()))?;
-      let children = fs
-        .ls(&current_remote)
-        .await
-        .with_context(|| format!("failed to list remote directory {}", current_remote))?;
-      for child in children {
-        let child_remote = join_remote_paths(&current_remote, &child.name);
-        let child_local = current_local.join(&child.name);
-        if child.kind.is_directory() {
-          stack.push((child, child_remote, child_local));
-        } else {
-          download_file(fs, &child_remote, &child_local).await?;
-        }
+  fs::create_dir_all(local_path)
+    .await
+    .with_context(|| format!("failed to create directory {}", local_path.display()))?;
+  // Count through a terminal first, so large trees are not walked through the
+  // Contents API only to be copied as a tar stream.
+  let bulk = match options.bulk_threshold() {
+    Some(threshold) => match fs.count_files(remote_path, threshold).await {
+      Ok(count) => count >= threshold,
+      Err(err) if err.is_terminal_unavailable() => {
+        debug!(error = %err, remote = %remote_path, "Terminals are unavailable; downloading files one by one");
+        false
+      }
+      Err(err) => return Err(err).with_context(|| format!("failed to count files in {}", remote_path)),
+    },
+    None => false,
+  };
+  if bulk {
+    match fs.bulk_download(remote_path, local_path).await {
+      Ok(report) => {
+        info!(files = report.files, bytes = report.bytes, remote = %remote_path, "Downloaded directory as a tar stream");
+        options.report.verification.lock().unwrap().verified += report.files;
+        return Ok(());
+      }
+      Err(err) if err.is_terminal_unavailable() => {
+        warn!(error = %err, remote = %remote_path, "Terminals are unavailable; downloading files one by one");
+      }
+      Err(err) => {
+        return Err(err).with_context(|| format!("failed to bulk download {} to {}", remote_path, local_path.display()));
       }
+    }
+  }
+  if matches!(fs.allows_hidden().await, Ok(false)) {
+    options.report.unlisted_hidden.lock().unwrap().push(remote_path.to_string());
+  }
+  let root = remote_path.trim_matches('/');
+  let children: Vec<Entry> = fs
+    .walk(remote_path)
+    .try_collect()
+    .await
+    .with_context(|| format!("failed to list remote directory {}", remote_path))?;
+  let mut files = Vec::new();
+  let mut directories = vec![(local_path.to_path_buf(), entry.last_modified)];
+  for child in children {
+    let relative = child
+      .path
+      .trim_start_matches('/')
+      .strip_prefix(root)
+      .unwrap_or(&child.path)
+      .trim_start_matches('/');
+    let child_local = local_path.join(relative);
+    if child.kind.is_directory() {
+      fs::create_dir_all(&child_local)
+        .await
+        .with_context(|| format!("failed to create directory {}", child_local.display()))?;
+      directories.push((child_local, child.last_modified));
     } else {
-      download_file(fs, &current_remote, &current_local).await?;
+      files.push((normalize_remote_path(&child.path), child_local, child.last_modified));
+    }
+  }
+  transfer_all(options, files, async |(remote, local, modified): (String, PathBuf, Option<DateTime<Utc>>)| {
+    download_file(fs, &remote, &local, options).await?;
+    match modified {
+      Some(stamp) if options.preserve => set_local_mtime(&local, stamp).await,
+      _ => Ok(()),
+    }
+  })
+  .await?;
+  if options.preserve {
+    // Deepest directories first, so stamping a parent is the last change inside it.
+    directories.sort_by_key(|(path, _)| std::cmp::Reverse(path.components().count()));
+    for (path, modified) in directories {
+      if let Some(stamp) = modified {
+        set_local_mtime(&path, stamp).await?;
+      }
     }
   }
   Ok(())
 }
 
-async fn download_file(fs: &FsService, remote_path: &str, local_path: &Path) -> anyhow::Result<()> {
+async fn download_file(fs: &FsService, remote_path: &str, local_path: &Path, options: &TransferOptions) -> anyhow::Result<()> {
+  if options.verify {
+    return verified_transfer(options, remote_path, async || {
+      let actual = download_hashed(fs, remote_path, local_path).await?;
+      Ok(match fs.remote_hashsum(remote_path).await {
+        Ok((algorithm, expected)) if algorithm.eq_ignore_ascii_case("sha256") => {
+          if expected.eq_ignore_ascii_case(&actual) {
+            Check::Match
+          } else {
+            Check::Mismatch { expected, actual }
+          }
+        }
+        Ok(_) | Err(_) => Check::Unavailable,
+      })
+    })
+    .await;
+  }
   let file = fs
     .download(remote_path)
     .await
@@ -477,10 +964,58 @@ async fn download_file(fs: &FsService, remote_path: &str, local_path: &Path) ->
   Ok(())
 }
 
-async fn ensure_remote_directory(fs: &FsService, path: &str) -> anyhow::Result<()> {
+/// Stream `remote_path` into `local_path`, returning the SHA-256 of the bytes written.
+async fn download_hashed(fs: &FsService, remote_path: &str, local_path: &Path) -> anyhow::Result<String> {
+  let mut download = fs
+    .download_reader(remote_path)
+    .await
+    .with_context(|| format!("failed to download {}", remote_path))?;
+  if let Some(parent) = local_path.parent() {
+    fs::create_dir_all(parent)
+      .await
+      .with_context(|| format!("failed to create parent directories for {}", local_path.display()))?;
+  }
+  let mut file = fs::File::create(local_path)
+    .await
+    .with_context(|| format!("failed to create {}", local_path.display()))?;
+  let mut hasher = Sha256::new();
+  let mut buf = vec![0u8; 64 * 1024];
+  let mut total = 0usize;
+  loop {
+    let read = download
+      .reader
+      .read(&mut buf)
+      .await
+      .with_context(|| format!("failed to read {}", remote_path))?;
+    if read == 0 {
+      break;
+    }
+    hasher.update(&buf[..read]);
+    file
+      .write_all(&buf[..read])
+      .await
+      .with_context(|| format!("failed to write {}", local_path.display()))?;
+    total += read;
+  }
+  file
+    .flush()
+    .await
+    .with_context(|| format!("failed to write {}", local_path.display()))?;
+  debug!(remote = remote_path, local = %local_path.display(), bytes = total, "Downloaded file");
+  Ok(format!("{:x}", hasher.finalize()))
+}
+
+async fn ensure_remote_directory(fs: &FsService, path: &str, parents: bool) -> anyhow::Result<()> {
   if path == "/" {
     return Ok(());
   }
+  if parents {
+    fs
+      .mkdir_all(path)
+      .await
+      .with_context(|| format!("failed to create remote directory {}", path))?;
+    return Ok(());
+  }
   match fetch_remote_entry(fs, path).await? {
     Some(entry) => {
       if !entry.kind.is_directory() {
@@ -514,6 +1049,13 @@ fn join_remote_paths(base: &str, child: &str) -> String {
   }
 }
 
+fn remote_parent(path: &str) -> Option<&str> {
+  match path.trim_end_matches('/').rsplit_once('/') {
+    Some(("", _)) | None => None,
+    Some((parent, _)) => Some(parent),
+  }
+}
+
 fn normalize_remote_path(path: &str) -> String {
   let mut components = Vec::new();
   for part in path.split('/') {
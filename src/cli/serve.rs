@@ -0,0 +1,111 @@
+use std::{convert::Infallible, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::bail;
+use clap::{builder::RangedU64ValueParser, value_parser, ArgAction, Args, ValueHint};
+use hyper::{server::conn::http1, service::service_fn};
+use hyper_util::rt::TokioIo;
+use jupyter_shell::{fs::FsService, serve::FileServer};
+use reqwest::Url;
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+
+use crate::cli::{build_throttle, parse_rate, DEFAULT_JUPYTER_URL, TokenArgs};
+
+const SERVE_BIND_ADDR: &str = "127.0.0.1:8000";
+
+pub(crate) async fn run(args: ServeArgs) -> anyhow::Result<()> {
+  let token_args = TokenArgs {
+    endpoint_url: args.endpoint_url,
+    token: args.token,
+    token_file: args.token_file,
+    api_base_path: args.api_base_path,
+    http_timeout_secs: args.http_timeout_secs,
+    accept_invalid_certs: args.accept_invalid_certs,
+  };
+  let base_url = token_args.derive_base_url()?;
+  let client = token_args.build_throttled_client(build_throttle(args.limit_rate, args.jobs))?;
+
+  let mut fs = FsService::new(Arc::new(client));
+  if let Some(ttl) = args.cache_ttl_secs.filter(|ttl| *ttl > 0) {
+    fs = fs.with_cache(Duration::from_secs(ttl));
+  }
+  let server = FileServer::new(fs).allow_uploads(args.allow_uploads);
+  let server = match &args.root {
+    Some(root) => server.root(root),
+    None => server,
+  };
+
+  if !args.bind.ip().is_loopback() {
+    if args.allow_uploads && !args.allow_remote {
+      bail!(
+        "refusing to accept uploads on {}: there is no authentication or TLS; \
+         bind to a loopback address, drop --allow-uploads, or pass --allow-remote",
+        args.bind
+      );
+    }
+    warn!(bind = %args.bind, "files are served without authentication or TLS; anyone who can reach this address can read them");
+  }
+  let listener = TcpListener::bind(args.bind).await?;
+  info!(
+    %base_url,
+    bind = %args.bind,
+    root = args.root.as_deref().unwrap_or("/"),
+    allow_uploads = args.allow_uploads,
+    "Serving Jupyter over HTTP"
+  );
+  loop {
+    let (stream, peer) = listener.accept().await?;
+    debug!(%peer, "HTTP client connected");
+    let server = server.clone();
+    tokio::spawn(async move {
+      let service = service_fn(move |req| {
+        let server = server.clone();
+        async move { Ok::<_, Infallible>(server.handle(req).await) }
+      });
+      if let Err(err) = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await {
+        debug!(%peer, error = %err, "HTTP connection ended with an error");
+      }
+    });
+  }
+}
+
+#[derive(Args, Debug)]
+#[command(
+  about = "Serve a Jupyter deployment as a local HTTP file server",
+  long_about = "Serve a Jupyter deployment as a local HTTP file server.\n\n\
+    Directories are shown as index pages and files are streamed from the Jupyter `/files` endpoint with \
+    Range support, so browsers, `curl` and `wget -r -np http://HOST:PORT/data/` can fetch data without \
+    knowing the Jupyter token. With --allow-uploads, `curl -T file.csv http://HOST:PORT/data/` stores a \
+    file (missing parent directories are created). There is no authentication or TLS, so keep --bind on \
+    loopback or put a reverse proxy in front; uploads are only accepted on other addresses with --allow-remote."
+)]
+pub struct ServeArgs {
+  #[arg(value_name = "JUPYTER_URL", default_value = DEFAULT_JUPYTER_URL, help = "Full Jupyter URL (supports ?token=<value>)")]
+  endpoint_url: Url,
+  #[arg(long, value_name = "TOKEN", env = "JUPYTER_TOKEN", help = "Override the token provided in the Jupyter URL")]
+  token: Option<String>,
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, env = "JUPYTER_TOKEN_FILE", conflicts_with = "token", help = "Load the API token from a file")]
+  token_file: Option<PathBuf>,
+
+  #[arg(long = "timeout", value_name = "SECONDS", env = "JUPYTER_SHELL_HTTP_TIMEOUT", value_parser = value_parser!(u64).range(1..=3600), help = "HTTP client timeout in seconds")]
+  http_timeout_secs: Option<u64>,
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_ACCEPT_INVALID_CERTS", help = "Disable TLS certificate verification for the Jupyter endpoint")]
+  accept_invalid_certs: bool,
+  #[arg(long, value_name = "PATH", env = "JUPYTER_SHELL_API_BASE_PATH", help = "Override the API base path instead of auto-detecting it")]
+  api_base_path: Option<String>,
+
+  #[arg(long, value_name = "IP:PORT", default_value = SERVE_BIND_ADDR, env = "JUPYTER_SHELL_SERVE_BIND", help = "Address the HTTP server listens on")]
+  bind: SocketAddr,
+  #[arg(long, value_name = "REMOTE_DIR", env = "JUPYTER_SHELL_SERVE_ROOT", help = "Serve this remote directory instead of the server root")]
+  root: Option<String>,
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_SERVE_ALLOW_UPLOADS", help = "Store the body of PUT requests as files")]
+  allow_uploads: bool,
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_SERVE_ALLOW_REMOTE", help = "Accept uploads on a non-loopback --bind address, without authentication")]
+  allow_remote: bool,
+  #[arg(long = "cache-ttl", value_name = "SECONDS", env = "JUPYTER_SHELL_SERVE_CACHE_TTL", value_parser = value_parser!(u64).range(0..=3600), help = "Cache file metadata and directory listings for this many seconds (0 disables)")]
+  cache_ttl_secs: Option<u64>,
+  #[arg(long, value_name = "RATE", env = "JUPYTER_SHELL_SERVE_LIMIT_RATE", value_parser = parse_rate, help = "Limit bandwidth to the Jupyter server to RATE bytes per second (accepts K, M and G suffixes)")]
+  limit_rate: Option<u64>,
+  #[arg(long, value_name = "N", env = "JUPYTER_SHELL_SERVE_JOBS", value_parser = RangedU64ValueParser::<usize>::new().range(1..), help = "Allow at most N HTTP requests to the Jupyter server in flight at once")]
+  jobs: Option<usize>,
+}
@@ -0,0 +1,93 @@
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+
+use clap::{builder::RangedU64ValueParser, value_parser, ArgAction, Args, ValueHint};
+use jupyter_shell::{
+  fs::FsService,
+  sftp::{self, SftpSubsystem},
+};
+use reqwest::Url;
+use tracing::{debug, info};
+
+use crate::cli::{build_throttle, parse_rate, serve_ssh, SshServerArgs, DEFAULT_JUPYTER_URL, TokenArgs};
+
+pub(crate) async fn run(args: SftpArgs) -> anyhow::Result<()> {
+  let token_args = TokenArgs {
+    endpoint_url: args.endpoint_url,
+    token: args.token,
+    token_file: args.token_file,
+    api_base_path: args.api_base_path,
+    http_timeout_secs: args.http_timeout_secs,
+    accept_invalid_certs: args.accept_invalid_certs,
+  };
+  let base_url = token_args.derive_base_url()?;
+  let client = token_args.build_throttled_client(build_throttle(args.limit_rate, args.jobs))?;
+
+  let mut fs = FsService::new(Arc::new(client));
+  if let Some(ttl) = args.cache_ttl_secs.filter(|ttl| *ttl > 0) {
+    fs = fs.with_cache(Duration::from_secs(ttl));
+  }
+  if args.shell_helpers {
+    fs = fs.with_shell_fallback();
+  }
+
+  let Some(listen) = args.listen else {
+    // Started as an SSH subsystem: packets arrive on stdin and replies go to stdout.
+    debug!(%base_url, "Serving Jupyter over SFTP on stdin/stdout");
+    sftp::serve(fs, tokio::io::join(tokio::io::stdin(), tokio::io::stdout())).await;
+    return Ok(());
+  };
+
+  let ssh_args = SshServerArgs {
+    host_key: args.host_key,
+    authorized_keys: args.authorized_keys,
+    password_file: args.password_file,
+    user: args.user,
+  };
+  info!(%base_url, %listen, "Serving Jupyter over SFTP");
+  serve_ssh(listen, &ssh_args, SftpSubsystem::new(fs)).await
+}
+
+#[derive(Args, Debug)]
+#[command(
+  about = "Serve a Jupyter deployment as an SFTP subsystem",
+  long_about = "Serve a Jupyter deployment as an SFTP subsystem.\n\n\
+    By default SFTP packets are read from stdin, so OpenSSH can run this command as its sftp \
+    subsystem and handle authentication, e.g. `Subsystem sftp /usr/local/bin/jupyter_shell sftp --token-file ...` \
+    in sshd_config, or `sftp -D 'jupyter_shell sftp ...'` locally. With --listen, an embedded SSH server \
+    serves the sftp subsystem itself (`sftp -P PORT user@host`, sshfs, rclone, WinSCP); clients log in \
+    with a key from --authorized-keys or the password in --password-file."
+)]
+pub struct SftpArgs {
+  #[arg(value_name = "JUPYTER_URL", default_value = DEFAULT_JUPYTER_URL, help = "Full Jupyter URL (supports ?token=<value>)")]
+  endpoint_url: Url,
+  #[arg(long, value_name = "TOKEN", env = "JUPYTER_TOKEN", help = "Override the token provided in the Jupyter URL")]
+  token: Option<String>,
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, env = "JUPYTER_TOKEN_FILE", conflicts_with = "token", help = "Load the API token from a file")]
+  token_file: Option<PathBuf>,
+
+  #[arg(long = "timeout", value_name = "SECONDS", env = "JUPYTER_SHELL_HTTP_TIMEOUT", value_parser = value_parser!(u64).range(1..=3600), help = "HTTP client timeout in seconds")]
+  http_timeout_secs: Option<u64>,
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_ACCEPT_INVALID_CERTS", help = "Disable TLS certificate verification for the Jupyter endpoint")]
+  accept_invalid_certs: bool,
+  #[arg(long, value_name = "PATH", env = "JUPYTER_SHELL_API_BASE_PATH", help = "Override the API base path instead of auto-detecting it")]
+  api_base_path: Option<String>,
+
+  #[arg(long, value_name = "IP:PORT", env = "JUPYTER_SHELL_SFTP_LISTEN", help = "Run an embedded SSH server serving SFTP on this address instead of using stdin/stdout")]
+  listen: Option<SocketAddr>,
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, requires = "listen", env = "JUPYTER_SHELL_SFTP_HOST_KEY", help = "Ed25519 host key in OpenSSH format, generated there if missing (default: a new key on every start)")]
+  host_key: Option<PathBuf>,
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, requires = "listen", env = "JUPYTER_SHELL_SFTP_AUTHORIZED_KEYS", help = "Let clients log in with the keys in this authorized_keys file")]
+  authorized_keys: Option<PathBuf>,
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, requires = "listen", env = "JUPYTER_SHELL_SFTP_PASSWORD_FILE", help = "Let clients log in with the password stored in this file")]
+  password_file: Option<PathBuf>,
+  #[arg(long, value_name = "NAME", requires = "listen", env = "JUPYTER_SHELL_SFTP_USER", help = "Only accept this user name (default: any)")]
+  user: Option<String>,
+  #[arg(long = "cache-ttl", value_name = "SECONDS", env = "JUPYTER_SHELL_SFTP_CACHE_TTL", value_parser = value_parser!(u64).range(0..=3600), help = "Cache file metadata and directory listings for this many seconds (0 disables)")]
+  cache_ttl_secs: Option<u64>,
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_SFTP_SHELL_HELPERS", help = "Apply modification times sent by clients (SETSTAT) by running helpers in a Jupyter terminal")]
+  shell_helpers: bool,
+  #[arg(long, value_name = "RATE", env = "JUPYTER_SHELL_SFTP_LIMIT_RATE", value_parser = parse_rate, help = "Limit bandwidth to the Jupyter server to RATE bytes per second (accepts K, M and G suffixes)")]
+  limit_rate: Option<u64>,
+  #[arg(long, value_name = "N", env = "JUPYTER_SHELL_SFTP_JOBS", value_parser = RangedU64ValueParser::<usize>::new().range(1..), help = "Allow at most N HTTP requests to the Jupyter server in flight at once")]
+  jobs: Option<usize>,
+}
@@ -1,6 +1,7 @@
 use std::{io::IsTerminal, path::PathBuf, time::Duration};
 
 use anyhow::{anyhow, bail, Context};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
 use clap::{value_parser, ArgAction, Args, ValueHint};
 use crossterm::terminal;
 use futures_util::StreamExt;
@@ -21,9 +22,19 @@ use crate::cli::{DEFAULT_JUPYTER_URL, TokenArgs};
 
 const STDIN_CHANNEL_CAPACITY: usize = 32;
 const RESIZE_CHANNEL_CAPACITY: usize = 8;
+/// Prefix of the markers framing an exec request's output.
+const EXEC_SENTINEL: &str = "__JUPYTER_SHELL_EXEC";
 
 #[derive(Args, Debug)]
-#[command(about = "Open an interactive shell against a Jupyter terminal")]
+#[command(
+  about = "Open an interactive shell against a Jupyter terminal",
+  long_about = "Open an interactive shell against a Jupyter terminal.\n\n\
+    The `sshd` command serves SSH clients with an embedded server. To keep OpenSSH in front instead, \
+    let it accept connections and authenticate users, and set `ForceCommand /usr/local/bin/jupyter_shell ssh --force-command --token-file ...` in sshd_config \
+    (optionally inside a `Match` block); `ssh host` then opens a Jupyter terminal, window size \
+    changes follow the SSH PTY, `ssh host 'cmd'` runs cmd as a one-line command, and sftp subsystem \
+    requests are served from the Jupyter file system."
+)]
 pub struct SshArgs {
   #[arg(long = "endpoint", value_name = "JUPYTER_URL", default_value = DEFAULT_JUPYTER_URL, help = "Full Jupyter URL (supports ?token=<value>)")]
   endpoint_url: Url,
@@ -45,6 +56,13 @@ pub struct SshArgs {
   keep_terminal: bool,
   #[arg(long, action = ArgAction::SetTrue, help = "Do not place the local TTY into raw mode")]
   no_raw: bool,
+  #[arg(
+    long,
+    action = ArgAction::SetTrue,
+    conflicts_with_all = ["terminal", "command"],
+    help = "Run as an sshd ForceCommand: exec requests ($SSH_ORIGINAL_COMMAND) run as one-line commands, other sessions get an interactive shell"
+  )]
+  force_command: bool,
 
   #[arg(
     long = "call-timeout",
@@ -65,7 +83,18 @@ pub struct SshArgs {
   command: Vec<String>,
 }
 
-pub(crate) async fn run(args: SshArgs) -> anyhow::Result<()> {
+pub(crate) async fn run(mut args: SshArgs) -> anyhow::Result<()> {
+  let mut sftp_request = false;
+  if args.force_command {
+    // sshd passes the client's exec request through the environment; PTY
+    // sessions arrive without one and resize via SIGWINCH like a local TTY.
+    if let Some(original) = std::env::var("SSH_ORIGINAL_COMMAND").ok().filter(|cmd| !cmd.trim().is_empty()) {
+      // ForceCommand also replaces the sftp subsystem, which sshd reports as its configured command.
+      sftp_request = is_sftp_subsystem(&original);
+      debug!(command = %original, sftp = sftp_request, "running SSH exec request");
+      args.command = vec![original];
+    }
+  }
   let one_line = !args.command.is_empty();
   if one_line && args.terminal.is_some() {
     bail!("one-line mode does not support --terminal (it would close the existing terminal). Omit --terminal to run a command in a fresh terminal.");
@@ -83,6 +112,18 @@ pub(crate) async fn run(args: SshArgs) -> anyhow::Result<()> {
   let base_url = token_args.derive_base_url()?;
   let client = token_args.build_client()?;
 
+  if sftp_request {
+    #[cfg(feature = "sftp")]
+    {
+      info!(%base_url, "Serving SFTP subsystem request");
+      let fs = jupyter_shell::fs::FsService::new(std::sync::Arc::new(client));
+      jupyter_shell::sftp::serve(fs, tokio::io::join(tokio::io::stdin(), tokio::io::stdout())).await;
+      return Ok(());
+    }
+    #[cfg(not(feature = "sftp"))]
+    bail!("SFTP requests need the `sftp` feature");
+  }
+
   let (terminal_name, created_terminal) = match args.terminal.clone() {
     Some(name) => {
       client
@@ -100,6 +141,32 @@ pub(crate) async fn run(args: SshArgs) -> anyhow::Result<()> {
     }
   };
 
+  if one_line && args.force_command {
+    // Exec requests behave like commands run by sshd itself: stdin is forwarded,
+    // the exit status is passed on and nothing is cut short unless asked to.
+    let cmd = join_shell_command(&args.command);
+    let timeout = args.call_timeout_secs.map(Duration::from_secs);
+    info!(%base_url, terminal = %terminal_name, "Running SSH exec request against Jupyter terminal");
+    // Every failure below is bound rather than returned early so the terminal
+    // created for this request is deleted before the error is reported.
+    let status = match TerminalService::connect(client, &terminal_name, false).await {
+      Ok(service) => match timeout {
+        Some(dur) => tokio::time::timeout(dur, run_exec(service, &cmd))
+          .await
+          .unwrap_or_else(|_| Err(anyhow!("command timed out after {}s", dur.as_secs()))),
+        None => run_exec(service, &cmd).await,
+      },
+      Err(e) => Err(anyhow!(e).context(format!("failed to connect to terminal {terminal_name}"))),
+    };
+    if created_terminal && !args.keep_terminal {
+      let client = token_args.build_client()?;
+      client.delete_terminal(&terminal_name).await.ok();
+    }
+    let status = status?;
+    debug!(status, "SSH exec request finished");
+    std::process::exit(status);
+  }
+
   if one_line {
     let cmd = join_shell_command(&args.command);
     let timeout = args
@@ -231,6 +298,158 @@ pub(crate) async fn run(args: SshArgs) -> anyhow::Result<()> {
   Ok(())
 }
 
+/// Run an sshd exec request on `service` and return the command's exit status.
+///
+/// Stdin is typed into the terminal base64-encoded and decoded on the far side,
+/// so binary input and control characters survive the terminal's line discipline.
+/// Output is streamed with terminal post-processing off; stderr arrives merged
+/// into it, as the terminal has a single output stream.
+async fn run_exec(service: TerminalService, command: &str) -> anyhow::Result<i32> {
+  let id = format!("{:x}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_nanos());
+  let mut frame = ExecFrame::new(&id);
+  // The status is printed inside the pipeline, so it arrives as soon as the command
+  // exits even while `base64 -d` still waits for stdin that will never be read. The
+  // subshell keeps an `exit` in the command from skipping it.
+  let script = format!(
+    "stty -echo -opost; printf '%s_%s\\n' {EXEC_SENTINEL}_BEGIN {id}; \
+     base64 -d | {{ ( {command}\n); printf '%s_%s_%s\\n' {EXEC_SENTINEL}_END {id} $?; }}\n"
+  );
+  let TerminalSplit { mut sink, mut stream, .. } = service.split();
+  sink.send_message(InputMessage::Stdin(script)).await.map_err(to_anyhow)?;
+
+  let (stdin_tx, mut stdin_rx) = mpsc::channel::<Vec<u8>>(STDIN_CHANNEL_CAPACITY);
+  let stdin_task = tokio::spawn(read_stdin(stdin_tx));
+  let mut stdout = tokio::io::stdout();
+  let mut encoder = StdinEncoder::default();
+  let mut stdin_closed = false;
+  let status = loop {
+    tokio::select! {
+      msg = stream.next() => match msg {
+        Some(Ok(OutputMessage::Stdout(data))) => {
+          let (output, status) = frame.push(&data);
+          stdout.write_all(output.as_bytes()).await.context("failed to write to stdout")?;
+          stdout.flush().await.context("failed to flush stdout")?;
+          if let Some(status) = status {
+            break status;
+          }
+        }
+        Some(Ok(OutputMessage::Init {})) => {}
+        Some(Err(err)) => {
+          stdin_task.abort();
+          return Err(to_anyhow(err));
+        }
+        Some(Ok(OutputMessage::Disconnect(_))) | None => {
+          stdin_task.abort();
+          bail!("the terminal closed before the command finished");
+        }
+      },
+      // Input typed before the command starts would be read by the shell instead.
+      chunk = stdin_rx.recv(), if frame.started() && !stdin_closed => {
+        let line = match chunk {
+          Some(chunk) => encoder.push(&chunk),
+          None => {
+            stdin_closed = true;
+            // Ctrl-D on a line of its own ends `base64 -d`'s input.
+            Some(encoder.finish().map(|line| line + "\u{4}").unwrap_or_else(|| "\u{4}".to_string()))
+          }
+        };
+        if let Some(line) = line {
+          sink.send_message(InputMessage::Stdin(line)).await.map_err(to_anyhow)?;
+        }
+      }
+    }
+  };
+  stdin_task.abort();
+  if !stdin_closed {
+    // Release `base64 -d` in case the terminal is kept.
+    sink.send_message(InputMessage::Stdin("\u{4}".to_string())).await.ok();
+  }
+  Ok(status)
+}
+
+/// Separates an exec request's output from the markers framing it.
+#[derive(Debug)]
+struct ExecFrame {
+  begin: String,
+  end: String,
+  buffer: String,
+  started: bool,
+}
+
+impl ExecFrame {
+  fn new(id: &str) -> Self {
+    Self {
+      begin: format!("{EXEC_SENTINEL}_BEGIN_{id}\n"),
+      end: format!("{EXEC_SENTINEL}_END_{id}_"),
+      buffer: String::new(),
+      started: false,
+    }
+  }
+
+  /// Whether the command has started, so input now reaches it rather than the shell.
+  fn started(&self) -> bool {
+    self.started
+  }
+
+  /// Take terminal output, returning the part that belongs to the command and,
+  /// once it has exited, its status.
+  fn push(&mut self, data: &str) -> (String, Option<i32>) {
+    self.buffer.push_str(data);
+    if !self.started {
+      // Skip the echoed command line; the markers are assembled by `printf`.
+      let Some(pos) = self.buffer.find(&self.begin) else {
+        return (String::new(), None);
+      };
+      self.buffer.drain(..pos + self.begin.len());
+      self.started = true;
+    }
+    if let Some(pos) = self.buffer.find(&self.end) {
+      let rest = &self.buffer[pos + self.end.len()..];
+      let status = rest.find('\n').map(|newline| rest[..newline].trim().parse().unwrap_or(255));
+      let output: String = self.buffer.drain(..pos).collect();
+      return (output, status);
+    }
+    // Hold back anything that could be the start of the end marker.
+    let keep = (1..self.end.len().min(self.buffer.len() + 1))
+      .rev()
+      .find(|len| self.buffer.ends_with(&self.end[..*len]))
+      .unwrap_or(0);
+    let output = self.buffer.drain(..self.buffer.len() - keep).collect();
+    (output, None)
+  }
+}
+
+/// Encodes stdin as base64 lines, carrying incomplete 3-byte groups to the next chunk.
+#[derive(Debug, Default)]
+struct StdinEncoder {
+  carry: Vec<u8>,
+}
+
+impl StdinEncoder {
+  fn push(&mut self, chunk: &[u8]) -> Option<String> {
+    self.carry.extend_from_slice(chunk);
+    let usable = self.carry.len() / 3 * 3;
+    if usable == 0 {
+      return None;
+    }
+    let line = STANDARD.encode(&self.carry[..usable]) + "\n";
+    self.carry.drain(..usable);
+    Some(line)
+  }
+
+  /// Encode the remaining bytes, with padding.
+  fn finish(&mut self) -> Option<String> {
+    let rest = std::mem::take(&mut self.carry);
+    (!rest.is_empty()).then(|| STANDARD.encode(&rest) + "\n")
+  }
+}
+
+/// Whether an sshd exec request is the sftp subsystem (`internal-sftp` or a path to `sftp-server`).
+fn is_sftp_subsystem(command: &str) -> bool {
+  let program = command.split_whitespace().next().unwrap_or_default();
+  program == "internal-sftp" || program.rsplit('/').next() == Some("sftp-server")
+}
+
 fn join_shell_command(parts: &[String]) -> String {
   if parts.is_empty() {
     return String::new();
@@ -358,3 +577,55 @@ fn spawn_resize_listener() -> anyhow::Result<ResizeChannel> {
   });
   Ok(rx)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn exec_frame_strips_markers_split_across_chunks() {
+    let mut frame = ExecFrame::new("42");
+    let chunks = [
+      "printf ... echo\r\n__JUPYTER_SHELL_EXEC_BE",
+      "GIN_42\nhello\nwor",
+      "ld __JUPYTER_SHELL_EX",
+      "EC_END_42_",
+      "3\n",
+    ];
+    let mut output = String::new();
+    let mut status = None;
+    for chunk in chunks {
+      let (text, code) = frame.push(chunk);
+      output.push_str(&text);
+      status = code;
+      if status.is_some() {
+        break;
+      }
+    }
+    assert!(frame.started());
+    assert_eq!(output, "hello\nworld ");
+    assert_eq!(status, Some(3));
+  }
+
+  #[test]
+  fn exec_frame_waits_for_the_command_to_start() {
+    let mut frame = ExecFrame::new("7");
+    assert_eq!(frame.push("$ stty -echo\r\n"), (String::new(), None));
+    assert!(!frame.started());
+    assert_eq!(frame.push("__JUPYTER_SHELL_EXEC_BEGIN_7\n__JUPYTER_SHELL_EXEC_END_7_0\n"), (String::new(), Some(0)));
+  }
+
+  #[test]
+  fn stdin_encoder_carries_partial_groups() {
+    let mut encoder = StdinEncoder::default();
+    let mut encoded = String::new();
+    for chunk in [&b"ab"[..], b"cdefg", b"h"] {
+      encoded.extend(encoder.push(chunk));
+    }
+    encoded.extend(encoder.finish());
+    assert_eq!(encoder.finish(), None);
+    let decoded: Vec<u8> = encoded.lines().flat_map(|line| STANDARD.decode(line).unwrap()).collect();
+    assert_eq!(decoded, b"abcdefgh");
+    assert!(encoded.lines().all(|line| !line.trim_end_matches('=').contains('=')));
+  }
+}
@@ -0,0 +1,84 @@
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+
+use clap::{value_parser, ArgAction, Args, ValueHint};
+#[cfg(feature = "sftp")]
+use jupyter_shell::fs::FsService;
+use jupyter_shell::sshd::TerminalSessions;
+use reqwest::Url;
+use tracing::info;
+
+use crate::cli::{serve_ssh, SshServerArgs, DEFAULT_JUPYTER_URL, TokenArgs};
+
+pub(crate) async fn run(args: SshdArgs) -> anyhow::Result<()> {
+  let token_args = TokenArgs {
+    endpoint_url: args.endpoint_url,
+    token: args.token,
+    token_file: args.token_file,
+    api_base_path: args.api_base_path,
+    http_timeout_secs: args.http_timeout_secs,
+    accept_invalid_certs: args.accept_invalid_certs,
+  };
+  let base_url = token_args.derive_base_url()?;
+  let client = Arc::new(token_args.build_client()?);
+
+  let mut sessions = TerminalSessions::new(client.clone());
+  if let Some(secs) = args.exec_timeout_secs {
+    sessions = sessions.with_exec_timeout(Duration::from_secs(secs));
+  }
+  #[cfg(feature = "sftp")]
+  if !args.no_sftp {
+    sessions = sessions.with_sftp(FsService::new(client));
+  }
+
+  let ssh_args = SshServerArgs {
+    host_key: args.host_key,
+    authorized_keys: args.authorized_keys,
+    password_file: args.password_file,
+    user: args.user,
+  };
+  info!(%base_url, listen = %args.listen, "Serving Jupyter terminals over SSH");
+  serve_ssh(args.listen, &ssh_args, sessions).await
+}
+
+#[derive(Args, Debug)]
+#[command(
+  about = "Serve Jupyter terminals to SSH clients",
+  long_about = "Serve Jupyter terminals to SSH clients.\n\n\
+    Runs an embedded SSH server, so `ssh -p 2222 localhost` opens a shell in a new Jupyter terminal. \
+    Terminal resizes are passed on, and the terminal is deleted when the session ends. \
+    `ssh host command` runs the command in a terminal of its own and reports its exit status; \
+    the output arrives once the command finishes, and the command cannot read stdin. \
+    The sftp subsystem is served as well, for `sftp`, `scp` and `sshfs`. \
+    Clients log in with a key from --authorized-keys or the password in --password-file."
+)]
+pub struct SshdArgs {
+  #[arg(value_name = "JUPYTER_URL", default_value = DEFAULT_JUPYTER_URL, help = "Full Jupyter URL (supports ?token=<value>)")]
+  endpoint_url: Url,
+  #[arg(long, value_name = "TOKEN", env = "JUPYTER_TOKEN", help = "Override the token provided in the Jupyter URL")]
+  token: Option<String>,
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, env = "JUPYTER_TOKEN_FILE", conflicts_with = "token", help = "Load the API token from a file")]
+  token_file: Option<PathBuf>,
+
+  #[arg(long = "timeout", value_name = "SECONDS", env = "JUPYTER_SHELL_HTTP_TIMEOUT", value_parser = value_parser!(u64).range(1..=3600), help = "HTTP client timeout in seconds")]
+  http_timeout_secs: Option<u64>,
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_ACCEPT_INVALID_CERTS", help = "Disable TLS certificate verification for the Jupyter endpoint")]
+  accept_invalid_certs: bool,
+  #[arg(long, value_name = "PATH", env = "JUPYTER_SHELL_API_BASE_PATH", help = "Override the API base path instead of auto-detecting it")]
+  api_base_path: Option<String>,
+
+  #[arg(long, value_name = "IP:PORT", default_value = "127.0.0.1:2222", env = "JUPYTER_SHELL_SSHD_LISTEN", help = "Address to accept SSH connections on")]
+  listen: SocketAddr,
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, env = "JUPYTER_SHELL_SSHD_HOST_KEY", help = "Ed25519 host key in OpenSSH format, generated there if missing (default: a new key on every start)")]
+  host_key: Option<PathBuf>,
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, env = "JUPYTER_SHELL_SSHD_AUTHORIZED_KEYS", help = "Let clients log in with the keys in this authorized_keys file")]
+  authorized_keys: Option<PathBuf>,
+  #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, env = "JUPYTER_SHELL_SSHD_PASSWORD_FILE", help = "Let clients log in with the password stored in this file")]
+  password_file: Option<PathBuf>,
+  #[arg(long, value_name = "NAME", env = "JUPYTER_SHELL_SSHD_USER", help = "Only accept this user name (default: any)")]
+  user: Option<String>,
+  #[arg(long = "exec-timeout", value_name = "SECONDS", env = "JUPYTER_SHELL_SSHD_EXEC_TIMEOUT", value_parser = value_parser!(u64).range(1..), help = "Stop waiting for `ssh host command` requests after this many seconds (default: no limit)")]
+  exec_timeout_secs: Option<u64>,
+  #[cfg(feature = "sftp")]
+  #[arg(long, action = ArgAction::SetTrue, env = "JUPYTER_SHELL_SSHD_NO_SFTP", help = "Refuse the sftp subsystem")]
+  no_sftp: bool,
+}
@@ -1,39 +1,475 @@
 use std::{
+  collections::HashSet,
   fmt,
   path::{Component, Path, PathBuf},
+  sync::Arc,
   time::SystemTime,
 };
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use libunftp::{
-  auth::DefaultUser,
-  storage::{Error, ErrorKind, Fileinfo, Metadata, Permissions, StorageBackend},
+  auth::{AuthenticationError, Authenticator, Credentials, UserDetail},
+  storage::{Error, ErrorKind, Fileinfo, Metadata, Permissions, StorageBackend, FEATURE_RESTART},
+  notification::{EventMeta, PresenceEvent, PresenceListener},
+  options::FtpsRequired,
   ServerBuilder,
 };
+use parking_lot::Mutex;
 use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
 use tokio::io::{AsyncRead, AsyncReadExt};
 
 use crate::{
-  api::client::ClientError,
+  api::{client::ClientError, jupyter::JupyterApi},
   fs::{Entry, EntryKind, FsError, FsService},
+  shell::{RemoteFileType, RemoteStat},
+  state::Cached,
 };
 
+/// Bytes read from an FTP data connection per Contents API save; at most two
+/// chunks are held in memory per upload.
+const UPLOAD_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
 /// Convenience alias for configuring a libunftp server backed by a [`FsService`].
-pub type FtpServerBuilder = ServerBuilder<FsStorage, DefaultUser>;
+pub type FtpServerBuilder = ServerBuilder<FsStorage, JupyterUser>;
 
 /// Construct a libunftp [`ServerBuilder`] that serves files via the Jupyter Contents API.
 pub fn server_builder(fs: FsService) -> FtpServerBuilder {
-  ServerBuilder::new(Box::new(move || FsStorage::new(fs.clone())))
+  server_builder_with(FsStorage::new(fs))
+}
+
+/// Like [`server_builder`], but serves a preconfigured [`FsStorage`].
+///
+/// Logins are anonymous; replace the authenticator with
+/// [`ServerBuilder::authenticator`] and an [`FtpAuthenticator`] to require credentials.
+pub fn server_builder_with(storage: FsStorage) -> FtpServerBuilder {
+  ServerBuilder::with_authenticator(Box::new(move || storage.for_session()), Arc::new(FtpAuthenticator::Anonymous))
+}
+
+/// Explicit TLS (`AUTH TLS`) settings for the FTP gateway.
+#[derive(Debug, Clone)]
+pub struct FtpsOptions {
+  /// PEM file with the certificate chain.
+  pub cert_file: PathBuf,
+  /// PEM file with the private key.
+  pub key_file: PathBuf,
+  /// Refuse logins and commands on a control channel that has not been upgraded to TLS.
+  pub require_control: bool,
+  /// Refuse transfers over plaintext data channels (`PROT C`).
+  pub require_data: bool,
+}
+
+impl FtpsOptions {
+  pub fn new(cert_file: impl Into<PathBuf>, key_file: impl Into<PathBuf>) -> Self {
+    Self { cert_file: cert_file.into(), key_file: key_file.into(), require_control: false, require_data: false }
+  }
+
+  /// Require TLS on both the control and the data channels.
+  pub fn required(mut self, required: bool) -> Self {
+    self.require_control = required;
+    self.require_data = required;
+    self
+  }
+
+  /// Turn on FTPS for `builder`; the certificate and key are loaded when the server is built.
+  pub fn apply(&self, builder: FtpServerBuilder) -> FtpServerBuilder {
+    builder
+      .ftps(self.cert_file.clone(), self.key_file.clone())
+      .ftps_required(FtpsRequired::from(self.require_control), FtpsRequired::from(self.require_data))
+  }
+}
+
+/// A logged-in FTP user.
+#[derive(Clone)]
+pub struct JupyterUser {
+  username: String,
+  /// Service bound to the user's own Jupyter token; `None` uses the gateway's client.
+  fs: Option<FsService>,
+}
+
+impl JupyterUser {
+  pub fn username(&self) -> &str {
+    &self.username
+  }
+}
+
+impl UserDetail for JupyterUser {}
+
+impl fmt::Display for JupyterUser {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.username)
+  }
+}
+
+impl fmt::Debug for JupyterUser {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("JupyterUser")
+      .field("username", &self.username)
+      .field("own_token", &self.fs.is_some())
+      .finish()
+  }
+}
+
+/// How the FTP gateway checks logins.
+pub enum FtpAuthenticator {
+  /// Accept any username and password; every session uses the gateway's Jupyter client.
+  Anonymous,
+  /// Accept a single configured username and password.
+  Password { username: String, password: String },
+  /// Treat the FTP password as a Jupyter token. Each login gets its own client with
+  /// that token, derived from `fs`, so one gateway can serve several Jupyter users.
+  Token { fs: FsService },
+}
+
+impl fmt::Debug for FtpAuthenticator {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      FtpAuthenticator::Anonymous => f.write_str("Anonymous"),
+      FtpAuthenticator::Password { username, .. } => f.debug_struct("Password").field("username", username).finish(),
+      FtpAuthenticator::Token { .. } => f.write_str("Token"),
+    }
+  }
+}
+
+#[async_trait]
+impl Authenticator<JupyterUser> for FtpAuthenticator {
+  async fn authenticate(&self, username: &str, creds: &Credentials) -> Result<JupyterUser, AuthenticationError> {
+    let password = creds.password.as_deref().unwrap_or_default();
+    match self {
+      FtpAuthenticator::Anonymous => Ok(JupyterUser { username: username.to_string(), fs: None }),
+      FtpAuthenticator::Password { username: expected_user, password: expected_password } => {
+        // Compare digests so the check does not leak how much of the password matched.
+        let user_ok = Sha256::digest(username) == Sha256::digest(expected_user);
+        let password_ok = Sha256::digest(password) == Sha256::digest(expected_password);
+        if !(user_ok && password_ok) {
+          debug!(%username, source = %creds.source_ip, "FTP login rejected");
+          return Err(if user_ok { AuthenticationError::BadPassword } else { AuthenticationError::BadUser });
+        }
+        Ok(JupyterUser { username: username.to_string(), fs: None })
+      }
+      FtpAuthenticator::Token { fs } => {
+        if password.trim().is_empty() {
+          return Err(AuthenticationError::BadPassword);
+        }
+        let client = fs
+          .client()
+          .for_token(password.trim())
+          .map_err(|err| AuthenticationError::with_source("invalid token", err))?;
+        // Ask the server whether it accepts the token before handing out a session.
+        match client.status().await {
+          Ok(_) => {
+            debug!(%username, source = %creds.source_ip, "FTP login accepted with a Jupyter token");
+            Ok(JupyterUser { username: username.to_string(), fs: Some(fs.with_client(Arc::new(client))) })
+          }
+          Err(ClientError::Api { status: StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN, .. }) => {
+            debug!(%username, source = %creds.source_ip, "FTP login rejected: Jupyter refused the token");
+            Err(AuthenticationError::BadPassword)
+          }
+          Err(err) => Err(AuthenticationError::with_source("could not validate the token with Jupyter", err)),
+        }
+      }
+    }
+  }
+}
+
+/// Caps the number of FTP sessions logged in at the same time.
+///
+/// libunftp has no connection limit of its own, so logins beyond the cap are
+/// refused and sessions are tracked through presence notifications. Connections
+/// that never log in are not counted; they are bounded by the idle timeout.
+#[derive(Debug, Clone)]
+pub struct SessionLimit {
+  max: usize,
+  sessions: Arc<Mutex<Sessions>>,
+}
+
+#[derive(Debug, Default)]
+struct Sessions {
+  /// Trace ids of the sessions currently logged in.
+  active: HashSet<String>,
+  /// Logins being authenticated or accepted but not yet reported as logged in.
+  pending: usize,
+}
+
+impl SessionLimit {
+  pub fn new(max: usize) -> Self {
+    Self { max, sessions: Arc::default() }
+  }
+
+  /// Number of sessions currently logged in.
+  pub fn active(&self) -> usize {
+    self.sessions.lock().active.len()
+  }
+
+  /// Wrap `authenticator` so logins are refused while the limit is reached.
+  pub fn apply(&self, builder: FtpServerBuilder, authenticator: FtpAuthenticator) -> FtpServerBuilder {
+    let limited = LimitedAuthenticator { inner: authenticator, limit: self.clone() };
+    builder.authenticator(Arc::new(limited)).notify_presence(self.clone())
+  }
+
+  /// Claim a slot for a login about to be authenticated, unless the limit is reached.
+  ///
+  /// Checking and claiming under one lock keeps concurrent logins from all passing
+  /// the check before any of them is counted.
+  fn reserve(&self) -> bool {
+    let mut sessions = self.sessions.lock();
+    if sessions.active.len() + sessions.pending >= self.max {
+      return false;
+    }
+    sessions.pending += 1;
+    true
+  }
+
+  /// Give back a slot claimed by [`SessionLimit::reserve`] for a login that failed.
+  fn release(&self) {
+    let mut sessions = self.sessions.lock();
+    sessions.pending = sessions.pending.saturating_sub(1);
+  }
+}
+
+#[async_trait]
+impl PresenceListener for SessionLimit {
+  async fn receive_presence_event(&self, event: PresenceEvent, meta: EventMeta) {
+    let mut sessions = self.sessions.lock();
+    match event {
+      PresenceEvent::LoggedIn => {
+        // The slot reserved during authentication now belongs to this session.
+        sessions.pending = sessions.pending.saturating_sub(1);
+        sessions.active.insert(meta.trace_id);
+      }
+      // Also sent for connections that never logged in; those are not in the set.
+      PresenceEvent::LoggedOut => {
+        sessions.active.remove(&meta.trace_id);
+      }
+    }
+    trace!(active = sessions.active.len(), pending = sessions.pending, max = self.max, user = %meta.username, "FTP session count changed");
+  }
+}
+
+#[derive(Debug)]
+struct LimitedAuthenticator {
+  inner: FtpAuthenticator,
+  limit: SessionLimit,
+}
+
+#[async_trait]
+impl Authenticator<JupyterUser> for LimitedAuthenticator {
+  async fn authenticate(&self, username: &str, creds: &Credentials) -> Result<JupyterUser, AuthenticationError> {
+    if !self.limit.reserve() {
+      warn!(%username, source = %creds.source_ip, max = self.limit.max, "FTP login refused: too many sessions");
+      return Err(AuthenticationError::new("too many concurrent sessions"));
+    }
+    let result = self.inner.authenticate(username, creds).await;
+    if result.is_err() {
+      self.limit.release();
+    }
+    result
+  }
 }
 
 #[derive(Clone)]
 pub struct FsStorage {
   fs: FsService,
+  recursive_rmdir: bool,
+  atomic_uploads: bool,
+  checkpoint_uploads: bool,
+  shell_metadata: bool,
+  read_only: bool,
+  /// Remote directory the client sees as `/`, normalized with a leading slash.
+  root: String,
+  /// `last_modified` of files this session downloaded or uploaded, used to detect
+  /// concurrent edits before overwriting them.
+  observed: Arc<Cached<String, DateTime<Utc>>>,
 }
 
 impl FsStorage {
   pub fn new(fs: FsService) -> Self {
-    Self { fs }
+    Self {
+      fs,
+      recursive_rmdir: false,
+      atomic_uploads: true,
+      checkpoint_uploads: false,
+      shell_metadata: false,
+      read_only: false,
+      root: "/".into(),
+      observed: Arc::default(),
+    }
+  }
+
+  /// Reject every command that modifies files: `STOR`, `DELE`, `RNFR`/`RNTO`, `MKD` and `RMD`.
+  pub fn read_only(mut self, enabled: bool) -> Self {
+    self.read_only = enabled;
+    self
+  }
+
+  /// Serve only the remote directory `root`; clients see it as `/` and cannot leave it.
+  pub fn root(mut self, root: impl AsRef<Path>) -> Self {
+    self.root = normalize_request_path(root);
+    self
+  }
+
+  /// Map a client path to the Jupyter path below the configured root.
+  fn resolve<P: AsRef<Path>>(&self, path: P) -> String {
+    // Normalizing first drops `..` segments that would climb above the client's `/`.
+    let target = normalize_request_path(path);
+    if self.root == "/" {
+      target
+    } else if target == "/" {
+      self.root.clone()
+    } else {
+      format!("{}{}", self.root, target)
+    }
+  }
+
+  /// Map a Jupyter entry path back to the path the client sees.
+  fn client_path(&self, raw: &str) -> PathBuf {
+    let absolute = absolute_entry_path(raw);
+    match absolute.strip_prefix(&self.root) {
+      Ok(relative) => Path::new("/").join(relative),
+      Err(_) => absolute,
+    }
+  }
+
+  fn entry_to_fileinfo(&self, entry: Entry) -> Fileinfo<PathBuf, FsMetadata> {
+    Fileinfo {
+      path: self.client_path(&entry.path),
+      metadata: FsMetadata::from(entry),
+    }
+  }
+
+  fn check_writable(&self, command: &str) -> Result<(), Error> {
+    if self.read_only {
+      debug!(command, "FTP write rejected: gateway is read-only");
+      return Err(Error::from(ErrorKind::PermissionDenied));
+    }
+    Ok(())
+  }
+
+  /// Attach shell metadata to listing entries by name and add entries the Contents API hides.
+  fn merge_stats(&self, listing: &mut Vec<Fileinfo<PathBuf, FsMetadata>>, stats: Vec<RemoteStat>) {
+    for stat in stats {
+      match listing.iter_mut().find(|info| info.metadata.entry.name == stat.name()) {
+        Some(info) => info.metadata = info.metadata.clone().with_stat(stat),
+        None => {
+          let entry = Entry::from_stat(&stat);
+          listing.push(Fileinfo {
+            path: self.client_path(&entry.path),
+            metadata: FsMetadata::from(entry).with_stat(stat),
+          });
+        }
+      }
+    }
+  }
+
+  /// Checkpoint existing files before `STOR` overwrites them (takes precedence over atomic uploads).
+  pub fn checkpoint_uploads(mut self, enabled: bool) -> Self {
+    self.checkpoint_uploads = enabled;
+    self
+  }
+
+  /// Clone the configuration with fresh per-session state.
+  fn for_session(&self) -> Self {
+    Self {
+      observed: Arc::default(),
+      ..self.clone()
+    }
+  }
+
+  fn observe(&self, path: &str, last_modified: Option<DateTime<Utc>>) {
+    match last_modified {
+      Some(stamp) => {
+        self.observed.insert(path.to_string(), stamp);
+      }
+      None => {
+        self.observed.remove(path);
+      }
+    }
+  }
+
+  /// Write `STOR` uploads to a temporary file and rename it into place once verified.
+  pub fn atomic_uploads(mut self, enabled: bool) -> Self {
+    self.atomic_uploads = enabled;
+    self
+  }
+
+  /// Report real modes, owners and symlinks in `LIST`/`MLST` via the shell fallback.
+  ///
+  /// Has no effect unless the [`FsService`] was built with
+  /// [`FsService::with_shell_fallback`]; shell failures fall back to Contents API values.
+  pub fn shell_metadata(mut self, enabled: bool) -> Self {
+    self.shell_metadata = enabled;
+    self
+  }
+
+  fn uses_shell(&self, user: &JupyterUser) -> bool {
+    self.shell_metadata && self.fs_for(user).shell().is_some()
+  }
+
+  /// The service to use for `user`: their own client when they logged in with a token.
+  fn fs_for<'a>(&'a self, user: &'a JupyterUser) -> &'a FsService {
+    user.fs.as_ref().unwrap_or(&self.fs)
+  }
+
+  /// Write `input` at byte `start_pos` of an existing file, as requested by `REST` + `STOR`.
+  ///
+  /// Resuming exactly at the end of the file appends through chunked saves. Any
+  /// other offset rewrites the file as its first `start_pos` bytes followed by
+  /// `input`, streamed through a temporary file so the original can still be read;
+  /// with checkpoints enabled that is refused. Returns the number of bytes taken
+  /// from `input`.
+  async fn write_at<R>(&self, user: &JupyterUser, target: &str, input: R, start_pos: u64) -> Result<(Entry, u64), FsError>
+  where
+    R: AsyncRead + Send + Sync + Unpin,
+  {
+    let fs = self.fs_for(user);
+    let existing = fs.metadata(target).await?;
+    let size = existing.size.unwrap_or(0);
+    if size == start_pos {
+      debug!(%target, start = start_pos, "resuming upload by appending");
+      let upload = fs.append_stream(target, input, UPLOAD_CHUNK_SIZE).await?;
+      return Ok((upload.entry, upload.bytes));
+    }
+    if start_pos > size {
+      return Err(FsError::InvalidPayload(format!(
+        "restart offset {} is past the end of {} ({} bytes)",
+        start_pos, target, size
+      )));
+    }
+    debug!(%target, start = start_pos, size, "resuming upload by rewriting the file");
+    if self.checkpoint_uploads {
+      // A swap through a temporary file would discard the checkpoint, and rewriting
+      // in place would have to hold the whole file in memory.
+      return Err(FsError::NotImplemented(format!(
+        "restarting {} at offset {} of {} bytes while checkpoints are enabled",
+        target, start_pos, size
+      )));
+    }
+    let prefix = fs.download_reader(target).await?.reader.take(start_pos);
+    let upload = fs.upload_stream_atomic(target, prefix.chain(input), UPLOAD_CHUNK_SIZE).await?;
+    Ok((upload.entry, upload.bytes - start_pos))
+  }
+
+  /// Replace `target` with `input`, honouring the checkpoint and atomic upload settings.
+  async fn store<R>(&self, user: &JupyterUser, target: &str, input: R) -> Result<(Entry, u64), FsError>
+  where
+    R: AsyncRead + Send + Sync + Unpin,
+  {
+    let fs = self.fs_for(user);
+    let upload = if self.checkpoint_uploads {
+      fs.upload_stream_with_checkpoint(target, input, UPLOAD_CHUNK_SIZE).await?.0
+    } else if self.atomic_uploads {
+      fs.upload_stream_atomic(target, input, UPLOAD_CHUNK_SIZE).await?
+    } else {
+      fs.upload_stream(target, input, UPLOAD_CHUNK_SIZE).await?
+    };
+    Ok((upload.entry, upload.bytes))
+  }
+
+  /// Let `RMD` remove non-empty directories together with their contents.
+  pub fn recursive_rmdir(mut self, enabled: bool) -> Self {
+    self.recursive_rmdir = enabled;
+    self
   }
 }
 
@@ -44,54 +480,73 @@ impl fmt::Debug for FsStorage {
 }
 
 #[async_trait]
-impl StorageBackend<DefaultUser> for FsStorage {
+impl StorageBackend<JupyterUser> for FsStorage {
+  fn supported_features(&self) -> u32 {
+    FEATURE_RESTART
+  }
+
   type Metadata = FsMetadata;
 
   async fn metadata<P: AsRef<Path> + Send + fmt::Debug>(
     &self,
-    _user: &DefaultUser,
+    user: &JupyterUser,
     path: P,
   ) -> Result<Self::Metadata, Error> {
-    let target = normalize_request_path(path);
+    let target = self.resolve(path);
     trace!(%target, "FTP metadata lookup");
-    let entry = self.fs.metadata(&target).await.map_err(map_fs_error)?;
-    Ok(FsMetadata::from(entry))
+    let entry = self.fs_for(user).metadata(&target).await.map_err(map_fs_error)?;
+    let mut metadata = FsMetadata::from(entry);
+    if self.uses_shell(user) {
+      match self.fs_for(user).stat(&target).await {
+        Ok(stat) => metadata.stat = Some(stat),
+        Err(err) => debug!(error = %err, %target, "shell stat failed; using contents metadata"),
+      }
+    }
+    Ok(metadata)
   }
 
   async fn list<P: AsRef<Path> + Send + fmt::Debug>(
     &self,
-    _user: &DefaultUser,
+    user: &JupyterUser,
     path: P,
   ) -> Result<Vec<Fileinfo<PathBuf, Self::Metadata>>, Error>
   where
     Self::Metadata: Metadata,
   {
-    let target = normalize_request_path(path);
+    let target = self.resolve(path);
     trace!(%target, "FTP directory listing");
-    let entries = self.fs.ls(&target).await.map_err(map_fs_error)?;
-    Ok(entries.into_iter().map(entry_to_fileinfo).collect())
+    let entries = self.fs_for(user).ls(&target).await.map_err(map_fs_error)?;
+    let mut listing: Vec<Fileinfo<PathBuf, FsMetadata>> = entries.into_iter().map(|entry| self.entry_to_fileinfo(entry)).collect();
+    if self.uses_shell(user) {
+      match self.fs_for(user).stat_dir(&target).await {
+        Ok(stats) => self.merge_stats(&mut listing, stats),
+        Err(err) => debug!(error = %err, %target, "shell listing failed; using contents metadata"),
+      }
+    }
+    Ok(listing)
   }
 
   async fn get<P: AsRef<Path> + Send + fmt::Debug>(
     &self,
-    _user: &DefaultUser,
+    user: &JupyterUser,
     path: P,
     start_pos: u64,
   ) -> Result<Box<dyn AsyncRead + Send + Sync + Unpin>, Error> {
-    let target = normalize_request_path(path);
+    let target = self.resolve(path);
     debug!(%target, start = start_pos, "FTP file read requested");
     let download = self
-      .fs
+      .fs_for(user)
       .download_reader_from(&target, start_pos)
       .await
       .map_err(map_fs_error)?;
+    self.observe(&target, download.entry.last_modified);
     Ok(download.reader)
   }
 
   async fn put<P, R>(
     &self,
-    _user: &DefaultUser,
-    mut input: R,
+    user: &JupyterUser,
+    input: R,
     path: P,
     start_pos: u64,
   ) -> Result<u64, Error>
@@ -99,74 +554,79 @@ impl StorageBackend<DefaultUser> for FsStorage {
     P: AsRef<Path> + Send + fmt::Debug,
     R: AsyncRead + Send + Sync + Unpin + 'static,
   {
-    if start_pos != 0 {
-      return Err(Error::from(ErrorKind::CommandNotImplemented));
-    }
-    let target = normalize_request_path(path);
+    self.check_writable("STOR")?;
+    let target = self.resolve(path);
     debug!(%target, start = start_pos, "FTP file write requested");
-    let mut buffer = Vec::new();
-    input
-      .read_to_end(&mut buffer)
-      .await
-      .map_err(|err| Error::new(ErrorKind::LocalError, err))?;
-    let size = buffer.len() as u64;
-    self.fs.upload(&target, buffer).await.map_err(map_fs_error)?;
+    if let Some(expected) = self.observed.get(target.as_str()) {
+      self.fs_for(user).check_unmodified(&target, expected).await.map_err(map_fs_error)?;
+    }
+    let written = if start_pos == 0 {
+      self.store(user, &target, input).await
+    } else {
+      self.write_at(user, &target, input, start_pos).await
+    };
+    let (entry, size) = written.map_err(map_fs_error)?;
+    self.observe(&target, entry.last_modified);
     debug!(%target, bytes = size, "FTP file write completed");
     Ok(size)
   }
 
   async fn del<P: AsRef<Path> + Send + fmt::Debug>(
     &self,
-    _user: &DefaultUser,
+    user: &JupyterUser,
     path: P,
   ) -> Result<(), Error> {
-    let target = normalize_request_path(path);
+    self.check_writable("DELE")?;
+    let target = self.resolve(path);
     debug!(%target, "FTP delete requested");
-    self.fs.rm(&target).await.map_err(map_fs_error)
+    self.fs_for(user).rm(&target).await.map_err(map_fs_error)
   }
 
   async fn mkd<P: AsRef<Path> + Send + fmt::Debug>(
     &self,
-    _user: &DefaultUser,
+    user: &JupyterUser,
     path: P,
   ) -> Result<(), Error> {
-    let target = normalize_request_path(path);
+    self.check_writable("MKD")?;
+    let target = self.resolve(path);
     debug!(%target, "FTP mkdir requested");
-    self.fs.mkdir(&target).await.map_err(map_fs_error)?;
+    self.fs_for(user).mkdir(&target).await.map_err(map_fs_error)?;
     Ok(())
   }
 
   async fn rename<P: AsRef<Path> + Send + fmt::Debug>(
     &self,
-    _user: &DefaultUser,
+    user: &JupyterUser,
     from: P,
     to: P,
   ) -> Result<(), Error> {
-    let source = normalize_request_path(from);
-    let dest = normalize_request_path(to);
+    self.check_writable("RNFR")?;
+    let source = self.resolve(from);
+    let dest = self.resolve(to);
     debug!(source = %source, dest = %dest, "FTP rename requested");
-    self.fs.rename(&source, &dest).await.map_err(map_fs_error)?;
+    self.fs_for(user).rename(&source, &dest).await.map_err(map_fs_error)?;
     Ok(())
   }
 
   async fn rmd<P: AsRef<Path> + Send + fmt::Debug>(
     &self,
-    _user: &DefaultUser,
+    user: &JupyterUser,
     path: P,
   ) -> Result<(), Error> {
-    let target = normalize_request_path(path);
-    debug!(%target, "FTP rmdir requested");
-    self.fs.rmdir(&target, false).await.map_err(map_fs_error)
+    self.check_writable("RMD")?;
+    let target = self.resolve(path);
+    debug!(%target, recursive = self.recursive_rmdir, "FTP rmdir requested");
+    self.fs_for(user).rmdir(&target, self.recursive_rmdir).await.map_err(map_fs_error)
   }
 
   async fn cwd<P: AsRef<Path> + Send + fmt::Debug>(
     &self,
-    _user: &DefaultUser,
+    user: &JupyterUser,
     path: P,
   ) -> Result<(), Error> {
-    let target = normalize_request_path(path);
+    let target = self.resolve(path);
     trace!(%target, "FTP cwd validation");
-    let entry = self.fs.metadata(&target).await.map_err(map_fs_error)?;
+    let entry = self.fs_for(user).metadata(&target).await.map_err(map_fs_error)?;
     if entry.kind.is_directory() {
       Ok(())
     } else {
@@ -178,17 +638,29 @@ impl StorageBackend<DefaultUser> for FsStorage {
 #[derive(Clone)]
 pub struct FsMetadata {
   entry: Entry,
+  /// Real file system metadata from the shell fallback, when available.
+  stat: Option<RemoteStat>,
+  symlink_target: Option<PathBuf>,
 }
 
 impl From<Entry> for FsMetadata {
   fn from(entry: Entry) -> Self {
-    Self { entry }
+    Self { entry, stat: None, symlink_target: None }
   }
 }
 
 impl FsMetadata {
+  fn with_stat(mut self, stat: RemoteStat) -> Self {
+    self.symlink_target = stat.symlink_target.as_ref().map(PathBuf::from);
+    self.stat = Some(stat);
+    self
+  }
+
   fn is_directory(&self) -> bool {
-    matches!(self.entry.kind, EntryKind::Directory)
+    match &self.stat {
+      Some(stat) => stat.kind == RemoteFileType::Directory,
+      None => matches!(self.entry.kind, EntryKind::Directory),
+    }
   }
 }
 
@@ -206,7 +678,7 @@ impl Metadata for FsMetadata {
   }
 
   fn is_symlink(&self) -> bool {
-    false
+    self.stat.as_ref().is_some_and(|stat| stat.kind == RemoteFileType::Symlink)
   }
 
   fn modified(&self) -> Result<SystemTime, Error> {
@@ -220,26 +692,26 @@ impl Metadata for FsMetadata {
   }
 
   fn gid(&self) -> u32 {
-    0
+    self.stat.as_ref().map_or(0, |stat| stat.gid)
   }
 
   fn uid(&self) -> u32 {
-    0
+    self.stat.as_ref().map_or(0, |stat| stat.uid)
+  }
+
+  fn readlink(&self) -> Option<&Path> {
+    self.symlink_target.as_deref()
   }
 
   fn permissions(&self) -> Permissions {
+    if let Some(stat) = &self.stat {
+      return Permissions(stat.mode);
+    }
     let writable_bits = if self.entry.writable { 0o755 } else { 0o555 };
     Permissions(writable_bits)
   }
 }
 
-fn entry_to_fileinfo(entry: Entry) -> Fileinfo<PathBuf, FsMetadata> {
-  Fileinfo {
-    path: absolute_entry_path(&entry.path),
-    metadata: FsMetadata::from(entry),
-  }
-}
-
 fn absolute_entry_path(raw: &str) -> PathBuf {
   if raw.is_empty() {
     PathBuf::from("/")
@@ -286,9 +758,16 @@ fn map_fs_error(err: FsError) -> Error {
     FsError::Client(e) => map_client_error(e),
     FsError::NotAFile(_) => Error::from(ErrorKind::PermanentFileNotAvailable),
     FsError::NotADirectory(_) => Error::from(ErrorKind::PermanentDirectoryNotAvailable),
-    FsError::MissingContent(_) | FsError::InvalidPayload(_) => Error::new(ErrorKind::LocalError, err),
+    FsError::MissingContent(_) | FsError::InvalidPayload(_) | FsError::ChecksumMismatch { .. } => {
+      Error::new(ErrorKind::LocalError, err)
+    }
     FsError::Decode(inner) => Error::new(ErrorKind::LocalError, inner),
     FsError::NotImplemented(feature) => Error::new(ErrorKind::CommandNotImplemented, feature),
+    FsError::NoCheckpoint(_) => Error::new(ErrorKind::PermanentFileNotAvailable, err),
+    FsError::HiddenNotAllowed(_) => Error::new(ErrorKind::PermissionDenied, err),
+    FsError::Conflict { .. } => Error::new(ErrorKind::TransientFileNotAvailable, err),
+    FsError::Terminal(_) | FsError::Io(_) => Error::new(ErrorKind::LocalError, err),
+    FsError::Command { .. } => Error::new(ErrorKind::PermanentFileNotAvailable, err),
   }
 }
 
@@ -320,6 +799,56 @@ mod tests {
     assert_eq!(normalize_request_path("../../nested"), "/nested");
   }
 
+  fn storage() -> FsStorage {
+    FsStorage::new(FsService::new(Arc::new(crate::api::client::tests::_setup_client())))
+  }
+
+  #[test]
+  fn root_confines_request_paths() {
+    let storage = storage().root("projects/demo/");
+    assert_eq!(storage.resolve("/"), "/projects/demo");
+    assert_eq!(storage.resolve("data/a.csv"), "/projects/demo/data/a.csv");
+    assert_eq!(storage.resolve("/../../etc/passwd"), "/projects/demo/etc/passwd");
+    assert_eq!(storage.client_path("projects/demo/data/a.csv"), PathBuf::from("/data/a.csv"));
+    assert_eq!(storage.client_path("projects/demo"), PathBuf::from("/"));
+
+    let unrooted = storage.root("/");
+    assert_eq!(unrooted.resolve("data"), "/data");
+    assert_eq!(unrooted.client_path("data"), PathBuf::from("/data"));
+  }
+
+  #[tokio::test]
+  async fn read_only_rejects_writes() {
+    let storage = storage().read_only(true);
+    let user = JupyterUser { username: "reader".into(), fs: None };
+    let err = storage.mkd(&user, "new_dir").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    let err = storage.put(&user, &b"data"[..], "file.txt", 0).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    let err = storage.rename(&user, "a", "b").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+  }
+
+  #[tokio::test]
+  async fn session_limit_reserves_slots_during_login() {
+    let limit = SessionLimit::new(1);
+    let authenticator = LimitedAuthenticator {
+      inner: FtpAuthenticator::Password { username: "user".into(), password: "secret".into() },
+      limit: limit.clone(),
+    };
+    assert!(authenticator.authenticate("user", &"wrong".into()).await.is_err());
+    authenticator.authenticate("user", &"secret".into()).await.unwrap();
+    // Accepted but not yet reported as logged in, the login still holds the only slot.
+    assert!(!limit.reserve());
+
+    let meta = EventMeta { username: "user".into(), trace_id: "session".into(), sequence_number: 1 };
+    limit.receive_presence_event(PresenceEvent::LoggedIn, meta.clone()).await;
+    assert_eq!(limit.active(), 1);
+    assert!(authenticator.authenticate("user", &"secret".into()).await.is_err());
+    limit.receive_presence_event(PresenceEvent::LoggedOut, meta).await;
+    authenticator.authenticate("user", &"secret".into()).await.unwrap();
+  }
+
   #[test]
   fn metadata_reflects_entry_kind() {
     let entry = Entry {
@@ -0,0 +1,49 @@
+//! Small HTTP helpers shared by the servers that front the Contents API.
+
+use chrono::{DateTime, Utc};
+
+/// Parse a single `bytes=` range against an object of `size` bytes into inclusive bounds.
+///
+/// Unsupported forms (several ranges, other units) select the whole object, which
+/// RFC 9110 allows a server to do; `Err` means the range is not satisfiable.
+pub(crate) fn parse_range(value: &str, size: u64) -> Result<Option<(u64, u64)>, ()> {
+  let Some(spec) = value.trim().strip_prefix("bytes=") else { return Ok(None) };
+  if spec.contains(',') {
+    return Ok(None);
+  }
+  let Some((start, end)) = spec.split_once('-') else { return Ok(None) };
+  let (start, end) = (start.trim(), end.trim());
+  let bounds = match (start.parse::<u64>(), end.parse::<u64>()) {
+    (Ok(start), Ok(end)) if start <= end => (start, end.min(size.saturating_sub(1))),
+    (Ok(start), Err(_)) if end.is_empty() => (start, size.saturating_sub(1)),
+    (Err(_), Ok(suffix)) if start.is_empty() && suffix > 0 => (size.saturating_sub(suffix), size.saturating_sub(1)),
+    _ => return Ok(None),
+  };
+  if size == 0 || bounds.0 >= size {
+    return Err(());
+  }
+  Ok(Some(bounds))
+}
+
+/// Format `time` as an IMF-fixdate, as used by `Last-Modified`.
+pub(crate) fn http_date(time: DateTime<Utc>) -> String {
+  time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn ranges_follow_http_semantics() {
+    assert_eq!(parse_range("bytes=0-9", 100), Ok(Some((0, 9))));
+    assert_eq!(parse_range("bytes=90-200", 100), Ok(Some((90, 99))));
+    assert_eq!(parse_range("bytes=10-", 100), Ok(Some((10, 99))));
+    assert_eq!(parse_range("bytes=-10", 100), Ok(Some((90, 99))));
+    assert_eq!(parse_range("bytes=-500", 100), Ok(Some((0, 99))));
+    assert_eq!(parse_range("bytes=0-1,5-6", 100), Ok(None));
+    assert_eq!(parse_range("items=0-1", 100), Ok(None));
+    assert_eq!(parse_range("bytes=100-", 100), Err(()));
+    assert_eq!(parse_range("bytes=0-0", 0), Err(()));
+  }
+}
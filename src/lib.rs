@@ -2,9 +2,21 @@
 extern crate tracing;
 
 pub mod api;
+#[cfg(any(feature = "s3", feature = "serve"))]
+mod http_util;
 pub mod services;
+#[cfg(feature = "s3")]
+pub mod s3;
 #[cfg(feature = "ftp")]
 pub mod ftp;
+#[cfg(feature = "serve")]
+pub mod serve;
+#[cfg(feature = "sftp")]
+pub mod sftp;
+#[cfg(feature = "sshd")]
+pub mod sshd;
+#[cfg(feature = "webdav")]
+pub mod webdav;
 pub mod state;
 
-pub use services::{fs, terminal};
+pub use services::{bulk, fs, search, shell, terminal, walk};
@@ -9,6 +9,8 @@ fn init_tracing() -> anyhow::Result<()> {
   let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug,libunftp=warn,reqwest=info,hyper_util=warn"));
   tracing_subscriber::fmt()
     .with_env_filter(filter)
+    // Keep stdout clean for protocols that use it, such as the SFTP subsystem.
+    .with_writer(std::io::stderr)
     // .with_target(false)
     .compact()
     .try_init()
@@ -24,20 +26,60 @@ async fn main() -> anyhow::Result<()> {
   match cli.command {
     #[cfg(feature = "ftp")]
     cli::Command::Ftp(args) => {
-      cli::ftp::run(args)
+      cli::ftp::run(*args)
         .await
         .context("FTP server exited with an error")?
     }
+    #[cfg(feature = "s3")]
+    cli::Command::S3(args) => {
+      cli::s3::run(args)
+        .await
+        .context("S3 gateway exited with an error")?
+    }
     cli::Command::Scp(args) => {
       cli::scp::run(args)
         .await
         .context("SCP command exited with an error")?
     }
+    #[cfg(feature = "serve")]
+    cli::Command::Serve(args) => {
+      cli::serve::run(args)
+        .await
+        .context("HTTP server exited with an error")?
+    }
+    #[cfg(feature = "sftp")]
+    cli::Command::Sftp(args) => {
+      cli::sftp::run(args)
+        .await
+        .context("SFTP server exited with an error")?
+    }
     cli::Command::Ssh(args) => {
       cli::ssh::run(args)
         .await
         .context("SSH command exited with an error")?
     }
+    #[cfg(feature = "sshd")]
+    cli::Command::Sshd(args) => {
+      cli::sshd::run(args)
+        .await
+        .context("SSH server exited with an error")?
+    }
+    #[cfg(feature = "webdav")]
+    cli::Command::Webdav(args) => {
+      cli::webdav::run(args)
+        .await
+        .context("WebDAV server exited with an error")?
+    }
+    cli::Command::Checkpoint(args) => {
+      cli::checkpoint::run(args)
+        .await
+        .context("checkpoint command exited with an error")?
+    }
+    cli::Command::Du(args) => {
+      cli::du::run(args)
+        .await
+        .context("du command exited with an error")?
+    }
   }
   Ok(())
 }
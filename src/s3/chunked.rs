@@ -0,0 +1,166 @@
+//! Decoder for `aws-chunked` request bodies.
+//!
+//! SDKs send streaming uploads as `<hex size>[;chunk-signature=<sig>]\r\n<data>\r\n`
+//! chunks ending with a zero-sized chunk and optional trailer headers. Chunk
+//! signatures are verified as the data arrives; trailers are ignored.
+
+use bytes::{Buf, Bytes, BytesMut};
+use sha2::{Digest, Sha256};
+
+use super::sigv4::ChunkSigner;
+
+/// Longest chunk header or trailer line accepted.
+const MAX_LINE: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ChunkError {
+  Malformed(&'static str),
+  SignatureMismatch,
+}
+
+enum State {
+  Header,
+  Data { remaining: u64, signature: Option<String> },
+  DataEnd { signature: Option<String> },
+  Trailer,
+  Done,
+}
+
+pub(crate) struct ChunkedDecoder {
+  buf: BytesMut,
+  state: State,
+  signer: Option<ChunkSigner>,
+  hasher: Sha256,
+}
+
+impl ChunkedDecoder {
+  /// Decoder for a body whose chunks must be signed by `signer`, or unsigned chunks when `None`.
+  pub fn new(signer: Option<ChunkSigner>) -> Self {
+    Self { buf: BytesMut::new(), state: State::Header, signer, hasher: Sha256::new() }
+  }
+
+  /// Feed raw body bytes and collect the decoded data that is complete so far into `out`.
+  pub fn push(&mut self, input: &[u8], out: &mut Vec<Bytes>) -> Result<(), ChunkError> {
+    self.buf.extend_from_slice(input);
+    loop {
+      match &mut self.state {
+        State::Header => {
+          let Some(line) = self.take_line()? else { return Ok(()) };
+          let (size, extensions) = line.split_once(';').unwrap_or((&line, ""));
+          let size = u64::from_str_radix(size.trim(), 16).map_err(|_| ChunkError::Malformed("invalid chunk size"))?;
+          let signature = extensions
+            .split(';')
+            .find_map(|extension| extension.trim().strip_prefix("chunk-signature="))
+            .map(str::to_string);
+          if self.signer.is_some() && signature.is_none() {
+            return Err(ChunkError::Malformed("missing chunk signature"));
+          }
+          if size == 0 {
+            self.verify_chunk(signature.as_deref())?;
+            self.state = State::Trailer;
+          } else {
+            self.state = State::Data { remaining: size, signature };
+          }
+        }
+        State::Data { remaining, signature } => {
+          if self.buf.is_empty() {
+            return Ok(());
+          }
+          let take = (*remaining).min(self.buf.len() as u64) as usize;
+          let data = self.buf.split_to(take).freeze();
+          self.hasher.update(&data);
+          out.push(data);
+          *remaining -= take as u64;
+          if *remaining == 0 {
+            self.state = State::DataEnd { signature: signature.take() };
+          }
+        }
+        State::DataEnd { signature } => {
+          if self.buf.len() < 2 {
+            return Ok(());
+          }
+          if &self.buf[..2] != b"\r\n" {
+            return Err(ChunkError::Malformed("chunk data is not followed by CRLF"));
+          }
+          self.buf.advance(2);
+          let signature = signature.take();
+          self.verify_chunk(signature.as_deref())?;
+          self.state = State::Header;
+        }
+        State::Trailer => {
+          let Some(line) = self.take_line()? else { return Ok(()) };
+          if line.is_empty() {
+            self.state = State::Done;
+          }
+        }
+        State::Done => {
+          self.buf.clear();
+          return Ok(());
+        }
+      }
+    }
+  }
+
+  /// Check that the body ended after the final chunk.
+  pub fn finish(&self) -> Result<(), ChunkError> {
+    match self.state {
+      // Some clients omit the blank line after the last chunk when there are no trailers.
+      State::Trailer | State::Done => Ok(()),
+      _ => Err(ChunkError::Malformed("body ended inside a chunk")),
+    }
+  }
+
+  fn take_line(&mut self) -> Result<Option<String>, ChunkError> {
+    let Some(end) = self.buf.windows(2).position(|pair| pair == b"\r\n") else {
+      if self.buf.len() > MAX_LINE {
+        return Err(ChunkError::Malformed("chunk header too long"));
+      }
+      return Ok(None);
+    };
+    let line = self.buf.split_to(end + 2);
+    String::from_utf8(line[..end].to_vec())
+      .map(Some)
+      .map_err(|_| ChunkError::Malformed("chunk header is not UTF-8"))
+  }
+
+  fn verify_chunk(&mut self, signature: Option<&str>) -> Result<(), ChunkError> {
+    let hash = format!("{:x}", std::mem::take(&mut self.hasher).finalize());
+    if let (Some(signer), Some(signature)) = (&mut self.signer, signature)
+      && !signer.verify_next(&hash, signature)
+    {
+      return Err(ChunkError::SignatureMismatch);
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn decode(body: &[u8], split: usize) -> Result<Vec<u8>, ChunkError> {
+    let mut decoder = ChunkedDecoder::new(None);
+    let mut out = Vec::new();
+    for piece in body.chunks(split) {
+      decoder.push(piece, &mut out)?;
+    }
+    decoder.finish()?;
+    Ok(out.concat())
+  }
+
+  #[test]
+  fn decodes_unsigned_chunks_with_trailer() {
+    let body = b"5\r\nhello\r\n6\r\n world\r\n0\r\nx-amz-checksum-crc32:DUoRhQ==\r\n\r\n";
+    for split in [1, 3, 7, body.len()] {
+      assert_eq!(decode(body, split).unwrap(), b"hello world");
+    }
+    assert_eq!(decode(b"0\r\n", 64).unwrap(), b"");
+  }
+
+  #[test]
+  fn rejects_truncated_or_malformed_bodies() {
+    assert_eq!(decode(b"5\r\nhel", 64), Err(ChunkError::Malformed("body ended inside a chunk")));
+    assert_eq!(decode(b"5\r\nhelloXX0\r\n\r\n", 64), Err(ChunkError::Malformed("chunk data is not followed by CRLF")));
+    assert_eq!(decode(b"zz\r\n", 64), Err(ChunkError::Malformed("invalid chunk size")));
+  }
+}
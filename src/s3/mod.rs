@@ -0,0 +1,1116 @@
+//! S3-compatible gateway over the Jupyter Contents API.
+//!
+//! One bucket is served, mapped onto a remote directory; object keys are paths
+//! below it and `/` in a key is a directory separator. Requests must be signed
+//! with SigV4 using one of the configured [`Credentials`].
+
+use std::{
+  collections::{BTreeMap, HashMap},
+  fmt, io,
+  sync::{
+    Arc, Weak,
+    atomic::{AtomicUsize, Ordering},
+  },
+  time::Duration,
+};
+
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use bytes::{Buf, Bytes};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::{StreamExt, TryStreamExt, stream};
+use http_body_util::{BodyExt, Empty, Full, StreamBody, combinators::BoxBody};
+use hyper::{
+  HeaderMap, Method, Request, Response, StatusCode,
+  body::{Body, Frame},
+  header::{self, HeaderValue},
+};
+use parking_lot::Mutex;
+use reqwest::StatusCode as ApiStatus;
+use sha2::{Digest, Sha256};
+use tokio::{io::AsyncReadExt, time::Instant};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::{
+  api::client::ClientError,
+  fs::{Entry, FsError, FsService, UploadPipe},
+  http_util::{http_date, parse_range},
+};
+
+mod chunked;
+mod sigv4;
+mod xml;
+
+use chunked::{ChunkError, ChunkedDecoder};
+pub use sigv4::Credentials;
+use sigv4::{AuthError, Signed};
+use xml::XmlWriter;
+
+/// Bytes buffered per Contents API save while an object is uploaded.
+const UPLOAD_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+/// Most keys returned by one listing page, as on AWS.
+const MAX_KEYS: usize = 1000;
+/// Largest multipart part accepted; parts are held in memory until they can be
+/// appended in order.
+const MAX_PART_SIZE: usize = 512 * 1024 * 1024;
+/// Most bytes held across all multipart uploads in parts waiting for their predecessors.
+const MAX_PARKED_BYTES: usize = 1024 * 1024 * 1024;
+/// Multipart uploads receiving no part for this long are aborted.
+const MULTIPART_IDLE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+/// Largest XML request body accepted (`CompleteMultipartUpload`, `DeleteObjects`).
+const MAX_XML_BODY: usize = 4 * 1024 * 1024;
+/// `DeleteObjects` keys removed concurrently.
+const DELETE_CONCURRENCY: usize = 8;
+
+/// Response body produced by [`S3Gateway::handle`].
+pub type S3Body = BoxBody<Bytes, io::Error>;
+
+/// Serves one bucket backed by a [`FsService`] over the S3 REST API.
+///
+/// Supported operations are ListObjects (v1 and v2), GetObject with `Range`,
+/// HeadObject, PutObject, CopyObject, DeleteObject, DeleteObjects and multipart
+/// uploads. Multipart parts are appended to the file in part-number order through
+/// chunked saves, so parts that arrive early wait in memory for their predecessors,
+/// up to [`MAX_PARKED_BYTES`] in total. Uploads idle for [`MULTIPART_IDLE_TIMEOUT`]
+/// are aborted.
+#[derive(Clone)]
+pub struct S3Gateway {
+  fs: FsService,
+  bucket: String,
+  root: String,
+  credentials: Arc<Credentials>,
+  read_only: bool,
+  uploads: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<MultipartUpload>>>>>,
+  parked_bytes: Arc<AtomicUsize>,
+}
+
+impl S3Gateway {
+  pub fn new(fs: FsService, bucket: impl Into<String>, credentials: Credentials) -> Self {
+    Self {
+      fs,
+      bucket: bucket.into(),
+      root: String::new(),
+      credentials: Arc::new(credentials),
+      read_only: false,
+      uploads: Arc::default(),
+      parked_bytes: Arc::default(),
+    }
+  }
+
+  /// Serve the remote directory `root` as the bucket instead of the server root.
+  pub fn root(mut self, root: impl AsRef<str>) -> Self {
+    self.root = root.as_ref().trim_matches('/').to_string();
+    self
+  }
+
+  /// Reject every request that would modify the bucket.
+  pub fn read_only(mut self, enabled: bool) -> Self {
+    self.read_only = enabled;
+    self
+  }
+
+  /// Answer one S3 request.
+  pub async fn handle<B>(&self, req: Request<B>) -> Response<S3Body>
+  where
+    B: Body + Unpin,
+    B::Error: fmt::Display,
+  {
+    let request_id = Uuid::new_v4().simple().to_string();
+    let method = req.method().clone();
+    let resource = req.uri().path().to_string();
+    let mut response = match self.dispatch(req).await {
+      Ok(response) => response,
+      Err(err) => {
+        debug!(%method, %resource, code = err.code, message = %err.message, "S3 request failed");
+        err.into_response(&resource, &request_id, method == Method::HEAD)
+      }
+    };
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+      response.headers_mut().insert("x-amz-request-id", value);
+    }
+    response
+  }
+
+  async fn dispatch<B>(&self, req: Request<B>) -> Result<Response<S3Body>, S3Error>
+  where
+    B: Body + Unpin,
+    B::Error: fmt::Display,
+  {
+    let (parts, body) = req.into_parts();
+    let signed = sigv4::verify(&self.credentials, &parts.method, &parts.uri, &parts.headers, Utc::now())?;
+    let query = sigv4::parse_query(parts.uri.query().unwrap_or(""));
+    let param = |name: &str| query.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str());
+    let (bucket, key) = self.locate(parts.uri.path(), &parts.headers)?;
+    trace!(method = %parts.method, ?bucket, %key, access_key = %signed.access_key, "S3 request");
+
+    let Some(bucket) = bucket else {
+      return match parts.method {
+        Method::GET => Ok(self.list_buckets()),
+        _ => Err(S3Error::not_implemented("only ListBuckets is supported on the service endpoint")),
+      };
+    };
+    if bucket != self.bucket {
+      return Err(S3Error::new(StatusCode::NOT_FOUND, "NoSuchBucket", format!("bucket {bucket} does not exist")));
+    }
+    if self.read_only && !matches!(parts.method, Method::GET | Method::HEAD) {
+      return Err(S3Error::access_denied("the bucket is read-only"));
+    }
+    let payload = || Payload::new(body, &signed);
+
+    if key.is_empty() {
+      return match parts.method {
+        Method::GET if param("location").is_some() => Ok(self.bucket_location()),
+        Method::GET if param("uploads").is_some() => Err(S3Error::not_implemented("ListMultipartUploads")),
+        Method::GET => self.list_objects(&query).await,
+        Method::HEAD => Ok(empty_response(StatusCode::OK)),
+        Method::POST if param("delete").is_some() => self.delete_objects(payload()?).await,
+        _ => Err(S3Error::not_implemented("creating, deleting or configuring buckets")),
+      };
+    }
+
+    let path = self.object_path(&key)?;
+    match (parts.method, param("uploadId")) {
+      (Method::GET, _) => self.get_object(&path, &parts.headers, false).await,
+      (Method::HEAD, _) => self.get_object(&path, &parts.headers, true).await,
+      (Method::PUT, Some(upload_id)) => {
+        let part_number = param("partNumber")
+          .and_then(|number| number.parse().ok())
+          .filter(|number| (1..=10_000).contains(number))
+          .ok_or_else(|| S3Error::invalid_argument("partNumber must be between 1 and 10000"))?;
+        self.upload_part(&key, upload_id, part_number, payload()?).await
+      }
+      (Method::PUT, None) if parts.headers.contains_key("x-amz-copy-source") => {
+        self.copy_object(&path, &parts.headers).await
+      }
+      (Method::PUT, None) => self.put_object(&path, key.ends_with('/'), payload()?).await,
+      (Method::POST, None) if param("uploads").is_some() => self.create_multipart_upload(&key, &path).await,
+      (Method::POST, Some(upload_id)) => self.complete_multipart_upload(&key, upload_id, payload()?).await,
+      (Method::DELETE, Some(upload_id)) => self.abort_multipart_upload(&key, upload_id),
+      (Method::DELETE, None) => self.delete_object(&path).await.map(|()| empty_response(StatusCode::NO_CONTENT)),
+      _ => Err(S3Error::new(StatusCode::METHOD_NOT_ALLOWED, "MethodNotAllowed", "method not allowed on an object")),
+    }
+  }
+
+  /// Split a request into bucket and key, accepting path-style and virtual-hosted-style addressing.
+  fn locate(&self, raw_path: &str, headers: &HeaderMap) -> Result<(Option<String>, String), S3Error> {
+    let path = percent_encoding::percent_decode_str(raw_path)
+      .decode_utf8()
+      .map_err(|_| S3Error::new(StatusCode::BAD_REQUEST, "InvalidURI", "object keys must be UTF-8"))?;
+    let path = path.strip_prefix('/').unwrap_or(&path);
+    let host = headers.get(header::HOST).and_then(|host| host.to_str().ok()).unwrap_or("");
+    if host.strip_prefix(self.bucket.as_str()).is_some_and(|rest| rest.starts_with('.')) {
+      return Ok((Some(self.bucket.clone()), path.to_string()));
+    }
+    Ok(match path.split_once('/') {
+      Some((bucket, key)) => (Some(bucket.to_string()), key.to_string()),
+      None if path.is_empty() => (None, String::new()),
+      None => (Some(path.to_string()), String::new()),
+    })
+  }
+
+  /// Jupyter path of the object `key`; a trailing `/` names a directory.
+  fn object_path(&self, key: &str) -> Result<String, S3Error> {
+    let trimmed = key.strip_suffix('/').unwrap_or(key);
+    if trimmed.is_empty() || trimmed.split('/').any(|segment| matches!(segment, "" | "." | "..")) {
+      return Err(S3Error::invalid_argument(format!("object key {key:?} is not a valid path")));
+    }
+    Ok(self.join(trimmed))
+  }
+
+  fn join(&self, relative: &str) -> String {
+    match (self.root.is_empty(), relative.is_empty()) {
+      (true, _) => relative.to_string(),
+      (false, true) => self.root.clone(),
+      (false, false) => format!("{}/{relative}", self.root),
+    }
+  }
+
+  /// Object key of the Jupyter path `path`, if it lies below the bucket root.
+  fn key_of(&self, path: &str) -> Option<String> {
+    let path = path.trim_start_matches('/');
+    if self.root.is_empty() {
+      return Some(path.to_string());
+    }
+    path.strip_prefix(self.root.as_str())?.strip_prefix('/').map(str::to_string)
+  }
+
+  fn list_buckets(&self) -> Response<S3Body> {
+    let mut doc = XmlWriter::new("ListAllMyBucketsResult");
+    doc.open("Owner").element("ID", "jupyter").element("DisplayName", "jupyter").close("Owner");
+    doc.open("Buckets").open("Bucket").element("Name", &self.bucket);
+    doc.element("CreationDate", "1970-01-01T00:00:00.000Z");
+    doc.close("Bucket").close("Buckets");
+    xml_response(StatusCode::OK, doc.finish("ListAllMyBucketsResult"))
+  }
+
+  fn bucket_location(&self) -> Response<S3Body> {
+    let doc = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+      <LocationConstraint xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\"></LocationConstraint>";
+    xml_response(StatusCode::OK, doc.to_string())
+  }
+
+  async fn list_objects(&self, query: &[(String, String)]) -> Result<Response<S3Body>, S3Error> {
+    let param = |name: &str| query.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str());
+    let v2 = param("list-type") == Some("2");
+    let prefix = param("prefix").unwrap_or("");
+    let delimiter = param("delimiter").filter(|delimiter| !delimiter.is_empty());
+    let url_encoded = param("encoding-type") == Some("url");
+    let max_keys = match param("max-keys") {
+      Some(value) => value.parse::<usize>().map_err(|_| S3Error::invalid_argument("max-keys must be a number"))?,
+      None => MAX_KEYS,
+    }
+    .min(MAX_KEYS);
+    let continuation = match param("continuation-token").filter(|_| v2) {
+      Some(token) => Some(decode_continuation(token)?),
+      None => None,
+    };
+    let after = match (&continuation, v2) {
+      (Some(key), _) => Some(key.as_str()),
+      (None, true) => param("start-after"),
+      (None, false) => param("marker"),
+    }
+    .filter(|after| !after.is_empty());
+
+    let mut page = self.collect_listing(prefix, delimiter, after, max_keys + 1).await?;
+    let truncated = page.len() > max_keys;
+    page.truncate(max_keys);
+    let encode = |value: &str| if url_encoded { sigv4::encode_path(value) } else { value.to_string() };
+
+    let mut doc = XmlWriter::new("ListBucketResult");
+    doc.element("Name", &self.bucket).element("Prefix", encode(prefix));
+    if let Some(delimiter) = delimiter {
+      doc.element("Delimiter", encode(delimiter));
+    }
+    doc.element("MaxKeys", max_keys.to_string());
+    if url_encoded {
+      doc.element("EncodingType", "url");
+    }
+    doc.element("IsTruncated", truncated.to_string());
+    let last = page.last().map(|item| item.key().to_string());
+    if v2 {
+      doc.element("KeyCount", page.len().to_string());
+      if let Some(token) = param("continuation-token") {
+        doc.element("ContinuationToken", token);
+      }
+      if let Some(start_after) = param("start-after") {
+        doc.element("StartAfter", encode(start_after));
+      }
+      if let (true, Some(last)) = (truncated, &last) {
+        doc.element("NextContinuationToken", URL_SAFE_NO_PAD.encode(last));
+      }
+    } else {
+      doc.element("Marker", encode(param("marker").unwrap_or("")));
+      if let (true, Some(last)) = (truncated, &last) {
+        doc.element("NextMarker", encode(last));
+      }
+    }
+    for item in &page {
+      match item {
+        Listed::Object(key, entry) => {
+          doc.open("Contents").element("Key", encode(key));
+          doc.element("LastModified", iso_timestamp(entry.last_modified));
+          doc.element("ETag", object_etag(entry));
+          doc.element("Size", entry.size.unwrap_or(0).to_string());
+          doc.element("StorageClass", "STANDARD");
+          doc.close("Contents");
+        }
+        Listed::Prefix(prefix) => {
+          doc.open("CommonPrefixes").element("Prefix", encode(prefix)).close("CommonPrefixes");
+        }
+      }
+    }
+    Ok(xml_response(StatusCode::OK, doc.finish("ListBucketResult")))
+  }
+
+  /// Up to `limit` objects and common prefixes matching `prefix` whose keys sort
+  /// after `after`, in key order.
+  async fn collect_listing(
+    &self,
+    prefix: &str,
+    delimiter: Option<&str>,
+    after: Option<&str>,
+    limit: usize,
+  ) -> Result<Vec<Listed>, S3Error> {
+    // The deepest directory that can contain matching keys.
+    let dir = prefix.rfind('/').map_or("", |slash| &prefix[..slash]);
+    if !dir.is_empty() && dir.split('/').any(|segment| matches!(segment, "" | "." | "..")) {
+      return Ok(Vec::new());
+    }
+    let past = |key: &str| after.is_none_or(|after| key > after);
+    let mut listing = Vec::new();
+    if delimiter == Some("/") {
+      // One directory listing suffices: subdirectories become common prefixes.
+      for entry in self.list_dir(&self.join(dir)).await? {
+        let key = if dir.is_empty() { entry.name.clone() } else { format!("{dir}/{}", entry.name) };
+        if !key.starts_with(prefix) {
+          continue;
+        }
+        listing.push(if entry.kind.is_directory() { Listed::Prefix(format!("{key}/")) } else { Listed::Object(key, entry) });
+      }
+      listing.retain(|item| past(item.key()));
+      listing.sort_by(|a, b| a.key().cmp(b.key()));
+      listing.truncate(limit);
+      return Ok(listing);
+    }
+
+    // Depth first in key order, so the walk can start at `after` and stop once the page is full.
+    // A directory's key ends in `/`, which places it among its siblings where its objects sort.
+    let mut pending = vec![(format!("{dir}/"), None)];
+    while let Some((key, entry)) = pending.pop() {
+      let Some(entry) = entry else {
+        let mut children: Vec<(String, Option<Entry>)> = Vec::new();
+        for entry in self.list_dir(&self.join(key.trim_end_matches('/'))).await? {
+          let Some(key) = self.key_of(&entry.path) else { continue };
+          if entry.kind.is_directory() {
+            let key = format!("{key}/");
+            // Descend only where matching keys past `after` can be.
+            let matches = key.starts_with(prefix) || prefix.starts_with(&key);
+            if matches && (past(&key) || after.is_some_and(|after| after.starts_with(&key))) {
+              children.push((key, None));
+            }
+          } else if key.starts_with(prefix) && past(&key) {
+            children.push((key, Some(entry)));
+          }
+        }
+        children.sort_by(|a, b| b.0.cmp(&a.0));
+        pending.extend(children);
+        continue;
+      };
+      let rolled_up = delimiter.and_then(|delimiter| {
+        let split = key[prefix.len()..].find(delimiter)?;
+        Some(key[..prefix.len() + split + delimiter.len()].to_string())
+      });
+      let item = match rolled_up {
+        Some(common) if !past(&common) => continue,
+        Some(common) => Listed::Prefix(common),
+        None => Listed::Object(key, entry),
+      };
+      if listing.last().is_some_and(|last| last.key() == item.key()) {
+        continue;
+      }
+      listing.push(item);
+      if listing.len() == limit {
+        break;
+      }
+    }
+    Ok(listing)
+  }
+
+  /// The entries of the directory `path`; a missing directory is empty.
+  async fn list_dir(&self, path: &str) -> Result<Vec<Entry>, S3Error> {
+    match self.fs.ls(path).await {
+      Ok(entries) => Ok(entries),
+      Err(err) if err.is_not_found() || matches!(err, FsError::NotADirectory(_)) => Ok(Vec::new()),
+      Err(err) => Err(err.into()),
+    }
+  }
+
+  async fn get_object(&self, path: &str, headers: &HeaderMap, head_only: bool) -> Result<Response<S3Body>, S3Error> {
+    let entry = self.fs.metadata(path).await?;
+    if entry.kind.is_directory() {
+      return Err(S3Error::no_such_key());
+    }
+    let size = entry.size.unwrap_or(0);
+    let range = match headers.get(header::RANGE).and_then(|range| range.to_str().ok()) {
+      Some(range) => parse_range(range, size).map_err(|()| {
+        S3Error::new(StatusCode::RANGE_NOT_SATISFIABLE, "InvalidRange", "the requested range is not satisfiable")
+      })?,
+      None => None,
+    };
+    let (start, length) = range.map_or((0, size), |(start, end)| (start, end - start + 1));
+
+    let mut response = Response::builder()
+      .status(if range.is_some() { StatusCode::PARTIAL_CONTENT } else { StatusCode::OK })
+      .header(header::CONTENT_LENGTH, length)
+      .header(header::CONTENT_TYPE, entry.mimetype.as_deref().unwrap_or("application/octet-stream"))
+      .header(header::ACCEPT_RANGES, "bytes")
+      .header(header::ETAG, object_etag(&entry));
+    if let Some(modified) = entry.last_modified {
+      response = response.header(header::LAST_MODIFIED, http_date(modified));
+    }
+    if let Some((start, end)) = range {
+      response = response.header(header::CONTENT_RANGE, format!("bytes {start}-{end}/{size}"));
+    }
+    let body = if head_only || length == 0 {
+      empty_body()
+    } else {
+      let download = self.fs.download_reader_from(path, start).await?;
+      let stream = ReaderStream::new(download.reader.take(length)).map_ok(Frame::data);
+      BodyExt::boxed(StreamBody::new(stream))
+    };
+    Ok(response.body(body).expect("valid response headers"))
+  }
+
+  async fn put_object<B>(&self, path: &str, directory: bool, mut payload: Payload<B>) -> Result<Response<S3Body>, S3Error>
+  where
+    B: Body + Unpin,
+    B::Error: fmt::Display,
+  {
+    self.ensure_parent(path).await?;
+    if directory {
+      // Keys ending in `/` are "folders" created by S3 consoles and tools; their body is ignored.
+      payload.collect(0).await?;
+      let entry = self.fs.mkdir_all(path).await?;
+      return Ok(etag_response(&entry));
+    }
+    let mut pipe = self.fs.upload_pipe(path, UPLOAD_CHUNK_SIZE);
+    loop {
+      match payload.next().await {
+        Ok(Some(data)) => {
+          if pipe.write(&data).await.is_err() {
+            // The upload task stopped; finish() reports why.
+            break;
+          }
+        }
+        Ok(None) => break,
+        Err(err) => {
+          pipe.abort();
+          return Err(err);
+        }
+      }
+    }
+    let upload = pipe.finish().await?;
+    debug!(path, bytes = upload.bytes, "S3 object stored");
+    Ok(etag_response(&upload.entry))
+  }
+
+  async fn copy_object(&self, path: &str, headers: &HeaderMap) -> Result<Response<S3Body>, S3Error> {
+    let source = headers.get("x-amz-copy-source").and_then(|value| value.to_str().ok()).unwrap_or("");
+    let source = source.split_once("?versionId=").map_or(source, |(source, _)| source);
+    let source = percent_encoding::percent_decode_str(source).decode_utf8_lossy();
+    let (bucket, key) = source
+      .trim_start_matches('/')
+      .split_once('/')
+      .ok_or_else(|| S3Error::invalid_argument("x-amz-copy-source must be bucket/key"))?;
+    if bucket != self.bucket {
+      return Err(S3Error::not_implemented("copying between buckets"));
+    }
+    let from = self.object_path(key)?;
+    let entry = if from == path {
+      self.fs.metadata(path).await?
+    } else {
+      self.ensure_parent(path).await?;
+      self.fs.copy_atomic(&from, path).await?
+    };
+    let mut doc = XmlWriter::new("CopyObjectResult");
+    doc.element("LastModified", iso_timestamp(entry.last_modified)).element("ETag", object_etag(&entry));
+    Ok(xml_response(StatusCode::OK, doc.finish("CopyObjectResult")))
+  }
+
+  /// Delete the object at `path`; missing objects are not an error, as on S3.
+  async fn delete_object(&self, path: &str) -> Result<(), S3Error> {
+    let entry = match self.fs.metadata(path).await {
+      Ok(entry) => entry,
+      Err(err) if err.is_not_found() => return Ok(()),
+      Err(err) => return Err(err.into()),
+    };
+    let result = if entry.kind.is_directory() {
+      // Only empty directories go away, like an S3 folder marker whose objects remain.
+      match self.fs.rmdir(path, false).await {
+        Err(FsError::InvalidPayload(_)) => Ok(()),
+        result => result,
+      }
+    } else {
+      self.fs.rm(path).await
+    };
+    match result {
+      Err(err) if !err.is_not_found() => Err(err.into()),
+      _ => Ok(()),
+    }
+  }
+
+  async fn delete_objects<B>(&self, payload: Payload<B>) -> Result<Response<S3Body>, S3Error>
+  where
+    B: Body + Unpin,
+    B::Error: fmt::Display,
+  {
+    let body = payload.collect(MAX_XML_BODY).await?;
+    let body = String::from_utf8_lossy(&body);
+    let quiet = xml::text(&body, "Quiet").is_some_and(|quiet| quiet.trim() == "true");
+    let keys: Vec<String> = xml::children(&body, "Object").into_iter().filter_map(|object| xml::text(object, "Key")).collect();
+    if keys.len() > MAX_KEYS {
+      return Err(S3Error::new(StatusCode::BAD_REQUEST, "MalformedXML", "at most 1000 keys can be deleted at once"));
+    }
+    let results: Vec<(String, Result<(), S3Error>)> = stream::iter(keys)
+      .map(|key| async move {
+        let result = match self.object_path(&key) {
+          Ok(path) => self.delete_object(&path).await,
+          Err(err) => Err(err),
+        };
+        (key, result)
+      })
+      .buffered(DELETE_CONCURRENCY)
+      .collect()
+      .await;
+
+    let mut doc = XmlWriter::new("DeleteResult");
+    for (key, result) in results {
+      match result {
+        Ok(()) if quiet => {}
+        Ok(()) => {
+          doc.open("Deleted").element("Key", key).close("Deleted");
+        }
+        Err(err) => {
+          doc.open("Error").element("Key", key).element("Code", err.code).element("Message", err.message);
+          doc.close("Error");
+        }
+      }
+    }
+    Ok(xml_response(StatusCode::OK, doc.finish("DeleteResult")))
+  }
+
+  async fn create_multipart_upload(&self, key: &str, path: &str) -> Result<Response<S3Body>, S3Error> {
+    self.ensure_parent(path).await?;
+    let upload_id = Uuid::new_v4().simple().to_string();
+    let upload = MultipartUpload::new(key, self.fs.upload_pipe(path, UPLOAD_CHUNK_SIZE), self.parked_bytes.clone());
+    let upload = Arc::new(tokio::sync::Mutex::new(upload));
+    self.uploads.lock().insert(upload_id.clone(), upload.clone());
+    tokio::spawn(expire_when_idle(Arc::downgrade(&self.uploads), upload_id.clone(), Arc::downgrade(&upload)));
+    debug!(path, %upload_id, "S3 multipart upload started");
+
+    let mut doc = XmlWriter::new("InitiateMultipartUploadResult");
+    doc.element("Bucket", &self.bucket).element("Key", key).element("UploadId", upload_id);
+    Ok(xml_response(StatusCode::OK, doc.finish("InitiateMultipartUploadResult")))
+  }
+
+  /// The upload `upload_id`; callers check its key with [`MultipartUpload::check_key`] once locked.
+  fn find_upload(&self, upload_id: &str) -> Result<Arc<tokio::sync::Mutex<MultipartUpload>>, S3Error> {
+    self.uploads.lock().get(upload_id).cloned().ok_or_else(S3Error::no_such_upload)
+  }
+
+  async fn upload_part<B>(
+    &self,
+    key: &str,
+    upload_id: &str,
+    part_number: u32,
+    payload: Payload<B>,
+  ) -> Result<Response<S3Body>, S3Error>
+  where
+    B: Body + Unpin,
+    B::Error: fmt::Display,
+  {
+    let upload = self.find_upload(upload_id)?;
+    // Buffer the whole part first so a failed or retried request never leaves partial data behind.
+    let data = payload.collect(MAX_PART_SIZE).await?;
+    let etag = part_etag(&data);
+    let mut upload = upload.lock().await;
+    upload.check_key(key)?;
+    if part_number < upload.next_part {
+      // Already appended; only an identical retry can be accepted.
+      return match upload.etags.get(&part_number) {
+        Some(existing) if *existing == etag => Ok(etag_header_response(&etag)),
+        _ => Err(S3Error::new(
+          StatusCode::BAD_REQUEST,
+          "InvalidPart",
+          "parts are appended in order and cannot be replaced once written",
+        )),
+      };
+    }
+    trace!(%upload_id, part_number, bytes = data.len(), "S3 multipart part received");
+    upload.touched = Instant::now();
+    upload.park(part_number, data)?;
+    upload.etags.insert(part_number, etag.clone());
+    upload.drain().await?;
+    upload.touched = Instant::now();
+    Ok(etag_header_response(&etag))
+  }
+
+  async fn complete_multipart_upload<B>(
+    &self,
+    key: &str,
+    upload_id: &str,
+    payload: Payload<B>,
+  ) -> Result<Response<S3Body>, S3Error>
+  where
+    B: Body + Unpin,
+    B::Error: fmt::Display,
+  {
+    let body = payload.collect(MAX_XML_BODY).await?;
+    let body = String::from_utf8_lossy(&body);
+    let requested: Vec<(u32, String)> = xml::children(&body, "Part")
+      .into_iter()
+      .map(|part| {
+        let number = xml::text(part, "PartNumber").and_then(|number| number.trim().parse().ok());
+        let etag = xml::text(part, "ETag").unwrap_or_default();
+        number.map(|number| (number, etag)).ok_or_else(|| S3Error::new(StatusCode::BAD_REQUEST, "MalformedXML", "invalid Part"))
+      })
+      .collect::<Result<_, _>>()?;
+
+    let upload = self.find_upload(upload_id)?;
+    let mut upload = upload.lock().await;
+    upload.check_key(key)?;
+    // Parts were appended as 1, 2, 3, ...; the completed upload must list exactly those.
+    let written: Vec<u32> = (1..upload.next_part).collect();
+    let listed: Vec<u32> = requested.iter().map(|(number, _)| *number).collect();
+    if written.is_empty() || listed != written || !upload.parked.is_empty() {
+      return Err(S3Error::new(
+        StatusCode::BAD_REQUEST,
+        "InvalidPart",
+        "the upload must list every uploaded part, numbered from 1 without gaps",
+      ));
+    }
+    if requested.iter().any(|(number, etag)| upload.etags.get(number).map(|e| e.trim_matches('"')) != Some(etag.trim_matches('"'))) {
+      return Err(S3Error::new(StatusCode::BAD_REQUEST, "InvalidPart", "a part ETag does not match"));
+    }
+    let pipe = upload.pipe.take().ok_or_else(S3Error::no_such_upload)?;
+    self.uploads.lock().remove(upload_id);
+    drop(upload);
+    let done = pipe.finish().await?;
+    debug!(path = %done.entry.path, bytes = done.bytes, parts = listed.len(), "S3 multipart upload completed");
+
+    let mut doc = XmlWriter::new("CompleteMultipartUploadResult");
+    doc.element("Location", format!("/{}/{}", self.bucket, sigv4::encode_path(key)));
+    doc.element("Bucket", &self.bucket).element("Key", key).element("ETag", object_etag(&done.entry));
+    Ok(xml_response(StatusCode::OK, doc.finish("CompleteMultipartUploadResult")))
+  }
+
+  fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> Result<Response<S3Body>, S3Error> {
+    let mut uploads = self.uploads.lock();
+    let upload = uploads.get(upload_id).ok_or_else(S3Error::no_such_upload)?;
+    // A part being appended holds the lock; let it finish and abort on the next request.
+    let mut upload = upload.try_lock().map_err(|_| {
+      S3Error::new(StatusCode::CONFLICT, "OperationAborted", "a part of this upload is still being written; retry")
+    })?;
+    upload.check_key(key)?;
+    upload.discard();
+    drop(upload);
+    uploads.remove(upload_id);
+    debug!(%upload_id, "S3 multipart upload aborted");
+    Ok(empty_response(StatusCode::NO_CONTENT))
+  }
+
+  /// Create the directories above `path`; S3 has no directories, so clients never do.
+  async fn ensure_parent(&self, path: &str) -> Result<(), S3Error> {
+    if let Some((parent, _)) = path.rsplit_once('/') {
+      self.fs.mkdir_all(parent).await?;
+    }
+    Ok(())
+  }
+}
+
+/// An object or a common prefix in a listing.
+enum Listed {
+  Object(String, Entry),
+  Prefix(String),
+}
+
+impl Listed {
+  fn key(&self) -> &str {
+    match self {
+      Listed::Object(key, _) | Listed::Prefix(key) => key,
+    }
+  }
+}
+
+/// Abort the upload once no part has arrived for [`MULTIPART_IDLE_TIMEOUT`].
+async fn expire_when_idle(
+  uploads: Weak<Mutex<HashMap<String, Arc<tokio::sync::Mutex<MultipartUpload>>>>>,
+  upload_id: String,
+  upload: Weak<tokio::sync::Mutex<MultipartUpload>>,
+) {
+  let mut deadline = Instant::now() + MULTIPART_IDLE_TIMEOUT;
+  loop {
+    tokio::time::sleep_until(deadline).await;
+    let Some(upload) = upload.upgrade() else { return };
+    let mut upload = upload.lock().await;
+    if upload.pipe.is_none() {
+      return;
+    }
+    deadline = upload.touched + MULTIPART_IDLE_TIMEOUT;
+    if deadline > Instant::now() {
+      continue;
+    }
+    upload.discard();
+    drop(upload);
+    if let Some(uploads) = uploads.upgrade() {
+      uploads.lock().remove(&upload_id);
+    }
+    debug!(%upload_id, "S3 multipart upload expired");
+    return;
+  }
+}
+
+/// A multipart upload in progress, streamed into one file through chunked saves.
+struct MultipartUpload {
+  key: String,
+  /// `None` once the upload has been completed or aborted.
+  pipe: Option<UploadPipe>,
+  /// The next part number to append.
+  next_part: u32,
+  /// Parts received ahead of `next_part`.
+  parked: BTreeMap<u32, Bytes>,
+  /// Bytes parked across every upload of the gateway.
+  parked_bytes: Arc<AtomicUsize>,
+  etags: BTreeMap<u32, String>,
+  /// When a part last arrived.
+  touched: Instant,
+}
+
+impl MultipartUpload {
+  fn new(key: &str, pipe: UploadPipe, parked_bytes: Arc<AtomicUsize>) -> Self {
+    Self {
+      key: key.to_string(),
+      pipe: Some(pipe),
+      next_part: 1,
+      parked: BTreeMap::new(),
+      parked_bytes,
+      etags: BTreeMap::new(),
+      touched: Instant::now(),
+    }
+  }
+
+  fn check_key(&self, key: &str) -> Result<(), S3Error> {
+    if self.key == key && self.pipe.is_some() { Ok(()) } else { Err(S3Error::no_such_upload()) }
+  }
+
+  /// Hold `data` until it can be appended, within the gateway-wide [`MAX_PARKED_BYTES`].
+  fn park(&mut self, part_number: u32, data: Bytes) -> Result<(), S3Error> {
+    let len = data.len();
+    // The next part is appended right away, so it is never refused.
+    let total = self.parked_bytes.fetch_add(len, Ordering::Relaxed) + len;
+    if part_number > self.next_part && total > MAX_PARKED_BYTES {
+      self.parked_bytes.fetch_sub(len, Ordering::Relaxed);
+      return Err(S3Error::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "SlowDown",
+        "too many parts are waiting for their predecessors; retry, or upload fewer parts at once",
+      ));
+    }
+    if let Some(replaced) = self.parked.insert(part_number, data) {
+      self.parked_bytes.fetch_sub(replaced.len(), Ordering::Relaxed);
+    }
+    Ok(())
+  }
+
+  /// Append every parked part that directly follows what has been written.
+  async fn drain(&mut self) -> Result<(), S3Error> {
+    while let Some(data) = self.parked.remove(&self.next_part) {
+      self.parked_bytes.fetch_sub(data.len(), Ordering::Relaxed);
+      let pipe = self.pipe.as_mut().ok_or_else(S3Error::no_such_upload)?;
+      if pipe.write(&data).await.is_err() {
+        // The upload task failed; report its error and drop the upload.
+        let err = match self.pipe.take().map(UploadPipe::finish) {
+          Some(finish) => finish.await.err().map(S3Error::from),
+          None => None,
+        };
+        self.discard();
+        return Err(err.unwrap_or_else(|| S3Error::internal("the upload stopped unexpectedly")));
+      }
+      self.next_part += 1;
+    }
+    Ok(())
+  }
+
+  /// Abort the upload and release its parked parts.
+  fn discard(&mut self) {
+    if let Some(pipe) = self.pipe.take() {
+      pipe.abort();
+    }
+    let parked: usize = std::mem::take(&mut self.parked).values().map(Bytes::len).sum();
+    self.parked_bytes.fetch_sub(parked, Ordering::Relaxed);
+  }
+}
+
+/// A request body, decoded and verified according to `x-amz-content-sha256`.
+struct Payload<B> {
+  body: B,
+  mode: PayloadMode,
+  decoded: std::collections::VecDeque<Bytes>,
+  done: bool,
+}
+
+enum PayloadMode {
+  /// The body as sent, checked against a SHA-256 when the client signed one.
+  Plain { expected: Option<String>, hasher: Sha256 },
+  Chunked(ChunkedDecoder),
+}
+
+impl<B> Payload<B>
+where
+  B: Body + Unpin,
+  B::Error: fmt::Display,
+{
+  fn new(body: B, signed: &Signed) -> Result<Self, S3Error> {
+    let mode = match signed.payload.as_str() {
+      "UNSIGNED-PAYLOAD" => PayloadMode::Plain { expected: None, hasher: Sha256::new() },
+      "STREAMING-AWS4-HMAC-SHA256-PAYLOAD" => PayloadMode::Chunked(ChunkedDecoder::new(Some(signed.chunk_signer()))),
+      "STREAMING-UNSIGNED-PAYLOAD-TRAILER" => PayloadMode::Chunked(ChunkedDecoder::new(None)),
+      hash if hash.len() == 64 && hash.bytes().all(|byte| byte.is_ascii_hexdigit()) => {
+        PayloadMode::Plain { expected: Some(hash.to_ascii_lowercase()), hasher: Sha256::new() }
+      }
+      other => return Err(S3Error::not_implemented(format!("payload signing mode {other}"))),
+    };
+    Ok(Self { body, mode, decoded: Default::default(), done: false })
+  }
+
+  /// The next piece of verified body data, or `None` at the end of the body.
+  async fn next(&mut self) -> Result<Option<Bytes>, S3Error> {
+    loop {
+      if let Some(data) = self.decoded.pop_front() {
+        return Ok(Some(data));
+      }
+      if self.done {
+        return Ok(None);
+      }
+      let frame = match self.body.frame().await {
+        Some(Ok(frame)) => frame,
+        Some(Err(err)) => {
+          return Err(S3Error::new(StatusCode::BAD_REQUEST, "IncompleteBody", format!("failed to read the request body: {err}")));
+        }
+        None => {
+          self.done = true;
+          self.verify_end()?;
+          continue;
+        }
+      };
+      let Ok(mut data) = frame.into_data() else { continue };
+      let data = data.copy_to_bytes(data.remaining());
+      match &mut self.mode {
+        PayloadMode::Plain { hasher, .. } => {
+          hasher.update(&data);
+          self.decoded.push_back(data);
+        }
+        PayloadMode::Chunked(decoder) => {
+          let mut out = Vec::new();
+          decoder.push(&data, &mut out)?;
+          self.decoded.extend(out);
+        }
+      }
+    }
+  }
+
+  fn verify_end(&mut self) -> Result<(), S3Error> {
+    match &mut self.mode {
+      PayloadMode::Plain { expected: Some(expected), hasher } => {
+        let actual = format!("{:x}", std::mem::take(hasher).finalize());
+        if actual != *expected {
+          return Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "XAmzContentSHA256Mismatch",
+            "the body does not match x-amz-content-sha256",
+          ));
+        }
+        Ok(())
+      }
+      PayloadMode::Plain { expected: None, .. } => Ok(()),
+      PayloadMode::Chunked(decoder) => Ok(decoder.finish()?),
+    }
+  }
+
+  /// Read the whole body into memory, failing if it exceeds `limit` bytes.
+  async fn collect(mut self, limit: usize) -> Result<Bytes, S3Error> {
+    let mut buf = Vec::new();
+    while let Some(data) = self.next().await? {
+      if buf.len() + data.len() > limit {
+        return Err(S3Error::new(
+          StatusCode::BAD_REQUEST,
+          "EntityTooLarge",
+          format!("the request body exceeds {limit} bytes"),
+        ));
+      }
+      buf.extend_from_slice(&data);
+    }
+    Ok(Bytes::from(buf))
+  }
+}
+
+/// An S3 error response.
+#[derive(Debug)]
+struct S3Error {
+  status: StatusCode,
+  code: &'static str,
+  message: String,
+}
+
+impl S3Error {
+  fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+    Self { status, code, message: message.into() }
+  }
+
+  fn no_such_key() -> Self {
+    Self::new(StatusCode::NOT_FOUND, "NoSuchKey", "the specified key does not exist")
+  }
+
+  fn no_such_upload() -> Self {
+    Self::new(StatusCode::NOT_FOUND, "NoSuchUpload", "the specified multipart upload does not exist")
+  }
+
+  fn access_denied(message: impl Into<String>) -> Self {
+    Self::new(StatusCode::FORBIDDEN, "AccessDenied", message)
+  }
+
+  fn invalid_argument(message: impl Into<String>) -> Self {
+    Self::new(StatusCode::BAD_REQUEST, "InvalidArgument", message)
+  }
+
+  fn not_implemented(what: impl fmt::Display) -> Self {
+    Self::new(StatusCode::NOT_IMPLEMENTED, "NotImplemented", format!("{what} is not supported by this gateway"))
+  }
+
+  fn internal(message: impl Into<String>) -> Self {
+    Self::new(StatusCode::INTERNAL_SERVER_ERROR, "InternalError", message)
+  }
+
+  fn into_response(self, resource: &str, request_id: &str, head: bool) -> Response<S3Body> {
+    if head {
+      // HEAD responses carry no body, so clients only see the status.
+      return empty_response(self.status);
+    }
+    let doc = format!(
+      "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Error><Code>{}</Code><Message>{}</Message>\
+       <Resource>{}</Resource><RequestId>{request_id}</RequestId></Error>",
+      self.code,
+      xml::escape(&self.message),
+      xml::escape(resource),
+    );
+    xml_response(self.status, doc)
+  }
+}
+
+impl From<FsError> for S3Error {
+  fn from(err: FsError) -> Self {
+    if err.is_not_found() {
+      return Self::no_such_key();
+    }
+    match &err {
+      FsError::Client(ClientError::Api { status, .. }) if matches!(*status, ApiStatus::FORBIDDEN | ApiStatus::UNAUTHORIZED) => {
+        Self::access_denied(err.to_string())
+      }
+      FsError::HiddenNotAllowed(_) => Self::access_denied(err.to_string()),
+      FsError::NotADirectory(_) | FsError::NotAFile(_) => {
+        Self::new(StatusCode::CONFLICT, "InvalidRequest", format!("{err}; keys and key prefixes cannot share a name"))
+      }
+      _ => Self::internal(err.to_string()),
+    }
+  }
+}
+
+impl From<AuthError> for S3Error {
+  fn from(err: AuthError) -> Self {
+    match err {
+      AuthError::Missing => Self::access_denied("requests must be signed with AWS Signature Version 4"),
+      AuthError::Malformed(message) => Self::new(StatusCode::BAD_REQUEST, "AuthorizationHeaderMalformed", message),
+      AuthError::UnknownAccessKey => {
+        Self::new(StatusCode::FORBIDDEN, "InvalidAccessKeyId", "the access key id is not configured on this gateway")
+      }
+      AuthError::Skewed => Self::new(
+        StatusCode::FORBIDDEN,
+        "RequestTimeTooSkewed",
+        "the difference between the request time and the server time is too large",
+      ),
+      AuthError::Expired => Self::access_denied("request has expired"),
+      AuthError::SignatureMismatch => Self::new(
+        StatusCode::FORBIDDEN,
+        "SignatureDoesNotMatch",
+        "the request signature does not match; check the secret key and signing method",
+      ),
+    }
+  }
+}
+
+impl From<ChunkError> for S3Error {
+  fn from(err: ChunkError) -> Self {
+    match err {
+      ChunkError::Malformed(reason) => Self::new(StatusCode::BAD_REQUEST, "IncompleteBody", reason),
+      ChunkError::SignatureMismatch => {
+        Self::new(StatusCode::FORBIDDEN, "SignatureDoesNotMatch", "a chunk signature does not match")
+      }
+    }
+  }
+}
+
+/// A stable ETag for an object, derived from its size and modification time.
+///
+/// Computing content MD5s would mean downloading every object, so these ETags
+/// change whenever a file is rewritten but are not content digests.
+fn object_etag(entry: &Entry) -> String {
+  let mut hasher = Sha256::new();
+  hasher.update(entry.size.unwrap_or(0).to_le_bytes());
+  if let Some(modified) = entry.last_modified {
+    hasher.update(modified.to_rfc3339_opts(SecondsFormat::Nanos, true).as_bytes());
+  }
+  let digest = format!("{:x}", hasher.finalize());
+  format!("\"{}\"", &digest[..32])
+}
+
+fn part_etag(data: &[u8]) -> String {
+  let digest = format!("{:x}", Sha256::digest(data));
+  format!("\"{}\"", &digest[..32])
+}
+
+fn decode_continuation(token: &str) -> Result<String, S3Error> {
+  URL_SAFE_NO_PAD
+    .decode(token)
+    .ok()
+    .and_then(|key| String::from_utf8(key).ok())
+    .ok_or_else(|| S3Error::invalid_argument("the continuation token is not valid"))
+}
+
+fn iso_timestamp(time: Option<DateTime<Utc>>) -> String {
+  time.unwrap_or_default().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn empty_body() -> S3Body {
+  Empty::new().map_err(|never| match never {}).boxed()
+}
+
+fn empty_response(status: StatusCode) -> Response<S3Body> {
+  Response::builder().status(status).body(empty_body()).expect("valid response")
+}
+
+fn xml_response(status: StatusCode, doc: String) -> Response<S3Body> {
+  Response::builder()
+    .status(status)
+    .header(header::CONTENT_TYPE, "application/xml")
+    .body(Full::new(Bytes::from(doc)).map_err(|never| match never {}).boxed())
+    .expect("valid response")
+}
+
+fn etag_header_response(etag: &str) -> Response<S3Body> {
+  Response::builder()
+    .status(StatusCode::OK)
+    .header(header::ETAG, etag)
+    .body(empty_body())
+    .expect("valid response")
+}
+
+fn etag_response(entry: &Entry) -> Response<S3Body> {
+  etag_header_response(&object_etag(entry))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn upload(parked_bytes: &Arc<AtomicUsize>) -> MultipartUpload {
+    MultipartUpload {
+      key: "data.bin".into(),
+      pipe: None,
+      next_part: 1,
+      parked: BTreeMap::new(),
+      parked_bytes: parked_bytes.clone(),
+      etags: BTreeMap::new(),
+      touched: Instant::now(),
+    }
+  }
+
+  #[test]
+  fn parked_parts_share_one_budget() {
+    let parked_bytes = Arc::new(AtomicUsize::new(MAX_PARKED_BYTES - 10));
+    let mut upload = upload(&parked_bytes);
+
+    let err = upload.park(3, Bytes::from(vec![0; 20])).unwrap_err();
+    assert_eq!(err.code, "SlowDown");
+    assert_eq!(parked_bytes.load(Ordering::Relaxed), MAX_PARKED_BYTES - 10);
+
+    upload.park(2, Bytes::from(vec![0; 5])).unwrap();
+    upload.park(2, Bytes::from(vec![0; 4])).unwrap();
+    // The next part in order is accepted even over the budget.
+    upload.park(1, Bytes::from(vec![0; 20])).unwrap();
+    assert_eq!(parked_bytes.load(Ordering::Relaxed), MAX_PARKED_BYTES + 14);
+
+    upload.discard();
+    assert!(upload.parked.is_empty());
+    assert_eq!(parked_bytes.load(Ordering::Relaxed), MAX_PARKED_BYTES - 10);
+  }
+}
@@ -0,0 +1,156 @@
+//! Just enough XML for S3 responses and the few request bodies the gateway reads.
+
+const S3_NAMESPACE: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+
+/// Builds an S3 response document.
+pub(crate) struct XmlWriter {
+  out: String,
+}
+
+impl XmlWriter {
+  /// Start a document whose root element is `root`, in the S3 namespace.
+  pub fn new(root: &str) -> Self {
+    Self { out: format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<{root} xmlns=\"{S3_NAMESPACE}\">") }
+  }
+
+  pub fn open(&mut self, tag: &str) -> &mut Self {
+    self.out.push_str(&format!("<{tag}>"));
+    self
+  }
+
+  pub fn close(&mut self, tag: &str) -> &mut Self {
+    self.out.push_str(&format!("</{tag}>"));
+    self
+  }
+
+  /// Add `<tag>text</tag>`, escaping `text`.
+  pub fn element(&mut self, tag: &str, text: impl AsRef<str>) -> &mut Self {
+    self.out.push_str(&format!("<{tag}>{}</{tag}>", escape(text.as_ref())));
+    self
+  }
+
+  pub fn finish(mut self, root: &str) -> String {
+    self.out.push_str(&format!("</{root}>"));
+    self.out
+  }
+}
+
+pub(crate) fn escape(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&apos;"),
+      // Control characters are not allowed in XML 1.0 text.
+      c if (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r') => escaped.push_str(&format!("&#x{:x};", c as u32)),
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+/// Raw contents of each `<tag>` element in `doc`, in document order.
+///
+/// Elements are matched by name only, which is enough for the flat bodies of
+/// `CompleteMultipartUpload` and `DeleteObjects`.
+pub(crate) fn children<'a>(doc: &'a str, tag: &str) -> Vec<&'a str> {
+  let open = format!("<{tag}");
+  let close = format!("</{tag}>");
+  let mut found = Vec::new();
+  let mut rest = doc;
+  while let Some(start) = rest.find(&open) {
+    let after = &rest[start + open.len()..];
+    let Some(end_of_tag) = after.find('>') else { break };
+    let attributes = &after[..end_of_tag];
+    if !attributes.is_empty() && !attributes.starts_with(|c: char| c.is_whitespace() || c == '/') {
+      // A longer tag name that merely starts with `tag`.
+      rest = after;
+      continue;
+    }
+    let body = &after[end_of_tag + 1..];
+    if attributes.ends_with('/') {
+      found.push("");
+      rest = body;
+      continue;
+    }
+    let Some(end) = body.find(&close) else { break };
+    found.push(&body[..end]);
+    rest = &body[end + close.len()..];
+  }
+  found
+}
+
+/// Text of the first `<tag>` element in `doc`, with entities decoded.
+pub(crate) fn text(doc: &str, tag: &str) -> Option<String> {
+  children(doc, tag).first().map(|raw| unescape(raw))
+}
+
+fn unescape(raw: &str) -> String {
+  let mut text = String::with_capacity(raw.len());
+  let mut rest = raw;
+  while let Some(amp) = rest.find('&') {
+    text.push_str(&rest[..amp]);
+    let entity = &rest[amp + 1..];
+    let Some(end) = entity.find(';') else {
+      rest = &rest[amp..];
+      break;
+    };
+    let decoded = match &entity[..end] {
+      "amp" => Some('&'),
+      "lt" => Some('<'),
+      "gt" => Some('>'),
+      "quot" => Some('"'),
+      "apos" => Some('\''),
+      name => name
+        .strip_prefix("#x")
+        .map(|hex| u32::from_str_radix(hex, 16))
+        .or_else(|| name.strip_prefix('#').map(str::parse))
+        .and_then(Result::ok)
+        .and_then(char::from_u32),
+    };
+    match decoded {
+      Some(c) => {
+        text.push(c);
+        rest = &entity[end + 1..];
+      }
+      None => {
+        text.push('&');
+        rest = entity;
+      }
+    }
+  }
+  text.push_str(rest);
+  text
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reads_complete_multipart_upload_body() {
+    let doc = r#"<CompleteMultipartUpload xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+      <Part><ETag>&quot;a1&quot;</ETag><PartNumber>1</PartNumber></Part>
+      <Part><PartNumber>2</PartNumber><ETag>"b2"</ETag></Part>
+    </CompleteMultipartUpload>"#;
+    let parts: Vec<_> = children(doc, "Part")
+      .into_iter()
+      .map(|part| (text(part, "PartNumber").unwrap(), text(part, "ETag").unwrap()))
+      .collect();
+    assert_eq!(parts, [("1".to_string(), "\"a1\"".to_string()), ("2".to_string(), "\"b2\"".to_string())]);
+    assert_eq!(text("<Delete><Quiet/></Delete>", "Quiet").as_deref(), Some(""));
+    let objects = children("<Delete><Object><Key>a&amp;b&#x20;c</Key></Object><ObjectKey/></Delete>", "Object");
+    assert_eq!(objects.len(), 1);
+    assert_eq!(text(objects[0], "Key").as_deref(), Some("a&b c"));
+  }
+
+  #[test]
+  fn escapes_text_in_elements() {
+    let mut writer = XmlWriter::new("Root");
+    writer.element("Key", "a<b>&\"c\"");
+    assert!(writer.finish("Root").ends_with("<Key>a&lt;b&gt;&amp;&quot;c&quot;</Key></Root>"));
+  }
+}
@@ -0,0 +1,401 @@
+//! Plain HTTP file server over the Jupyter Contents API.
+//!
+//! Directories are rendered as HTML index pages and files are streamed from the
+//! `/files` endpoint, so browsers and tools such as `wget -r` can fetch data
+//! while the Jupyter token never leaves this process.
+
+use std::{fmt, io, pin::Pin};
+
+use bytes::{Buf, Bytes};
+use chrono::{DateTime, Utc};
+use futures_util::{TryStreamExt, stream::Stream};
+use http_body_util::{BodyExt, Empty, Full, StreamBody, combinators::BoxBody};
+use hyper::{
+  HeaderMap, Method, Request, Response, StatusCode,
+  body::{Body, Frame},
+  header,
+};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use reqwest::StatusCode as ApiStatus;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::{
+  api::{client::ClientError, jupyter::JupyterLabApi},
+  fs::{Entry, FsError, FsService},
+  http_util::{http_date, parse_range},
+};
+
+/// Bytes buffered per Contents API save while a file is uploaded.
+const UPLOAD_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+/// Characters left unescaped in the links of an index page.
+const LINK_ENCODE: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+/// Response body produced by [`FileServer::handle`].
+pub type ServeBody = BoxBody<Bytes, io::Error>;
+
+/// Serves a remote directory tree over plain HTTP.
+///
+/// `GET` and `HEAD` return directory index pages or file contents (honouring a
+/// single `Range`); `PUT` stores the request body when uploads are allowed.
+#[derive(Clone)]
+pub struct FileServer {
+  fs: FsService,
+  root: String,
+  allow_uploads: bool,
+}
+
+impl FileServer {
+  pub fn new(fs: FsService) -> Self {
+    Self { fs, root: String::new(), allow_uploads: false }
+  }
+
+  /// Serve the remote directory `root` instead of the server root.
+  pub fn root(mut self, root: &str) -> Self {
+    self.root = root.trim_matches('/').to_string();
+    self
+  }
+
+  /// Accept `PUT` uploads; requests are read-only otherwise.
+  pub fn allow_uploads(mut self, allow_uploads: bool) -> Self {
+    self.allow_uploads = allow_uploads;
+    self
+  }
+
+  pub async fn handle<B>(&self, req: Request<B>) -> Response<ServeBody>
+  where
+    B: Body + Unpin,
+    B::Error: fmt::Display,
+  {
+    let head_only = req.method() == Method::HEAD;
+    let result = match *req.method() {
+      Method::GET | Method::HEAD => self.get(req.uri().path(), req.headers(), head_only).await,
+      Method::PUT if self.allow_uploads => {
+        let (parts, body) = req.into_parts();
+        self.put(parts.uri.path(), body).await
+      }
+      _ => Err(ServeError::method_not_allowed(self.allow_uploads)),
+    };
+    result.unwrap_or_else(|err| {
+      if err.status.is_server_error() {
+        warn!(status = %err.status, error = %err.message, "HTTP request failed");
+      }
+      err.into_response(head_only)
+    })
+  }
+
+  async fn get(&self, uri_path: &str, headers: &HeaderMap, head_only: bool) -> Result<Response<ServeBody>, ServeError> {
+    let path = self.resolve(uri_path)?;
+    let entry = self.fs.metadata(&path).await?;
+    if !entry.kind.is_directory() {
+      return self.get_file(&path, &entry, headers, head_only).await;
+    }
+    if !uri_path.ends_with('/') {
+      // Relative links in the index only resolve below a URL ending in `/`.
+      return Ok(
+        Response::builder()
+          .status(StatusCode::MOVED_PERMANENTLY)
+          .header(header::LOCATION, format!("{uri_path}/"))
+          .body(empty_body())
+          .expect("valid response"),
+      );
+    }
+    let mut entries = self.fs.ls(&path).await?;
+    entries.sort_by(|a, b| b.kind.is_directory().cmp(&a.kind.is_directory()).then_with(|| a.name.cmp(&b.name)));
+    let page = render_index(&percent_decode_str(uri_path).decode_utf8_lossy(), &entries);
+    let length = page.len();
+    let body = if head_only { empty_body() } else { full_body(page) };
+    Ok(
+      Response::builder()
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(header::CONTENT_LENGTH, length)
+        .body(body)
+        .expect("valid response"),
+    )
+  }
+
+  async fn get_file(
+    &self,
+    path: &str,
+    entry: &Entry,
+    headers: &HeaderMap,
+    head_only: bool,
+  ) -> Result<Response<ServeBody>, ServeError> {
+    let size = entry.size.unwrap_or(0);
+    let range = match headers.get(header::RANGE).and_then(|range| range.to_str().ok()) {
+      Some(range) => match parse_range(range, size) {
+        Ok(range) => range,
+        Err(()) => {
+          return Ok(
+            Response::builder()
+              .status(StatusCode::RANGE_NOT_SATISFIABLE)
+              .header(header::CONTENT_RANGE, format!("bytes */{size}"))
+              .body(empty_body())
+              .expect("valid response"),
+          );
+        }
+      },
+      None => None,
+    };
+    let (start, length) = range.map_or((0, size), |(start, end)| (start, end - start + 1));
+
+    let mut response = Response::builder()
+      .status(if range.is_some() { StatusCode::PARTIAL_CONTENT } else { StatusCode::OK })
+      .header(header::CONTENT_LENGTH, length)
+      .header(header::CONTENT_TYPE, entry.mimetype.as_deref().unwrap_or("application/octet-stream"))
+      .header(header::ACCEPT_RANGES, "bytes");
+    if let Some(modified) = entry.last_modified {
+      response = response.header(header::LAST_MODIFIED, http_date(modified));
+    }
+    if let Some((start, end)) = range {
+      response = response.header(header::CONTENT_RANGE, format!("bytes {start}-{end}/{size}"));
+    }
+    let body = if head_only || length == 0 {
+      empty_body()
+    } else {
+      let reader = self.open(path, start, length, size).await?;
+      BodyExt::boxed(StreamBody::new(ReaderStream::new(reader.take(length)).map_ok(Frame::data)))
+    };
+    Ok(response.body(body).expect("valid response headers"))
+  }
+
+  /// Stream `path` from byte `start` through `/files`, falling back to the Contents API.
+  async fn open(&self, path: &str, start: u64, length: u64, size: u64) -> Result<Box<dyn AsyncRead + Send + Sync + Unpin>, FsError> {
+    let client = self.fs.client().clone();
+    let range = (start > 0 || length < size).then_some((start, Some(start + length)));
+    let response = match client.get_files_stream(path, range).await {
+      Ok(response) => response,
+      Err(err) => {
+        debug!(path, error = %err, "streaming via /files failed; falling back to the contents endpoint");
+        return Ok(self.fs.download_reader_from(path, start).await?.reader);
+      }
+    };
+    let partial = response.status() == ApiStatus::PARTIAL_CONTENT;
+    let stream = response
+      .bytes_stream()
+      .map_err(io::Error::other)
+      .and_then(move |chunk| {
+        let client = client.clone();
+        async move {
+          client.consume(chunk.len() as u64).await;
+          Ok(chunk)
+        }
+      });
+    let stream: Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send + Sync>> = Box::pin(stream);
+    let mut reader = StreamReader::new(stream);
+    if range.is_some() && !partial && start > 0 {
+      // The server ignored the range and sent the whole file.
+      tokio::io::copy(&mut (&mut reader).take(start), &mut tokio::io::sink()).await.map_err(FsError::Io)?;
+    }
+    Ok(Box::new(reader))
+  }
+
+  async fn put<B>(&self, uri_path: &str, mut body: B) -> Result<Response<ServeBody>, ServeError>
+  where
+    B: Body + Unpin,
+    B::Error: fmt::Display,
+  {
+    if uri_path.ends_with('/') {
+      return Err(ServeError::new(StatusCode::BAD_REQUEST, "PUT needs a file name, not a directory"));
+    }
+    let path = self.resolve(uri_path)?;
+    match self.fs.metadata(&path).await {
+      Ok(entry) if entry.kind.is_directory() => {
+        return Err(ServeError::new(StatusCode::CONFLICT, format!("{path} is a directory")));
+      }
+      Ok(_) => {}
+      Err(err) if err.is_not_found() => {
+        if let Some((parent, _)) = path.rsplit_once('/') {
+          self.fs.mkdir_all(parent).await?;
+        }
+      }
+      Err(err) => return Err(err.into()),
+    }
+
+    let mut pipe = self.fs.upload_pipe(&path, UPLOAD_CHUNK_SIZE);
+    while let Some(frame) = body.frame().await {
+      let frame = match frame {
+        Ok(frame) => frame,
+        Err(err) => {
+          pipe.abort();
+          return Err(ServeError::new(StatusCode::BAD_REQUEST, format!("failed to read the request body: {err}")));
+        }
+      };
+      let Ok(mut data) = frame.into_data() else { continue };
+      if pipe.write(&data.copy_to_bytes(data.remaining())).await.is_err() {
+        // The upload task stopped; finish() reports why.
+        break;
+      }
+    }
+    let upload = pipe.finish().await?;
+    info!(path = %path, bytes = upload.bytes, "file uploaded over HTTP");
+    Ok(
+      Response::builder()
+        .status(StatusCode::CREATED)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(full_body(format!("stored {} bytes at {}\n", upload.bytes, upload.entry.path)))
+        .expect("valid response"),
+    )
+  }
+
+  /// Jupyter path for the request path `uri_path`, which must stay below the served root.
+  fn resolve(&self, uri_path: &str) -> Result<String, ServeError> {
+    let decoded = percent_decode_str(uri_path)
+      .decode_utf8()
+      .map_err(|_| ServeError::new(StatusCode::BAD_REQUEST, "request paths must be UTF-8"))?;
+    let mut segments = vec![];
+    if !self.root.is_empty() {
+      segments.push(self.root.as_str());
+    }
+    for segment in decoded.split('/') {
+      match segment {
+        "" | "." => {}
+        ".." => return Err(ServeError::new(StatusCode::FORBIDDEN, "`..` is not allowed in request paths")),
+        segment => segments.push(segment),
+      }
+    }
+    Ok(segments.join("/"))
+  }
+}
+
+/// HTML index page for the directory shown at `display_path`, linking each entry relatively.
+fn render_index(display_path: &str, entries: &[Entry]) -> String {
+  let title = format!("Index of {}", escape_html(display_path));
+  let mut page = format!(
+    "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body>\n<h1>{title}</h1>\n\
+     <table>\n<tr><th>Name</th><th>Size</th><th>Last modified</th></tr>\n"
+  );
+  if display_path != "/" {
+    page.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+  }
+  for entry in entries {
+    let suffix = if entry.kind.is_directory() { "/" } else { "" };
+    let size = match (entry.kind.is_directory(), entry.size) {
+      (false, Some(size)) => size.to_string(),
+      _ => "-".to_string(),
+    };
+    page.push_str(&format!(
+      "<tr><td><a href=\"{}{suffix}\">{}{suffix}</a></td><td>{size}</td><td>{}</td></tr>\n",
+      utf8_percent_encode(&entry.name, LINK_ENCODE),
+      escape_html(&entry.name),
+      entry.last_modified.as_ref().map(format_time).unwrap_or_default(),
+    ));
+  }
+  page.push_str("</table>\n</body>\n</html>\n");
+  page
+}
+
+fn format_time(time: &DateTime<Utc>) -> String {
+  time.format("%Y-%m-%d %H:%M").to_string()
+}
+
+fn escape_html(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+fn empty_body() -> ServeBody {
+  Empty::new().map_err(|never| match never {}).boxed()
+}
+
+fn full_body(text: String) -> ServeBody {
+  Full::new(Bytes::from(text)).map_err(|never| match never {}).boxed()
+}
+
+/// A failed request, reported to the client as a plain-text error page.
+#[derive(Debug)]
+struct ServeError {
+  status: StatusCode,
+  message: String,
+  /// Methods listed in the `Allow` header of a 405 response.
+  allow: Option<&'static str>,
+}
+
+impl ServeError {
+  fn new(status: StatusCode, message: impl Into<String>) -> Self {
+    Self { status, message: message.into(), allow: None }
+  }
+
+  fn method_not_allowed(allow_uploads: bool) -> Self {
+    let (allow, message) = if allow_uploads {
+      ("GET, HEAD, PUT", "only GET, HEAD and PUT are supported")
+    } else {
+      ("GET, HEAD", "only GET and HEAD are supported; uploads are disabled")
+    };
+    Self { allow: Some(allow), ..Self::new(StatusCode::METHOD_NOT_ALLOWED, message) }
+  }
+
+  fn into_response(self, head_only: bool) -> Response<ServeBody> {
+    let text = format!("{} {}\n", self.status, self.message);
+    let mut response = Response::builder()
+      .status(self.status)
+      .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+      .header(header::CONTENT_LENGTH, text.len());
+    if let Some(allow) = self.allow {
+      response = response.header(header::ALLOW, allow);
+    }
+    let body = if head_only { empty_body() } else { full_body(text) };
+    response.body(body).expect("valid response")
+  }
+}
+
+impl From<FsError> for ServeError {
+  fn from(err: FsError) -> Self {
+    if err.is_not_found() {
+      return Self::new(StatusCode::NOT_FOUND, "no such file or directory");
+    }
+    match &err {
+      FsError::Client(ClientError::Api { status, .. }) if matches!(*status, ApiStatus::FORBIDDEN | ApiStatus::UNAUTHORIZED) => {
+        Self::new(StatusCode::FORBIDDEN, err.to_string())
+      }
+      FsError::HiddenNotAllowed(_) => Self::new(StatusCode::FORBIDDEN, err.to_string()),
+      FsError::NotADirectory(_) | FsError::NotAFile(_) => Self::new(StatusCode::CONFLICT, err.to_string()),
+      _ => Self::new(StatusCode::BAD_GATEWAY, err.to_string()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::fs::EntryKind;
+
+  fn entry(name: &str, kind: EntryKind, size: Option<u64>) -> Entry {
+    Entry {
+      name: name.to_string(),
+      path: format!("data/{name}"),
+      kind,
+      writable: true,
+      created: None,
+      last_modified: None,
+      size,
+      mimetype: None,
+      hash: None,
+      hash_algorithm: None,
+    }
+  }
+
+  #[test]
+  fn index_links_are_encoded_and_names_escaped() {
+    let entries = [
+      entry("raw data", EntryKind::Directory, None),
+      entry("a&b <1>.csv", EntryKind::File, Some(42)),
+    ];
+    let page = render_index("/data/", &entries);
+    assert!(page.contains("<title>Index of /data/</title>"));
+    assert!(page.contains("<a href=\"../\">../</a>"));
+    assert!(page.contains("<a href=\"raw%20data/\">raw data/</a></td><td>-</td>"));
+    assert!(page.contains("<a href=\"a%26b%20%3C1%3E.csv\">a&amp;b &lt;1&gt;.csv</a></td><td>42</td>"));
+    assert!(!render_index("/", &[]).contains("../"));
+  }
+}
@@ -0,0 +1,504 @@
+use std::{
+  collections::BTreeMap,
+  fs,
+  io::{self, Read, Write},
+  mem,
+  path::{Path, PathBuf},
+  time::Duration,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures_util::stream;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+
+use crate::{
+  fs::{FsError, FsService, temp_sibling},
+  shell::shell_path,
+};
+
+/// Base64 characters per line typed into the terminal; well below the 4096-byte tty line limit.
+const BULK_LINE_LEN: usize = 1024;
+/// Lines batched into a single terminal stdin message.
+const BULK_LINES_PER_MESSAGE: usize = 64;
+/// Archive bytes that encode to exactly one full terminal message.
+const BULK_MESSAGE_BYTES: usize = BULK_LINE_LEN / 4 * 3 * BULK_LINES_PER_MESSAGE;
+/// Messages or decoded chunks queued between the archive and the terminal.
+const BULK_CHANNEL_CAPACITY: usize = 16;
+/// Timeout for a whole bulk transfer, including unpacking on the far side.
+const BULK_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Relative path (with `/` separators) to hex-encoded sha256 digest.
+type Manifest = BTreeMap<String, String>;
+
+/// Summary of a completed [`FsService::bulk_upload`] or [`FsService::bulk_download`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BulkReport {
+  pub files: usize,
+  pub bytes: u64,
+}
+
+impl FsService {
+  /// Upload a local directory tree as a single tar stream typed into a Jupyter terminal.
+  ///
+  /// The tree is packed locally and streamed as base64 into `base64 -d | tar x`,
+  /// then every file is checked against a remote `sha256sum`. This trades one
+  /// request per file for one terminal session, which pays off for trees with many
+  /// small files. Symlinks are followed, as they are by a per-file upload.
+  ///
+  /// With `atomic`, the archive is unpacked into a hidden staging directory next
+  /// to `remote_dir` and verified there, so a failed transfer leaves the target as
+  /// it was. The verified files are then moved into place one at a time: the swap
+  /// is per file, not atomic. If a move fails, the target keeps the files moved so
+  /// far and the staging directory is kept with the rest.
+  #[tracing::instrument(skip(self), fields(local = %local_dir.display(), remote = %remote_dir))]
+  pub async fn bulk_upload(&self, local_dir: &Path, remote_dir: &str, atomic: bool) -> Result<BulkReport, FsError> {
+    debug!("fs: bulk_upload {} -> {}", local_dir.display(), remote_dir);
+    let shell = self._shell_or_default().timeout(BULK_TIMEOUT);
+    let staging = atomic.then(|| temp_sibling(remote_dir, true));
+    let extract_to = staging.as_deref().unwrap_or(remote_dir);
+    let result = self._bulk_extract(local_dir, extract_to).await;
+    let result = match (result, &staging) {
+      (Ok(report), Some(staging)) => {
+        // Parents are listed before their contents; the loop stops at the first failure,
+        // and `mv -T` refuses to move a file into a directory of the same name.
+        let target = shell_path(remote_dir);
+        let script = format!(
+          "mkdir -p -- {target} && t=$(cd -- {target} && pwd) && (cd -- {staging} && \
+           find . -mindepth 1 -print0 | while IFS= read -r -d '' f; do \
+           if [ -d \"$f\" ]; then mkdir -p -- \"$t/$f\"; else mv -fT -- \"$f\" \"$t/$f\"; fi || exit 1; \
+           done) && rm -rf -- {staging}",
+          staging = shell_path(staging),
+        );
+        let installed = shell.run_checked(&script).await;
+        if let Err(err) = &installed {
+          warn!(error = %err, %staging, "bulk upload only partly moved into place; keeping the staging directory");
+        }
+        installed.map(|_| report)
+      }
+      (Err(err), Some(staging)) => {
+        shell.run(&format!("rm -rf -- {}", shell_path(staging))).await.ok();
+        Err(err)
+      }
+      (result, None) => result,
+    };
+    self._invalidate(remote_dir);
+    let report = result?;
+    debug!(files = report.files, bytes = report.bytes, "bulk upload verified");
+    Ok(report)
+  }
+
+  async fn _bulk_extract(&self, local_dir: &Path, remote_dir: &str) -> Result<BulkReport, FsError> {
+    let (tx, mut rx) = mpsc::channel(BULK_CHANNEL_CAPACITY);
+    let root = local_dir.to_path_buf();
+    let packer = tokio::task::spawn_blocking(move || {
+      let packed = pack_tree(&root, LineEncoder::new(tx.clone()))
+        .and_then(|(encoder, manifest, bytes)| encoder.finish().map(|_| (manifest, bytes)));
+      if let Err(err) = &packed {
+        // Stop the script before end-of-file, so a truncated archive is never unpacked.
+        tx.blocking_send(Err(FsError::Io(io::Error::new(err.kind(), err.to_string())))).ok();
+      }
+      packed
+    });
+
+    let shell = self._shell_or_default().timeout(BULK_TIMEOUT);
+    let target = shell_path(remote_dir);
+    let script = format!("mkdir -p -- {target} && base64 -d | tar xf - -C {target}");
+    let input = Box::pin(stream::poll_fn(move |cx| rx.poll_recv(cx)));
+    let result = shell.run_with_input_stream(&script, input).await;
+    let packed = packer.await.map_err(io::Error::other)?;
+    let output = result?;
+    let (manifest, bytes) = packed?;
+    if output.status != 0 {
+      return Err(FsError::Command { status: output.status, output: output.output.trim().to_string() });
+    }
+    trace!(files = manifest.len(), bytes, "streamed local tree");
+
+    let remote = self._remote_manifest(remote_dir).await?;
+    if let Some(reason) = manifest_mismatch(&manifest, &remote) {
+      return Err(FsError::InvalidPayload(reason));
+    }
+    Ok(BulkReport { files: manifest.len(), bytes })
+  }
+
+  /// Count regular files below `remote_dir` with `find` in a Jupyter terminal,
+  /// stopping early once `limit` is reached.
+  ///
+  /// Hidden files are counted, as [`FsService::bulk_download`] copies them too;
+  /// unreadable subdirectories are skipped.
+  #[tracing::instrument(skip(self), fields(remote = %remote_dir))]
+  pub async fn count_files(&self, remote_dir: &str, limit: usize) -> Result<usize, FsError> {
+    debug!("fs: count_files {} (limit {})", remote_dir, limit);
+    let script = format!("find {} -type f 2>/dev/null | head -n {limit} | wc -l", shell_path(remote_dir));
+    let output = self._shell_or_default().run_checked(&script).await?;
+    output
+      .trim()
+      .parse()
+      .map_err(|_| FsError::InvalidPayload(format!("unexpected file count {:?}", output.trim())))
+  }
+
+  /// Download a remote directory tree as a single tar stream read from a Jupyter terminal.
+  ///
+  /// The reverse of [`FsService::bulk_upload`]: the server runs `tar c | base64`,
+  /// the archive is unpacked into `local_dir` as it arrives, and each file is
+  /// checked against the remote `sha256sum` output.
+  #[tracing::instrument(skip(self), fields(remote = %remote_dir, local = %local_dir.display()))]
+  pub async fn bulk_download(&self, remote_dir: &str, local_dir: &Path) -> Result<BulkReport, FsError> {
+    debug!("fs: bulk_download {} -> {}", remote_dir, local_dir.display());
+    let (tx, rx) = mpsc::channel::<Vec<u8>>(BULK_CHANNEL_CAPACITY);
+    let root = local_dir.to_path_buf();
+    let unpacker = tokio::task::spawn_blocking(move || {
+      fs::create_dir_all(&root)?;
+      tar::Archive::new(ChannelReader::new(rx)).unpack(&root)
+    });
+
+    let shell = self._shell_or_default().timeout(BULK_TIMEOUT);
+    let mut decoder = LineDecoder::default();
+    let mut diagnostics = Vec::new();
+    let script = format!("tar cf - -C {} . | base64", shell_path(remote_dir));
+    let result = shell
+      .run_with_output_lines(&script, async |line: &str| {
+        if !is_base64_line(line) {
+          diagnostics.push(line.to_string());
+          return Ok(());
+        }
+        let chunk = decoder.push(line)?;
+        if !chunk.is_empty() {
+          // The unpacker may stop reading at the end-of-archive marker; the padding after it is not needed.
+          tx.send(chunk).await.ok();
+        }
+        Ok(())
+      })
+      .await;
+    drop(tx);
+    let unpacked = unpacker.await.map_err(io::Error::other)?;
+    let status = result?;
+    if status != 0 {
+      return Err(FsError::Command { status, output: diagnostics.join("\n") });
+    }
+    decoder.finish()?;
+    unpacked?;
+    trace!(warnings = diagnostics.len(), "unpacked remote archive");
+
+    let remote = self._remote_manifest(remote_dir).await?;
+    let root = local_dir.to_path_buf();
+    let paths: Vec<String> = remote.keys().cloned().collect();
+    let (local, bytes) = tokio::task::spawn_blocking(move || hash_files(&root, &paths))
+      .await
+      .map_err(io::Error::other)??;
+    if let Some(reason) = manifest_mismatch(&remote, &local) {
+      return Err(FsError::InvalidPayload(reason));
+    }
+    debug!(files = remote.len(), bytes, "bulk download verified");
+    Ok(BulkReport { files: remote.len(), bytes })
+  }
+
+  async fn _remote_manifest(&self, remote_dir: &str) -> Result<Manifest, FsError> {
+    let script = format!("cd {} && find . -type f -exec sha256sum -- {{}} +", shell_path(remote_dir));
+    let output = self._shell_or_default().timeout(BULK_TIMEOUT).run_checked(&script).await?;
+    Ok(parse_sha256sum(&output))
+  }
+}
+
+/// Encodes an archive into terminal messages as it is written, handing each to the uploader.
+struct LineEncoder {
+  buffer: Vec<u8>,
+  tx: mpsc::Sender<Result<String, FsError>>,
+}
+
+impl LineEncoder {
+  fn new(tx: mpsc::Sender<Result<String, FsError>>) -> Self {
+    Self { buffer: Vec::with_capacity(BULK_MESSAGE_BYTES), tx }
+  }
+
+  fn send(&self, chunk: &[u8]) -> io::Result<()> {
+    for message in encode_lines(chunk) {
+      self
+        .tx
+        .blocking_send(Ok(message))
+        .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the terminal stopped reading the archive"))?;
+    }
+    Ok(())
+  }
+
+  /// Send whatever is left of the archive, with its base64 padding.
+  fn finish(self) -> io::Result<()> {
+    if self.buffer.is_empty() { Ok(()) } else { self.send(&self.buffer) }
+  }
+}
+
+impl Write for LineEncoder {
+  fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+    self.buffer.extend_from_slice(data);
+    while self.buffer.len() >= BULK_MESSAGE_BYTES {
+      let rest = self.buffer.split_off(BULK_MESSAGE_BYTES);
+      let chunk = mem::replace(&mut self.buffer, rest);
+      self.send(&chunk)?;
+    }
+    Ok(data.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/// Decodes base64 output line by line, carrying incomplete groups over to the next line.
+#[derive(Debug, Default)]
+struct LineDecoder {
+  carry: String,
+}
+
+impl LineDecoder {
+  fn push(&mut self, line: &str) -> io::Result<Vec<u8>> {
+    self.carry.push_str(line.trim());
+    let usable = self.carry.len() / 4 * 4;
+    let decoded = STANDARD
+      .decode(&self.carry[..usable])
+      .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    self.carry.drain(..usable);
+    Ok(decoded)
+  }
+
+  fn finish(self) -> io::Result<()> {
+    if self.carry.is_empty() {
+      Ok(())
+    } else {
+      Err(io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        format!("archive ended mid-way through a base64 group: {:?}", self.carry),
+      ))
+    }
+  }
+}
+
+/// Whether `line` is `base64` output rather than a message from `tar`, which shares the terminal.
+fn is_base64_line(line: &str) -> bool {
+  !line.is_empty() && line.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/' | b'='))
+}
+
+/// Blocking reader over decoded chunks, for feeding `tar` from async code.
+struct ChannelReader {
+  rx: mpsc::Receiver<Vec<u8>>,
+  chunk: Vec<u8>,
+  pos: usize,
+}
+
+impl ChannelReader {
+  fn new(rx: mpsc::Receiver<Vec<u8>>) -> Self {
+    Self { rx, chunk: Vec::new(), pos: 0 }
+  }
+}
+
+impl Read for ChannelReader {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    while self.pos == self.chunk.len() {
+      match self.rx.blocking_recv() {
+        Some(chunk) => {
+          self.chunk = chunk;
+          self.pos = 0;
+        }
+        None => return Ok(0),
+      }
+    }
+    let len = buf.len().min(self.chunk.len() - self.pos);
+    buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+    self.pos += len;
+    Ok(len)
+  }
+}
+
+/// Reader that hashes and counts what passes through it.
+struct HashingReader<R> {
+  inner: R,
+  hasher: Sha256,
+  count: u64,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let len = self.inner.read(buf)?;
+    self.hasher.update(&buf[..len]);
+    self.count += len as u64;
+    Ok(len)
+  }
+}
+
+/// Describe the first file in `expected` that did not arrive with the same digest in `actual`.
+fn manifest_mismatch(expected: &Manifest, actual: &Manifest) -> Option<String> {
+  expected.iter().find_map(|(path, digest)| match actual.get(path) {
+    Some(found) if found.eq_ignore_ascii_case(digest) => None,
+    found => Some(format!(
+      "sha256 mismatch for {}: expected {}, got {}",
+      path,
+      digest,
+      found.map(String::as_str).unwrap_or("nothing")
+    )),
+  })
+}
+
+/// Split the archive into base64 lines and batch them into terminal messages.
+fn encode_lines(archive: &[u8]) -> Vec<String> {
+  let encoded = STANDARD.encode(archive);
+  let lines: Vec<&str> = encoded
+    .as_bytes()
+    .chunks(BULK_LINE_LEN)
+    .map(|chunk| std::str::from_utf8(chunk).expect("base64 output is ascii"))
+    .collect();
+  lines
+    .chunks(BULK_LINES_PER_MESSAGE)
+    .map(|batch| {
+      let mut message = batch.join("\n");
+      message.push('\n');
+      message
+    })
+    .collect()
+}
+
+/// Parse `sha256sum` output for paths relative to the directory it ran in.
+///
+/// Lines for names that `sha256sum` had to escape (leading backslash) are skipped,
+/// so such files surface as checksum mismatches rather than being misattributed.
+fn parse_sha256sum(output: &str) -> Manifest {
+  output
+    .lines()
+    .filter(|line| !line.starts_with('\\'))
+    .filter_map(|line| {
+      let (digest, path) = line.split_once("  ").or_else(|| line.split_once(" *"))?;
+      Some((path.trim_start_matches("./").to_string(), digest.to_ascii_lowercase()))
+    })
+    .collect()
+}
+
+/// Pack `root` into a tar archive written to `writer`, returning it with the manifest and total file bytes.
+///
+/// Symlinks are followed and packed as the files or directories they point to.
+fn pack_tree<W: Write>(root: &Path, writer: W) -> io::Result<(W, Manifest, u64)> {
+  let mut builder = tar::Builder::new(writer);
+  let mut manifest = Manifest::new();
+  let mut total = 0u64;
+  let mut stack = vec![(root.to_path_buf(), String::new())];
+  while let Some((dir, prefix)) = stack.pop() {
+    let mut entries = fs::read_dir(&dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+      let path = entry.path();
+      let name = entry.file_name().to_string_lossy().into_owned();
+      let relative = if prefix.is_empty() { name } else { format!("{prefix}/{name}") };
+      let metadata = fs::metadata(&path)?;
+      if metadata.is_dir() {
+        builder.append_dir(&relative, &path)?;
+        stack.push((path, relative));
+      } else if metadata.is_file() {
+        let file = fs::File::open(&path)?;
+        let size = metadata.len();
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&metadata);
+        header.set_size(size);
+        let mut reader = HashingReader { inner: file.take(size), hasher: Sha256::new(), count: 0 };
+        builder.append_data(&mut header, &relative, &mut reader)?;
+        if reader.count != size {
+          return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("{} shrank from {} to {} bytes while it was being packed", path.display(), size, reader.count),
+          ));
+        }
+        manifest.insert(relative, format!("{:x}", reader.hasher.finalize()));
+        total += size;
+      } else {
+        return Err(io::Error::new(
+          io::ErrorKind::InvalidInput,
+          format!("{} is neither a file nor a directory", path.display()),
+        ));
+      }
+    }
+  }
+  Ok((builder.into_inner()?, manifest, total))
+}
+
+/// Hash the copies of `paths` below `root`, skipping any that are missing.
+fn hash_files(root: &Path, paths: &[String]) -> io::Result<(Manifest, u64)> {
+  let mut manifest = Manifest::new();
+  let mut total = 0u64;
+  for relative in paths {
+    let local: PathBuf = relative.split('/').fold(root.to_path_buf(), |path, part| path.join(part));
+    let mut file = match fs::File::open(&local) {
+      Ok(file) => file,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+      Err(err) => return Err(err),
+    };
+    let mut hasher = Sha256::new();
+    total += io::copy(&mut file, &mut hasher)?;
+    manifest.insert(relative.clone(), format!("{:x}", hasher.finalize()));
+  }
+  Ok((manifest, total))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sha256sum_output_is_keyed_by_relative_path() {
+    let manifest = parse_sha256sum("ABC  ./a.txt\ndef *./dir/b.bin\n\\123  ./weird\\nname\n");
+    assert_eq!(manifest.get("a.txt").map(String::as_str), Some("abc"));
+    assert_eq!(manifest.get("dir/b.bin").map(String::as_str), Some("def"));
+    assert_eq!(manifest.len(), 2);
+  }
+
+  #[test]
+  fn encoded_lines_stay_below_tty_limit() {
+    let archive = vec![7u8; BULK_LINE_LEN * BULK_LINES_PER_MESSAGE];
+    let messages = encode_lines(&archive);
+    assert!(messages.iter().all(|message| message.ends_with('\n')));
+    assert!(messages.iter().flat_map(|message| message.lines()).all(|line| line.len() <= BULK_LINE_LEN));
+    let decoded = STANDARD.decode(messages.concat().replace('\n', "")).unwrap();
+    assert_eq!(decoded, archive);
+  }
+
+  #[test]
+  fn decoder_carries_partial_groups_between_lines() {
+    let encoded = STANDARD.encode(b"hello, bulk transfer");
+    let mut decoder = LineDecoder::default();
+    let mut decoded = Vec::new();
+    for chunk in encoded.as_bytes().chunks(7) {
+      decoded.extend(decoder.push(std::str::from_utf8(chunk).unwrap()).unwrap());
+    }
+    decoder.finish().unwrap();
+    assert_eq!(decoded, b"hello, bulk transfer");
+    assert!(is_base64_line(&encoded));
+    assert!(!is_base64_line("tar: ./gone: Cannot open: No such file or directory"));
+  }
+
+  #[test]
+  fn pack_and_unpack_round_trip() {
+    let base = std::env::temp_dir().join(format!("jupyter_shell_bulk_{}", std::process::id()));
+    let source = base.join("src");
+    fs::create_dir_all(source.join("nested")).unwrap();
+    fs::write(source.join("a.txt"), "alpha").unwrap();
+    fs::write(source.join("nested/b.txt"), "beta").unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(source.join("a.txt"), source.join("link.txt")).unwrap();
+    let expected_files = if cfg!(unix) { 3 } else { 2 };
+
+    let (archive, manifest, total) = pack_tree(&source, Vec::new()).unwrap();
+    assert_eq!(manifest.len(), expected_files);
+    assert_eq!(total, if cfg!(unix) { 14 } else { 9 });
+
+    let destination = base.join("dst");
+    fs::create_dir_all(&destination).unwrap();
+    tar::Archive::new(archive.as_slice()).unpack(&destination).unwrap();
+    let paths: Vec<String> = manifest.keys().cloned().collect();
+    let (restored, bytes) = hash_files(&destination, &paths).unwrap();
+    assert_eq!(bytes, total);
+    assert_eq!(manifest_mismatch(&manifest, &restored), None);
+    assert_eq!(fs::read_to_string(destination.join("nested/b.txt")).unwrap(), "beta");
+    #[cfg(unix)]
+    assert!(!fs::symlink_metadata(destination.join("link.txt")).unwrap().file_type().is_symlink());
+
+    let mut tampered = manifest.clone();
+    tampered.insert("a.txt".into(), "0".repeat(64));
+    assert!(manifest_mismatch(&tampered, &restored).unwrap().contains("a.txt"));
+    fs::remove_dir_all(&base).ok();
+  }
+}
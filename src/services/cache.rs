@@ -0,0 +1,262 @@
+use std::{collections::BTreeMap, sync::atomic::{AtomicU64, Ordering}, time::Duration};
+
+use parking_lot::RwLock;
+
+use crate::fs::Entry;
+
+/// Short-lived cache of [`Entry`] lookups and directory listings.
+///
+/// Values expire after a fixed TTL. Paths written, renamed or deleted through
+/// the owning [`FsService`](crate::fs::FsService) are invalidated eagerly, but
+/// changes made by other clients only show up once the TTL elapses.
+///
+/// Expired values are swept at most once per TTL as new ones are stored, and the
+/// least recently stored values are dropped once [`MAX_ENTRIES`] or
+/// [`MAX_LISTINGS`] is exceeded, so long-running gateways do not grow without
+/// bound. Values are kept sorted by path, so invalidating a directory only
+/// visits the paths below it.
+pub struct MetadataCache {
+  ttl_ms: u64,
+  entries: RwLock<BTreeMap<String, Slot<Entry>>>,
+  listings: RwLock<BTreeMap<String, Slot<Vec<Entry>>>>,
+  last_sweep: AtomicU64,
+  /// Source of [`Slot::seq`].
+  next_seq: AtomicU64,
+}
+
+/// A cached value with the time it was stored.
+struct Slot<V> {
+  stamp: u64,
+  /// Increases with every store, ordering values that share a millisecond stamp.
+  seq: u64,
+  value: V,
+}
+
+/// Most entries kept before the oldest are evicted.
+const MAX_ENTRIES: usize = 50_000;
+/// Most directory listings kept before the oldest are evicted.
+const MAX_LISTINGS: usize = 5_000;
+
+impl MetadataCache {
+  pub fn new(ttl: Duration) -> Self {
+    Self {
+      ttl_ms: ttl.as_millis() as u64,
+      entries: RwLock::default(),
+      listings: RwLock::default(),
+      last_sweep: AtomicU64::new(now()),
+      next_seq: AtomicU64::new(0),
+    }
+  }
+
+  /// How long values stay fresh.
+  pub fn ttl(&self) -> Duration {
+    Duration::from_millis(self.ttl_ms)
+  }
+
+  pub fn entry(&self, path: &str) -> Option<Entry> {
+    self.fresh(&self.entries, cache_key(path))
+  }
+
+  pub fn listing(&self, path: &str) -> Option<Vec<Entry>> {
+    self.fresh(&self.listings, cache_key(path))
+  }
+
+  pub fn put_entry(&self, path: &str, entry: Entry) {
+    let slot = self.slot(entry);
+    self.entries.write().insert(cache_key(path).to_string(), slot);
+    self.maybe_sweep();
+  }
+
+  /// Cache a directory listing along with an entry for each child.
+  pub fn put_listing(&self, path: &str, entries: &[Entry]) {
+    {
+      let mut cached = self.entries.write();
+      for entry in entries {
+        cached.insert(cache_key(&entry.path).to_string(), self.slot(entry.clone()));
+      }
+    }
+    let slot = self.slot(entries.to_vec());
+    self.listings.write().insert(cache_key(path).to_string(), slot);
+    self.maybe_sweep();
+  }
+
+  /// Drop everything cached for `path`, its descendants and its parent listing.
+  pub fn invalidate(&self, path: &str) {
+    let key = cache_key(path);
+    trace!(path = %key, "invalidating cached metadata");
+    remove_tree(&mut self.entries.write(), key);
+    let mut listings = self.listings.write();
+    remove_tree(&mut listings, key);
+    listings.remove(parent_key(key));
+  }
+
+  pub fn clear(&self) {
+    self.entries.write().clear();
+    self.listings.write().clear();
+  }
+
+  fn slot<V>(&self, value: V) -> Slot<V> {
+    Slot { stamp: now(), seq: self.next_seq.fetch_add(1, Ordering::Relaxed), value }
+  }
+
+  /// The value cached for `key` if it has not expired; expired values are dropped.
+  fn fresh<V: Clone>(&self, cached: &RwLock<BTreeMap<String, Slot<V>>>, key: &str) -> Option<V> {
+    let seq = {
+      let map = cached.read();
+      let slot = map.get(key)?;
+      if self.is_fresh(slot.stamp) {
+        return Some(slot.value.clone());
+      }
+      slot.seq
+    };
+    let mut map = cached.write();
+    // Only drop the value if it was not stored again in the meantime.
+    if map.get(key).is_some_and(|slot| slot.seq == seq) {
+      map.remove(key);
+    }
+    None
+  }
+
+  /// Sweep when a TTL has passed since the last sweep or a size cap is exceeded.
+  fn maybe_sweep(&self) {
+    let now = now();
+    let last = self.last_sweep.load(Ordering::Relaxed);
+    let due = now.saturating_sub(last) >= self.ttl_ms;
+    let full = self.entries.read().len() > MAX_ENTRIES || self.listings.read().len() > MAX_LISTINGS;
+    if (due || full) && self.last_sweep.compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+      self.sweep();
+    }
+  }
+
+  /// Drop expired values, then the oldest ones until each map is back to three
+  /// quarters of its cap, so a full cache is not swept on every insert.
+  fn sweep(&self) {
+    let mut entries = self.entries.write();
+    let mut listings = self.listings.write();
+    entries.retain(|_, slot| self.is_fresh(slot.stamp));
+    listings.retain(|_, slot| self.is_fresh(slot.stamp));
+    evict_oldest(&mut entries, MAX_ENTRIES);
+    evict_oldest(&mut listings, MAX_LISTINGS);
+    trace!(entries = entries.len(), listings = listings.len(), "swept metadata cache");
+  }
+
+  fn is_fresh(&self, stamp: u64) -> bool {
+    now().saturating_sub(stamp) < self.ttl_ms
+  }
+}
+
+fn now() -> u64 {
+  chrono::Utc::now().timestamp_millis() as u64
+}
+
+/// Remove `key` and every path below it; an empty key is the root and clears the map.
+fn remove_tree<V>(cached: &mut BTreeMap<String, V>, key: &str) {
+  if key.is_empty() {
+    cached.clear();
+    return;
+  }
+  cached.remove(key);
+  // Paths below `key` sort between `key/` and `key0`, as '0' follows '/'.
+  let nested: Vec<String> = cached
+    .range(format!("{key}/")..format!("{key}0"))
+    .map(|(path, _)| path.clone())
+    .collect();
+  for path in nested {
+    cached.remove(&path);
+  }
+}
+
+/// Drop the least recently stored values until `cached` holds three quarters of `cap`.
+fn evict_oldest<V>(cached: &mut BTreeMap<String, Slot<V>>, cap: usize) {
+  let len = cached.len();
+  if len <= cap {
+    return;
+  }
+  let mut stored: Vec<(u64, String)> = cached.iter().map(|(path, slot)| (slot.seq, path.clone())).collect();
+  let excess = len - cap * 3 / 4;
+  stored.select_nth_unstable(excess - 1);
+  for (_, path) in &stored[..excess] {
+    cached.remove(path);
+  }
+}
+
+fn cache_key(path: &str) -> &str {
+  path.trim_matches('/')
+}
+
+fn parent_key(key: &str) -> &str {
+  key.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::fs::EntryKind;
+
+  fn entry(path: &str, kind: EntryKind) -> Entry {
+    Entry {
+      name: path.rsplit('/').next().unwrap_or(path).into(),
+      path: path.into(),
+      kind,
+      writable: true,
+      created: None,
+      last_modified: None,
+      size: Some(1),
+      mimetype: None,
+      hash: None,
+      hash_algorithm: None,
+    }
+  }
+
+  #[test]
+  fn listing_populates_entries_and_invalidates_parent() {
+    let cache = MetadataCache::new(Duration::from_secs(60));
+    let children = vec![entry("data/a.txt", EntryKind::File), entry("data/sub", EntryKind::Directory)];
+    cache.put_listing("/data", &children);
+    cache.put_entry("data/sub/b.txt", entry("data/sub/b.txt", EntryKind::File));
+
+    assert_eq!(cache.listing("data").map(|l| l.len()), Some(2));
+    assert!(cache.entry("/data/a.txt").is_some());
+
+    cache.invalidate("data/a.txt");
+    assert!(cache.entry("data/a.txt").is_none());
+    assert!(cache.listing("data").is_none());
+    assert!(cache.entry("data/sub").is_some());
+
+    cache.put_entry("data/subway", entry("data/subway", EntryKind::File));
+    cache.invalidate("data/sub");
+    assert!(cache.entry("data/sub/b.txt").is_none());
+    assert!(cache.entry("data/subway").is_some());
+  }
+
+  #[test]
+  fn expired_values_are_not_returned() {
+    let cache = MetadataCache::new(Duration::ZERO);
+    cache.put_entry("a.txt", entry("a.txt", EntryKind::File));
+    assert!(cache.entry("a.txt").is_none());
+  }
+
+  #[test]
+  fn sweeps_expired_values_and_caps_size() {
+    let expiring = MetadataCache::new(Duration::ZERO);
+    for name in ["a.txt", "b.txt", "c.txt"] {
+      expiring.put_entry(name, entry(name, EntryKind::File));
+    }
+    expiring.put_listing("data", &[entry("data/d.txt", EntryKind::File)]);
+    assert!(expiring.entries.read().is_empty() && expiring.listings.read().is_empty());
+
+    let cache = MetadataCache::new(Duration::from_secs(60));
+    for i in 0..=MAX_ENTRIES {
+      let path = format!("f{i}");
+      cache.put_entry(&path, entry(&path, EntryKind::File));
+      if i == MAX_ENTRIES / 2 {
+        // Storing a value again makes it the newest.
+        cache.put_entry("f0", entry("f0", EntryKind::File));
+      }
+    }
+    assert!(cache.entries.read().len() <= MAX_ENTRIES);
+    assert!(cache.entry("f1").is_none());
+    assert!(cache.entry("f0").is_some());
+    assert!(cache.entry(&format!("f{MAX_ENTRIES}")).is_some());
+  }
+}
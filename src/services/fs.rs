@@ -1,34 +1,180 @@
-use std::{fmt, pin::Pin, sync::Arc};
+use std::{fmt, future::Future, pin::Pin, sync::{Arc, OnceLock}, time::Duration};
 use std::io;
 use std::task::{Context, Poll};
 
 use base64::{engine::general_purpose::STANDARD, Engine as _};
 use chrono::{DateTime, Utc};
 use sha2::{Digest, Sha256};
-use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
-use tokio_util::io::StreamReader;
-use futures_util::TryStreamExt;
+use tokio::{
+  io::{AsyncRead, AsyncReadExt, AsyncWriteExt, DuplexStream, ReadBuf},
+  task::JoinHandle,
+};
+use tokio_util::{io::StreamReader, sync::{CancellationToken, DropGuard}};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use reqwest::StatusCode;
 
 use crate::api::{
-  client::{JupyterLabClient, ClientError}, jupyter::{JupyterApi, JupyterLabApi}, param::{ContentsEntryType, ContentsFormat, ContentsGetParams, RenameContentsModel, SaveContentsModel}, resp::{ContentValue, Contents}
+  client::{JupyterLabClient, ClientError}, jupyter::{JupyterApi, JupyterLabApi}, param::{ContentsEntryType, ContentsFormat, ContentsGetParams, CreateContentsModel, RenameContentsModel, SaveContentsModel}, resp::{Checkpoint, ContentValue, Contents}
+};
+use crate::services::{
+  cache::MetadataCache,
+  shell::{RemoteFileType, RemoteStat, ShellHelper},
+  terminal::TerminalError,
 };
 
+/// Maximum number of concurrent delete requests issued by [`FsService::rmdir_all`].
+const RMDIR_CONCURRENCY: usize = 8;
+
+/// Clock skew allowed by [`FsService::check_unmodified`], matching JupyterLab's editor.
+pub const CONFLICT_TOLERANCE: chrono::TimeDelta = chrono::TimeDelta::milliseconds(500);
+
 /// High-level convenience helpers for interacting with the Jupyter contents API
 /// using file system-like verbs.
 #[derive(Clone)]
 pub struct FsService {
   inner: Arc<JupyterLabClient>,
+  cache: Option<Arc<MetadataCache>>,
+  shell: Option<Arc<ShellHelper>>,
+  /// Whether the server accepts hidden paths (`ContentsManager.allow_hidden`), once known.
+  hidden_allowed: Arc<OnceLock<bool>>,
 }
 
 impl FsService {
   pub fn new(inner: Arc<JupyterLabClient>) -> Self {
-    Self { inner }
+    Self { inner, cache: None, shell: None, hidden_allowed: Arc::default() }
+  }
+
+  /// Cache `metadata` and `ls` results for `ttl`.
+  ///
+  /// Writes, renames and deletes issued through this service (and its clones)
+  /// invalidate the affected paths immediately.
+  pub fn with_cache(mut self, ttl: Duration) -> Self {
+    self.cache = Some(Arc::new(MetadataCache::new(ttl)));
+    self
+  }
+
+  /// Enable the terminal-backed fallback for operations the Contents API cannot do.
+  ///
+  /// The Contents API stays the primary path; the shell helper is used for
+  /// [`FsService::stat`], [`FsService::chmod`], [`FsService::symlink`] and for
+  /// downloading hidden files the server refuses to serve.
+  pub fn with_shell_fallback(mut self) -> Self {
+    self.shell = Some(Arc::new(ShellHelper::new(self.inner.clone())));
+    self
+  }
+
+  /// A service for the same server that sends its requests through `inner`,
+  /// e.g. a client carrying another user's token.
+  ///
+  /// The hidden-path probe result is shared with `self`, as it is a server setting.
+  /// The metadata cache starts empty with the same TTL, since another token may not
+  /// see the same files; the shell fallback is kept, bound to the new client.
+  pub fn with_client(&self, inner: Arc<JupyterLabClient>) -> Self {
+    let shell = self.shell.as_ref().map(|_| Arc::new(ShellHelper::new(inner.clone())));
+    let cache = self.cache.as_ref().map(|cache| Arc::new(MetadataCache::new(cache.ttl())));
+    Self { inner, cache, shell, hidden_allowed: self.hidden_allowed.clone() }
+  }
+
+  /// The client this service sends its requests through.
+  pub fn client(&self) -> &Arc<JupyterLabClient> {
+    &self.inner
+  }
+
+  /// The shell helper, if [`FsService::with_shell_fallback`] was enabled.
+  pub fn shell(&self) -> Option<&ShellHelper> {
+    self.shell.as_deref()
+  }
+
+
+  /// The configured shell helper, or a fresh one for operations that always need a terminal.
+  pub(crate) fn _shell_or_default(&self) -> ShellHelper {
+    match &self.shell {
+      Some(shell) => (**shell).clone(),
+      None => ShellHelper::new(self.inner.clone()),
+    }
+  }
+
+  /// Whether the server serves and accepts hidden paths such as `.git` or `.env`.
+  ///
+  /// `ContentsManager.allow_hidden` is not exposed through the config API, so this
+  /// asks the server to rename a hidden file that does not exist: the request is
+  /// refused with 400 when hidden paths are disallowed and fails to find the file
+  /// otherwise, leaving nothing behind either way. The answer is remembered for
+  /// this service and its clones.
+  #[tracing::instrument(skip(self))]
+  pub async fn allows_hidden(&self) -> Result<bool, FsError> {
+    if let Some(allowed) = self.hidden_allowed.get() {
+      return Ok(*allowed);
+    }
+    let probe = temp_sibling(".jupyter_shell_probe", false);
+    let rename = RenameContentsModel { path: format!("{probe}.renamed") };
+    let allowed = match self.inner.rename_contents(&probe, &rename).await {
+      Err(ClientError::Api { status, .. }) if status == StatusCode::BAD_REQUEST => false,
+      // Jupyter Server reports the missing source as 404, or as 500 from older releases.
+      Err(ClientError::Api { status, .. }) if status == StatusCode::NOT_FOUND || status.is_server_error() => true,
+      Ok(_) => {
+        // Only reachable if the unique probe name existed; put it back.
+        self.inner.rename_contents(&rename.path, &RenameContentsModel { path: probe }).await.ok();
+        true
+      }
+      Err(err) => return Err(err.into()),
+    };
+    debug!(allowed, "fs: probed hidden file support");
+    Ok(*self.hidden_allowed.get_or_init(|| allowed))
+  }
+
+  /// Turn a server rejection of a hidden path into [`FsError::HiddenNotAllowed`].
+  ///
+  /// Writes, renames and deletes of hidden paths fail with 400 when hidden files are
+  /// disallowed, and reads with 404; as both statuses have other causes, they are
+  /// only attributed to the policy when the message says so or
+  /// [`FsService::allows_hidden`] confirms it.
+  async fn _hidden_rejection(&self, err: FsError, paths: &[&str]) -> FsError {
+    let Some(hidden) = paths.iter().find(|path| is_hidden_path(path)) else {
+      return err;
+    };
+    let rejected = match &err {
+      FsError::Client(ClientError::Api { status, message })
+        if *status == StatusCode::BAD_REQUEST && message.to_ascii_lowercase().contains("hidden") =>
+      {
+        self.hidden_allowed.get_or_init(|| false);
+        true
+      }
+      FsError::Client(ClientError::Api { status, .. }) if *status == StatusCode::BAD_REQUEST => {
+        matches!(self.allows_hidden().await, Ok(false))
+      }
+      _ if err.is_not_found() => matches!(self.allows_hidden().await, Ok(false)),
+      _ => false,
+    };
+    if rejected {
+      trace!(path = %hidden, "server rejected hidden path");
+      FsError::HiddenNotAllowed(hidden.to_string())
+    } else {
+      err
+    }
+  }
+
+  /// Forget all cached metadata, if caching is enabled.
+  pub fn clear_cache(&self) {
+    if let Some(cache) = &self.cache {
+      cache.clear();
+    }
+  }
+
+  pub(crate) fn _invalidate(&self, path: &str) {
+    if let Some(cache) = &self.cache {
+      cache.invalidate(path);
+    }
   }
 
   /// List directory contents or return metadata for a single file.
   #[tracing::instrument(skip(self), fields(path = %path))]
   pub async fn ls(&self, path: &str) -> Result<Vec<Entry>, FsError> {
     debug!("fs: ls {}", path);
+    if let Some(entries) = self.cache.as_ref().and_then(|cache| cache.listing(path)) {
+      trace!(entry_count = entries.len(), "directory listing served from cache");
+      return Ok(entries);
+    }
     let mut params = ContentsGetParams::default();
     params.content = Some(true);
     let contents = self
@@ -41,7 +187,11 @@ impl FsService {
       return match contents.content {
         Some(ContentValue::Contents(entries)) => {
           trace!(entry_count = entries.len(), "directory listing resolved");
-          Ok(entries.into_iter().map(Entry::from).collect())
+          let entries: Vec<Entry> = entries.into_iter().map(Entry::from).collect();
+          if let Some(cache) = &self.cache {
+            cache.put_listing(path, &entries);
+          }
+          Ok(entries)
         }
         Some(ContentValue::Text(_)) => Err(FsError::InvalidPayload(contents.path)),
         None => Err(FsError::MissingContent(contents.path)),
@@ -49,22 +199,37 @@ impl FsService {
     }
 
     trace!("path is a file; returning metadata only");
-    Ok(vec![Entry::from(contents)])
+    let entry = Entry::from(contents);
+    if let Some(cache) = &self.cache {
+      cache.put_entry(path, entry.clone());
+    }
+    Ok(vec![entry])
   }
 
   /// Fetch metadata for a path without downloading its payload.
   #[tracing::instrument(skip(self), fields(path = %path))]
   pub async fn metadata(&self, path: &str) -> Result<Entry, FsError> {
     debug!("fs: metadata {}", path);
+    if let Some(entry) = self.cache.as_ref().and_then(|cache| cache.entry(path)) {
+      trace!(kind = ?entry.kind, "metadata served from cache");
+      return Ok(entry);
+    }
+    self._fetch_metadata(path).await
+  }
+
+  /// Fetch metadata from the server even when a cached copy exists, refreshing the cache.
+  async fn _fetch_metadata(&self, path: &str) -> Result<Entry, FsError> {
     let mut params = ContentsGetParams::default();
     params.content = Some(false);
-    let contents = self
-      .inner
-      .get_contents(path, Some(&params))
-      .await
-      .map_err(FsError::from)?;
+    let contents = match self.inner.get_contents(path, Some(&params)).await {
+      Ok(contents) => contents,
+      Err(err) => return Err(self._hidden_rejection(err.into(), &[path]).await),
+    };
     let entry = Entry::from(contents);
     trace!(kind = ?entry.kind, "metadata fetched");
+    if let Some(cache) = &self.cache {
+      cache.put_entry(path, entry.clone());
+    }
     Ok(entry)
   }
 
@@ -80,12 +245,12 @@ impl FsService {
     model.content = Some(encoded);
     model.chunk = chunk;
 
-    let contents = self
-      .inner
-      .save_contents(path, &model)
-      .await
-      .map_err(FsError::from)?;
-    Ok(Entry::from(contents))
+    let result = self.inner.save_contents(path, &model).await;
+    self._invalidate(path);
+    match result {
+      Ok(contents) => Ok(Entry::from(contents)),
+      Err(err) => Err(self._hidden_rejection(err.into(), &[path]).await),
+    }
   }
 
   fn _check_uploaded(&self, entry: &Entry, total_len: u64) -> Result<(), FsError> {
@@ -137,6 +302,338 @@ impl FsService {
     unreachable!()
   }
 
+  /// Append `data` to an existing file through chunked Contents API saves.
+  ///
+  /// Jupyter opens the file in append mode for every chunk after the first, so the
+  /// data is sent as chunks numbered from 2, ending with `-1`. The final size is
+  /// checked against the size the file had before.
+  #[tracing::instrument(skip(self, data), fields(path = %path, chunk_size = ?chunk_size))]
+  pub async fn append(&self, path: &str, data: impl AsRef<[u8]>, chunk_size: Option<u64>) -> Result<Entry, FsError> {
+    let data = data.as_ref();
+    debug!(len = data.len(), "fs: append {}", path);
+    let chunk_size = chunk_size.unwrap_or(data.len() as u64);
+    Ok(self.append_stream(path, data, chunk_size).await?.entry)
+  }
+
+  /// Upload everything `reader` yields as `path`, holding at most two chunks in memory.
+  ///
+  /// Input that fits in a single chunk is saved with one plain request; anything
+  /// larger goes through numbered chunked saves as it is read.
+  #[tracing::instrument(skip(self, reader), fields(path = %path, chunk_size = chunk_size))]
+  pub async fn upload_stream<R>(&self, path: &str, reader: R, chunk_size: u64) -> Result<StreamedUpload, FsError>
+  where
+    R: AsyncRead + Unpin,
+  {
+    debug!(chunk_size, "fs: upload_stream {}", path);
+    self._upload_stream(path, reader, chunk_size, None).await
+  }
+
+  /// Like [`FsService::append`], but reads the appended data from `reader` chunk by chunk.
+  #[tracing::instrument(skip(self, reader), fields(path = %path, chunk_size = chunk_size))]
+  pub async fn append_stream<R>(&self, path: &str, reader: R, chunk_size: u64) -> Result<StreamedUpload, FsError>
+  where
+    R: AsyncRead + Unpin,
+  {
+    debug!(chunk_size, "fs: append_stream {}", path);
+    let existing = self.metadata(path).await?;
+    if !existing.kind.is_file_like() {
+      return Err(FsError::NotAFile(existing.path));
+    }
+    self._upload_stream(path, reader, chunk_size, Some(existing.size.unwrap_or(0))).await
+  }
+
+  /// Streaming counterpart of [`FsService::upload_atomic`].
+  ///
+  /// The temporary sibling is hidden unless the server is known to reject hidden
+  /// paths, since a stream cannot be replayed to retry with a visible name.
+  #[tracing::instrument(skip(self, reader), fields(path = %path, chunk_size = chunk_size))]
+  pub async fn upload_stream_atomic<R>(&self, path: &str, reader: R, chunk_size: u64) -> Result<StreamedUpload, FsError>
+  where
+    R: AsyncRead + Unpin,
+  {
+    debug!(chunk_size, "fs: upload_stream_atomic {}", path);
+    let hidden = !matches!(self.allows_hidden().await, Ok(false)) || is_hidden_path(path);
+    let temp = temp_sibling(path, hidden);
+    let upload = match self._upload_stream(&temp, reader, chunk_size, None).await {
+      Ok(upload) => upload,
+      Err(err) => {
+        self.rm(&temp).await.ok();
+        return Err(err);
+      }
+    };
+    if let Err(err) = self._verify_digest(&temp, &upload.sha256).await {
+      self.rm(&temp).await.ok();
+      return Err(err);
+    }
+    let entry = self._swap_into_place(&temp, path).await?;
+    Ok(StreamedUpload { entry, ..upload })
+  }
+
+  /// Start an atomic upload to `path` that is fed through [`UploadPipe::write`].
+  ///
+  /// For frontends that receive a file as a series of writes rather than as a
+  /// reader; the upload runs on a background task until [`UploadPipe::finish`].
+  pub fn upload_pipe(&self, path: &str, chunk_size: u64) -> UploadPipe {
+    let (fs, path) = (self.clone(), path.to_string());
+    UploadPipe::spawn(move |reader| async move { fs.upload_stream_atomic(&path, reader, chunk_size).await })
+  }
+
+  /// Like [`FsService::upload_pipe`], but appends to the existing file at `path`.
+  pub fn append_pipe(&self, path: &str, chunk_size: u64) -> UploadPipe {
+    let (fs, path) = (self.clone(), path.to_string());
+    UploadPipe::spawn(move |reader| async move { fs.append_stream(&path, reader, chunk_size).await })
+  }
+
+  /// Streaming counterpart of [`FsService::upload_with_checkpoint`].
+  #[tracing::instrument(skip(self, reader), fields(path = %path, chunk_size = chunk_size))]
+  pub async fn upload_stream_with_checkpoint<R>(
+    &self,
+    path: &str,
+    reader: R,
+    chunk_size: u64,
+  ) -> Result<(StreamedUpload, Option<Checkpoint>), FsError>
+  where
+    R: AsyncRead + Unpin,
+  {
+    debug!(chunk_size, "fs: upload_stream_with_checkpoint {}", path);
+    let checkpoint = match self.metadata(path).await {
+      Ok(entry) if entry.kind.is_file_like() => Some(self.snapshot(path).await?),
+      Ok(entry) => return Err(FsError::NotAFile(entry.path)),
+      Err(err) if err.is_not_found() => None,
+      Err(err) => return Err(err),
+    };
+    match self._upload_stream(path, reader, chunk_size, None).await {
+      Ok(upload) => Ok((upload, checkpoint)),
+      Err(err) => {
+        if let Some(checkpoint) = &checkpoint {
+          warn!(error = %err, checkpoint = %checkpoint.id, "upload failed; restoring checkpoint");
+          self.restore(path, &checkpoint.id.to_string()).await.ok();
+        }
+        Err(err)
+      }
+    }
+  }
+
+  /// Save `reader` chunk by chunk, reading one chunk ahead to know which one is last.
+  ///
+  /// With `append_to` set, every chunk is appended to a file of that size; otherwise
+  /// the file is created or replaced.
+  async fn _upload_stream<R>(
+    &self,
+    path: &str,
+    mut reader: R,
+    chunk_size: u64,
+    append_to: Option<u64>,
+  ) -> Result<StreamedUpload, FsError>
+  where
+    R: AsyncRead + Unpin,
+  {
+    let chunk_size = chunk_size.max(1);
+    let mut hasher = Sha256::new();
+    let mut total = 0u64;
+    let mut current = Vec::new();
+    let mut next = Vec::new();
+    read_chunk(&mut reader, &mut current, chunk_size).await?;
+    let mut idx: isize = if append_to.is_some() { 2 } else { 1 };
+    loop {
+      read_chunk(&mut reader, &mut next, chunk_size).await?;
+      let is_last_chunk = next.is_empty();
+      let chunk = match (is_last_chunk, idx) {
+        (true, 1) => None,
+        (true, _) => Some(-1),
+        (false, idx) => Some(idx),
+      };
+      trace!(chunk = ?chunk, offset = total, len = current.len(), "uploading streamed chunk");
+      let entry = self._upload(path, &current, chunk).await?;
+      hasher.update(&current);
+      total += current.len() as u64;
+      if is_last_chunk {
+        self._check_uploaded(&entry, append_to.unwrap_or(0) + total)?;
+        return Ok(StreamedUpload { entry, bytes: total, sha256: format!("{:x}", hasher.finalize()) });
+      }
+      std::mem::swap(&mut current, &mut next);
+      idx += 1;
+    }
+  }
+
+  /// Fail with [`FsError::Conflict`] if `path` changed since `expected_last_modified`.
+  ///
+  /// Mirrors the JupyterLab editor's save check: the remote copy counts as changed
+  /// when its `last_modified` is more than [`CONFLICT_TOLERANCE`] newer than expected,
+  /// or when it no longer exists.
+  #[tracing::instrument(skip(self), fields(path = %path))]
+  pub async fn check_unmodified(&self, path: &str, expected_last_modified: DateTime<Utc>) -> Result<(), FsError> {
+    // A cached entry would hide exactly the concurrent edits this check exists to catch.
+    let actual = match self._fetch_metadata(path).await {
+      Ok(entry) => entry.last_modified,
+      Err(err) if err.is_not_found() => None,
+      Err(err) => return Err(err),
+    };
+    let changed = match actual {
+      Some(actual) => actual - expected_last_modified > CONFLICT_TOLERANCE,
+      None => true,
+    };
+    if changed {
+      debug!(expected = %expected_last_modified, actual = ?actual, "remote copy changed since it was read");
+      return Err(FsError::Conflict {
+        path: path.to_string(),
+        expected: expected_last_modified,
+        actual,
+      });
+    }
+    Ok(())
+  }
+
+  /// Upload `data` only if the remote copy still has the expected modification time.
+  #[tracing::instrument(skip(self, data), fields(path = %path))]
+  pub async fn upload_if_unmodified(
+    &self,
+    path: &str,
+    data: impl AsRef<[u8]>,
+    expected_last_modified: DateTime<Utc>,
+  ) -> Result<Entry, FsError> {
+    debug!(expected = %expected_last_modified, "fs: upload_if_unmodified {}", path);
+    self.check_unmodified(path, expected_last_modified).await?;
+    self.upload(path, data).await
+  }
+
+  /// Upload a file after checkpointing the copy it overwrites.
+  ///
+  /// If the upload fails part way, the checkpoint is restored so the destination is
+  /// never left truncated. Returns the checkpoint taken, if the file already existed.
+  ///
+  /// This writes in place rather than through [`FsService::upload_atomic`]: Jupyter
+  /// moves and deletes checkpoints together with their file, so a rename-based swap
+  /// would discard the snapshot.
+  #[tracing::instrument(skip(self, data), fields(path = %path, chunk_size = ?chunk_size))]
+  pub async fn upload_with_checkpoint(
+    &self,
+    path: &str,
+    data: impl AsRef<[u8]>,
+    chunk_size: Option<u64>,
+  ) -> Result<(Entry, Option<Checkpoint>), FsError> {
+    let data = data.as_ref();
+    debug!(len = data.len(), "fs: upload_with_checkpoint {}", path);
+    let checkpoint = match self.metadata(path).await {
+      Ok(entry) if entry.kind.is_file_like() => Some(self.snapshot(path).await?),
+      Ok(entry) => return Err(FsError::NotAFile(entry.path)),
+      Err(err) if err.is_not_found() => None,
+      Err(err) => return Err(err),
+    };
+    let result = match chunk_size {
+      Some(chunk_size) if (data.len() as u64) > chunk_size => self.upload_chunked(path, data, chunk_size).await,
+      _ => self.upload(path, data).await,
+    };
+    match result {
+      Ok(entry) => Ok((entry, checkpoint)),
+      Err(err) => {
+        if let Some(checkpoint) = &checkpoint {
+          warn!(error = %err, checkpoint = %checkpoint.id, "upload failed; restoring checkpoint");
+          self.restore(path, &checkpoint.id.to_string()).await.ok();
+        }
+        Err(err)
+      }
+    }
+  }
+
+  /// Upload a file without ever exposing a partially written destination.
+  ///
+  /// The payload is written to a hidden temporary sibling (or a visible one when
+  /// the server rejects hidden paths), its size and SHA-256 are verified, and it is
+  /// then renamed over `path`. An existing destination is moved aside first and
+  /// restored if the final rename fails.
+  #[tracing::instrument(skip(self, data), fields(path = %path, chunk_size = ?chunk_size))]
+  pub async fn upload_atomic(&self, path: &str, data: impl AsRef<[u8]>, chunk_size: Option<u64>) -> Result<Entry, FsError> {
+    let data = data.as_ref();
+    debug!(len = data.len(), "fs: upload_atomic {}", path);
+    let write = |temp: String| async move {
+      let result = match chunk_size {
+        Some(chunk_size) if (data.len() as u64) > chunk_size => self.upload_chunked(&temp, data, chunk_size).await,
+        _ => self.upload(&temp, data).await,
+      };
+      (temp, result)
+    };
+
+    let (temp, result) = match write(temp_sibling(path, true)).await {
+      (_, Err(FsError::HiddenNotAllowed(_))) if !is_hidden_path(path) => {
+        trace!("server rejected hidden temporary path; retrying with a visible one");
+        write(temp_sibling(path, false)).await
+      }
+      other => other,
+    };
+    if let Err(err) = result {
+      self.rm(&temp).await.ok();
+      return Err(err);
+    }
+
+    if let Err(err) = self._verify_sha256(&temp, data).await {
+      self.rm(&temp).await.ok();
+      return Err(err);
+    }
+    self._swap_into_place(&temp, path).await
+  }
+
+  async fn _verify_sha256(&self, path: &str, data: &[u8]) -> Result<(), FsError> {
+    self._verify_digest(path, &format!("{:x}", Sha256::digest(data))).await
+  }
+
+  async fn _verify_digest(&self, path: &str, expected: &str) -> Result<(), FsError> {
+    match self.remote_hashsum(path).await {
+      Ok((algorithm, digest)) if algorithm.eq_ignore_ascii_case("sha256") => {
+        if !digest.eq_ignore_ascii_case(expected) {
+          return Err(FsError::ChecksumMismatch {
+            path: path.to_string(),
+            expected: expected.to_string(),
+            actual: digest,
+          });
+        }
+        trace!("server sha256 matches uploaded payload");
+      }
+      Ok((algorithm, _)) => trace!(hash_algorithm = %algorithm, "server hash is not sha256; relying on size check"),
+      Err(err) => trace!(error = ?err, "server hash unavailable; relying on size check"),
+    }
+    Ok(())
+  }
+
+  /// Rename the uploaded `temp` over `path`, moving an existing destination aside first.
+  ///
+  /// The backup is named after `temp`, whose visibility already matches what the
+  /// server accepts, so overwrites work when hidden files are disallowed. `temp` is
+  /// removed whenever the swap fails.
+  async fn _swap_into_place(&self, temp: &str, path: &str) -> Result<Entry, FsError> {
+    let backup = match self.metadata(path).await {
+      Ok(_) => {
+        let backup = format!("{temp}.bak");
+        if let Err(err) = self.rename(path, &backup).await {
+          self.rm(temp).await.ok();
+          return Err(err);
+        }
+        Some(backup)
+      }
+      Err(err) if err.is_not_found() => None,
+      Err(err) => {
+        self.rm(temp).await.ok();
+        return Err(err);
+      }
+    };
+    match self.rename(temp, path).await {
+      Ok(entry) => {
+        if let Some(backup) = backup {
+          self.rm(&backup).await.ok();
+        }
+        Ok(entry)
+      }
+      Err(err) => {
+        warn!(error = %err, "failed to move temporary upload into place");
+        if let Some(backup) = backup {
+          self.rename(&backup, path).await.ok();
+        }
+        self.rm(temp).await.ok();
+        Err(err)
+      }
+    }
+  }
+
   /// Download a remote file/notebook and return its bytes along with metadata.
   #[tracing::instrument(skip(self), fields(path = %path))]
   pub async fn _download_use_contents(&self, path: &str) -> Result<FileContent, FsError> {
@@ -166,6 +663,25 @@ impl FsService {
     Ok(FileContent { entry, bytes })
   }
 
+  /// Download via the contents endpoint, falling back to the shell helper when the
+  /// server refuses the path (e.g. hidden files with `allow_hidden` off).
+  async fn _download_use_contents_or_shell(&self, path: &str) -> Result<FileContent, FsError> {
+    let err = match self._download_use_contents(path).await {
+      Ok(content) => return Ok(content),
+      Err(err) => err,
+    };
+    let Some(shell) = self.shell.as_deref() else {
+      return Err(self._hidden_rejection(err, &[path]).await);
+    };
+    trace!(error = ?err, "contents download failed; falling back to shell helper");
+    let stat = shell.stat(path).await.map_err(|_| err)?;
+    if stat.kind != RemoteFileType::File {
+      return Err(FsError::NotAFile(stat.path));
+    }
+    let bytes = shell.read(path).await?;
+    Ok(FileContent { entry: Entry::from_stat(&stat), bytes })
+  }
+
   #[tracing::instrument(skip(self), fields(path = %path, range = ?range))]
   pub async fn _download_use_files(&self, path: &str, range: Option<(u64, Option<u64>)>) -> Result<Vec<u8>, FsError> {
     trace!("downloading via /files endpoint");
@@ -180,9 +696,18 @@ impl FsService {
   ) -> Result<Box<dyn AsyncRead + Send + Sync + Unpin>, FsError> {
     trace!("streaming via /files endpoint");
     let response = self.inner.get_files_stream(path, range).await?;
+    let client = self.inner.clone();
     let stream = response
       .bytes_stream()
-      .map_err(|err| io::Error::new(io::ErrorKind::Other, err));
+      .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+      .and_then(move |chunk| {
+        let client = client.clone();
+        async move {
+          client.consume(chunk.len() as u64).await;
+          Ok(chunk)
+        }
+      });
+    let stream: Pin<Box<dyn futures_util::Stream<Item = io::Result<_>> + Send + Sync>> = Box::pin(stream);
     Ok(Box::new(StreamReader::new(stream)))
   }
 
@@ -196,7 +721,7 @@ impl FsService {
       return Ok(FileContent { entry, bytes: payload } );
     }
     trace!("falling back to contents fallback download");
-    self._download_use_contents(path).await
+    self._download_use_contents_or_shell(path).await
   }
 
   #[tracing::instrument(skip(self), fields(path = %path))]
@@ -216,7 +741,7 @@ impl FsService {
       }
       Err(err) => {
         trace!(error = ?err, "streaming via /files failed; falling back to contents endpoint");
-        let FileContent { entry, mut bytes } = self._download_use_contents(path).await?;
+        let FileContent { entry, mut bytes } = self._download_use_contents_or_shell(path).await?;
         if start_pos > 0 {
           let offset = usize::try_from(start_pos).map_err(|_| {
             FsError::InvalidPayload(format!(
@@ -316,16 +841,65 @@ impl FsService {
     Ok(digest)
   }
 
+  /// List checkpoints for a file, oldest first.
+  #[tracing::instrument(skip(self), fields(path = %path))]
+  pub async fn checkpoints(&self, path: &str) -> Result<Vec<Checkpoint>, FsError> {
+    let mut checkpoints = self.inner.list_checkpoints(path).await?;
+    checkpoints.sort_by_key(|checkpoint| checkpoint.last_modified);
+    trace!(count = checkpoints.len(), "checkpoints listed");
+    Ok(checkpoints)
+  }
+
+  /// Record the current contents of a file as a new checkpoint.
+  #[tracing::instrument(skip(self), fields(path = %path))]
+  pub async fn snapshot(&self, path: &str) -> Result<Checkpoint, FsError> {
+    debug!("fs: snapshot {}", path);
+    Ok(self.inner.create_checkpoint(path).await?)
+  }
+
+  /// Roll a file back to the given checkpoint.
+  #[tracing::instrument(skip(self), fields(path = %path, checkpoint = %checkpoint_id))]
+  pub async fn restore(&self, path: &str, checkpoint_id: &str) -> Result<(), FsError> {
+    debug!("fs: restore {} to {}", path, checkpoint_id);
+    let result = self.inner.restore_checkpoint(path, checkpoint_id).await;
+    self._invalidate(path);
+    Ok(result?)
+  }
+
+  /// Roll a file back to its most recent checkpoint and return that checkpoint.
+  #[tracing::instrument(skip(self), fields(path = %path))]
+  pub async fn restore_latest(&self, path: &str) -> Result<Checkpoint, FsError> {
+    let latest = self
+      .checkpoints(path)
+      .await?
+      .pop()
+      .ok_or_else(|| FsError::NoCheckpoint(path.to_string()))?;
+    self.restore(path, &latest.id.to_string()).await?;
+    Ok(latest)
+  }
+
+  /// Delete all but the newest `keep` checkpoints of a file, returning how many were removed.
+  #[tracing::instrument(skip(self), fields(path = %path, keep = keep))]
+  pub async fn prune(&self, path: &str, keep: usize) -> Result<usize, FsError> {
+    let checkpoints = self.checkpoints(path).await?;
+    let excess = checkpoints.len().saturating_sub(keep);
+    for checkpoint in &checkpoints[..excess] {
+      trace!(checkpoint = %checkpoint.id, "deleting checkpoint");
+      self.inner.delete_checkpoint(path, &checkpoint.id.to_string()).await?;
+    }
+    Ok(excess)
+  }
+
   /// Remove a file or directory from the Jupyter server.
   #[tracing::instrument(skip(self), fields(path = %path))]
   pub async fn rm(&self, path: &str) -> Result<(), FsError> {
     trace!("deleting entry via contents API");
-    self
-      .inner
-      .delete_contents(path)
-      .await
-      .map_err(FsError::from)?;
-    Ok(())
+    let result = self.inner.delete_contents(path).await;
+    self._invalidate(path);
+    match result {
+      Ok(()) => Ok(()),
+      Err(err) => Err(self._hidden_rejection(err.into(), &[path]).await),
+    }
   }
 
   /// Create a directory at the provided fully-qualified Jupyter path.
@@ -334,12 +908,12 @@ impl FsService {
     trace!("creating directory");
     let mut model = SaveContentsModel::default();
     model.entry_type = Some(ContentsEntryType::Directory);
-    let contents = self
-      .inner
-      .save_contents(path, &model)
-      .await
-      .map_err(FsError::from)?;
-    Ok(Entry::from(contents))
+    let result = self.inner.save_contents(path, &model).await;
+    self._invalidate(path);
+    match result {
+      Ok(contents) => Ok(Entry::from(contents)),
+      Err(err) => Err(self._hidden_rejection(err.into(), &[path]).await),
+    }
   }
 
   /// Rename or move an entry to a new path.
@@ -349,20 +923,155 @@ impl FsService {
     let payload = RenameContentsModel {
       path: trim_leading_slash(to).to_string(),
     };
-    let contents = self
-      .inner
-      .rename_contents(from, &payload)
-      .await
-      .map_err(FsError::from)?;
-    Ok(Entry::from(contents))
+    let result = self.inner.rename_contents(from, &payload).await;
+    self._invalidate(from);
+    self._invalidate(to);
+    match result {
+      Ok(contents) => Ok(Entry::from(contents)),
+      Err(err) => Err(self._hidden_rejection(err.into(), &[from, to]).await),
+    }
+  }
+
+  /// Copy a file to `to` on the server, without transferring its content.
+  ///
+  /// The Contents API copies into the destination directory under a generated
+  /// name (`name-Copy1.ext`), which is then renamed to `to` and removed if that
+  /// fails. Directories cannot be copied this way.
+  #[tracing::instrument(skip(self), fields(from = %from, to = %to))]
+  pub async fn copy(&self, from: &str, to: &str) -> Result<Entry, FsError> {
+    debug!("fs: copy {} {}", from, to);
+    let target = trim_leading_slash(to);
+    let parent = target.rsplit_once('/').map_or("", |(parent, _)| parent);
+    let model = CreateContentsModel {
+      copy_from: Some(trim_leading_slash(from).to_string()),
+      ..Default::default()
+    };
+    let copied = match self.inner.create_contents(parent, &model).await {
+      Ok(contents) => Entry::from(contents),
+      Err(err) => return Err(self._hidden_rejection(err.into(), &[from, to]).await),
+    };
+    self._invalidate(&copied.path);
+    if copied.path == target {
+      return Ok(copied);
+    }
+    match self.rename(&copied.path, target).await {
+      Ok(entry) => Ok(entry),
+      Err(err) => {
+        // Don't leave the generated copy behind, e.g. when `to` already exists.
+        warn!(error = %err, "failed to move copy into place");
+        self.rm(&copied.path).await.ok();
+        Err(err)
+      }
+    }
+  }
+
+  /// Like [`FsService::copy`], but replaces an existing file at `to`.
+  ///
+  /// The copy is made under a temporary sibling and swapped into place, so `to`
+  /// keeps its old content if the copy fails.
+  #[tracing::instrument(skip(self), fields(from = %from, to = %to))]
+  pub async fn copy_atomic(&self, from: &str, to: &str) -> Result<Entry, FsError> {
+    debug!("fs: copy_atomic {} {}", from, to);
+    let hidden = !matches!(self.allows_hidden().await, Ok(false)) || is_hidden_path(to);
+    let temp = temp_sibling(to, hidden);
+    self.copy(from, &temp).await?;
+    self._swap_into_place(&temp, to).await
+  }
+
+  /// Real mode, owner and symlink target of `path`, as reported by the shell fallback.
+  #[tracing::instrument(skip(self), fields(path = %path))]
+  pub async fn stat(&self, path: &str) -> Result<RemoteStat, FsError> {
+    debug!("fs: stat {}", path);
+    self.shell().ok_or_else(|| shell_required("stat"))?.stat(path).await
+  }
+
+  /// Shell-backed directory listing that includes hidden entries and symlinks.
+  #[tracing::instrument(skip(self), fields(path = %path))]
+  pub async fn stat_dir(&self, path: &str) -> Result<Vec<RemoteStat>, FsError> {
+    debug!("fs: stat_dir {}", path);
+    self.shell().ok_or_else(|| shell_required("stat_dir"))?.list(path).await
+  }
+
+  /// Change the permission bits of `path` (e.g. `0o644`).
+  #[tracing::instrument(skip(self), fields(path = %path, mode = format!("{mode:o}")))]
+  pub async fn chmod(&self, path: &str, mode: u32) -> Result<(), FsError> {
+    debug!("fs: chmod {:o} {}", mode, path);
+    let result = self.shell().ok_or_else(|| shell_required("chmod"))?.chmod(path, mode).await;
+    self._invalidate(path);
+    result
+  }
+
+  /// Set the modification times of remote paths, e.g. to mirror local files after an upload.
+  #[tracing::instrument(skip(self, stamps), fields(count = stamps.len()))]
+  pub async fn touch(&self, stamps: &[(String, DateTime<Utc>)]) -> Result<(), FsError> {
+    debug!("fs: touch {} paths", stamps.len());
+    let result = self.shell().ok_or_else(|| shell_required("touch"))?.touch(stamps).await;
+    for (path, _) in stamps {
+      self._invalidate(path);
+    }
+    result
+  }
+
+  /// Create a symlink at `link` pointing to `target`.
+  #[tracing::instrument(skip(self), fields(target = %target, link = %link))]
+  pub async fn symlink(&self, target: &str, link: &str) -> Result<(), FsError> {
+    debug!("fs: symlink {} -> {}", link, target);
+    let result = self.shell().ok_or_else(|| shell_required("symlink"))?.symlink(target, link).await;
+    self._invalidate(link);
+    result
+  }
+
+  /// Create a directory along with any missing ancestors, like `mkdir -p`.
+  ///
+  /// Existing directories along the way are left untouched; a plain file in the
+  /// way yields [`FsError::NotADirectory`].
+  #[tracing::instrument(skip(self), fields(path = %path))]
+  pub async fn mkdir_all(&self, path: &str) -> Result<Entry, FsError> {
+    debug!("fs: mkdir_all {}", path);
+    let target = trim_leading_slash(path);
+    match self.metadata(target).await {
+      Ok(entry) if entry.kind.is_directory() => return Ok(entry),
+      Ok(entry) => return Err(FsError::NotADirectory(entry.path)),
+      Err(err) if err.is_not_found() => {}
+      Err(err) => return Err(err),
+    }
+
+    let mut current = String::new();
+    let mut last = None;
+    for segment in target.split('/').filter(|segment| !segment.is_empty()) {
+      if !current.is_empty() {
+        current.push('/');
+      }
+      current.push_str(segment);
+      let entry = match self.metadata(&current).await {
+        Ok(entry) if entry.kind.is_directory() => entry,
+        Ok(entry) => return Err(FsError::NotADirectory(entry.path)),
+        Err(err) if err.is_not_found() => {
+          trace!(ancestor = %current, "creating missing directory");
+          self.mkdir(&current).await?
+        }
+        Err(err) => return Err(err),
+      };
+      last = Some(entry);
+    }
+    match last {
+      Some(entry) => Ok(entry),
+      None => self.metadata(target).await,
+    }
   }
 
   /// Remove a directory after verifying the target is not a plain file.
+  ///
+  /// With `recursive` set, the directory contents are removed first via
+  /// [`FsService::rmdir_all`]; otherwise non-empty directories are rejected.
   #[tracing::instrument(skip(self), fields(path = %path, recursive = recursive))]
   pub async fn rmdir(&self, path: &str, recursive: bool) -> Result<(), FsError> {
     debug!(recursive, "fs: rmdir {}", path);
+    if recursive {
+      return self.rmdir_all(path).await;
+    }
     let mut params = ContentsGetParams::default();
-    params.content = Some(!recursive);
+    params.content = Some(true);
     let metadata = self
       .inner
       .get_contents(path, Some(&params))
@@ -377,11 +1086,40 @@ impl FsService {
         metadata.path
       )));
     }
-    self
-      .inner
-      .delete_contents(path)
-      .await
-      .map_err(FsError::from)
+    self.rm(path).await
+  }
+
+  /// Remove a directory and everything below it, like `rm -r`.
+  ///
+  /// The tree is walked depth-first; files within a directory are deleted with
+  /// at most [`RMDIR_CONCURRENCY`] requests in flight, and each directory is
+  /// removed once its children are gone.
+  #[tracing::instrument(skip(self), fields(path = %path))]
+  pub async fn rmdir_all(&self, path: &str) -> Result<(), FsError> {
+    debug!("fs: rmdir_all {}", path);
+    let entry = self.metadata(path).await?;
+    if !entry.kind.is_directory() {
+      return Err(FsError::NotADirectory(entry.path));
+    }
+    self._remove_tree(path).await
+  }
+
+  fn _remove_tree<'a>(&'a self, path: &'a str) -> Pin<Box<dyn Future<Output = Result<(), FsError>> + Send + 'a>> {
+    Box::pin(async move {
+      let children = self.ls(path).await?;
+      let (dirs, files): (Vec<_>, Vec<_>) = children.into_iter().partition(|child| child.kind.is_directory());
+      trace!(path = %path, dirs = dirs.len(), files = files.len(), "removing directory contents");
+
+      stream::iter(files)
+        .map(|file| async move { self.rm(&file.path).await })
+        .buffer_unordered(RMDIR_CONCURRENCY)
+        .try_collect::<Vec<_>>()
+        .await?;
+      for dir in dirs {
+        self._remove_tree(&dir.path).await?;
+      }
+      self.rm(path).await
+    })
   }
 }
 
@@ -455,6 +1193,120 @@ impl Entry {
       hash_algorithm,
     }
   }
+
+  pub(crate) fn from_stat(stat: &RemoteStat) -> Self {
+    Entry {
+      name: stat.name().to_string(),
+      path: stat.path.clone(),
+      kind: match stat.kind {
+        RemoteFileType::Directory => EntryKind::Directory,
+        RemoteFileType::File => EntryKind::File,
+        RemoteFileType::Symlink => EntryKind::Other("symlink".into()),
+        RemoteFileType::Other(kind) => EntryKind::Other(kind.to_string()),
+      },
+      writable: stat.mode & 0o200 != 0,
+      created: None,
+      last_modified: stat.modified,
+      size: Some(stat.size),
+      mimetype: None,
+      hash: None,
+      hash_algorithm: None,
+    }
+  }
+}
+
+/// Outcome of a streamed upload such as [`FsService::upload_stream`].
+#[derive(Debug, Clone)]
+pub struct StreamedUpload {
+  pub entry: Entry,
+  /// Bytes read from the stream (for appends, only the appended part).
+  pub bytes: u64,
+  /// Hex-encoded SHA-256 of the streamed bytes.
+  pub sha256: String,
+}
+
+/// Capacity of the in-memory pipe between [`UploadPipe::write`] and its upload task.
+const UPLOAD_PIPE_CAPACITY: usize = 256 * 1024;
+
+/// A streamed upload running in the background, created by [`FsService::upload_pipe`]
+/// or [`FsService::append_pipe`].
+pub struct UploadPipe {
+  /// Cancels the upload unless it is finished; declared before `pipe` so it is
+  /// dropped first and the upload task never mistakes the drop for the end of the data.
+  abort: DropGuard,
+  pipe: DuplexStream,
+  task: JoinHandle<Result<StreamedUpload, FsError>>,
+  written: u64,
+}
+
+impl UploadPipe {
+  fn spawn<F, Fut>(upload: F) -> Self
+  where
+    F: FnOnce(PipeReader) -> Fut,
+    Fut: Future<Output = Result<StreamedUpload, FsError>> + Send + 'static,
+  {
+    let (pipe, reader) = tokio::io::duplex(UPLOAD_PIPE_CAPACITY);
+    let aborted = CancellationToken::new();
+    let task = tokio::spawn(upload(PipeReader { pipe: reader, aborted: aborted.clone() }));
+    Self { abort: aborted.drop_guard(), pipe, task, written: 0 }
+  }
+
+  /// Bytes written so far.
+  pub fn written(&self) -> u64 {
+    self.written
+  }
+
+  /// Append `data` to the upload, waiting while the upload task catches up.
+  ///
+  /// Fails once the upload task has stopped; [`UploadPipe::finish`] then reports why.
+  pub async fn write(&mut self, data: &[u8]) -> Result<(), FsError> {
+    self.pipe.write_all(data).await?;
+    self.written += data.len() as u64;
+    Ok(())
+  }
+
+  /// Mark the end of the data and wait for the upload to complete.
+  pub async fn finish(self) -> Result<StreamedUpload, FsError> {
+    let Self { abort, mut pipe, task, .. } = self;
+    pipe.shutdown().await.ok();
+    drop(pipe);
+    let result = task.await;
+    abort.disarm();
+    match result {
+      Ok(result) => result,
+      Err(err) => Err(FsError::Io(io::Error::other(err))),
+    }
+  }
+
+  /// Stop the upload without completing it.
+  ///
+  /// The upload task sees a read error and cleans up in the background, so an
+  /// atomic upload removes its temporary file. Dropping the pipe does the same.
+  pub fn abort(self) {
+    drop(self);
+  }
+}
+
+/// The upload task's end of an [`UploadPipe`].
+struct PipeReader {
+  pipe: DuplexStream,
+  aborted: CancellationToken,
+}
+
+impl AsyncRead for PipeReader {
+  fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+    if self.aborted.is_cancelled() {
+      return Poll::Ready(Err(io::Error::other("upload aborted")));
+    }
+    Pin::new(&mut self.pipe).poll_read(cx, buf)
+  }
+}
+
+/// Replace `buf` with up to `limit` bytes from `reader`; it stays empty at end of input.
+async fn read_chunk<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut Vec<u8>, limit: u64) -> io::Result<()> {
+  buf.clear();
+  reader.take(limit).read_to_end(buf).await?;
+  Ok(())
 }
 
 pub struct FileDownload {
@@ -503,6 +1355,10 @@ pub struct FileContent {
   pub bytes: Vec<u8>,
 }
 
+fn shell_required(feature: &str) -> FsError {
+  FsError::NotImplemented(format!("{feature} requires the shell fallback"))
+}
+
 fn decode_file_bytes(format: Option<&str>, payload: ContentValue) -> Result<Vec<u8>, FsError> {
   match payload {
     ContentValue::Text(data) => match format.unwrap_or("text") {
@@ -517,6 +1373,33 @@ fn decode_file_bytes(format: Option<&str>, payload: ContentValue) -> Result<Vec<
   }
 }
 
+/// Build a unique temporary sibling of `path`, e.g. `dir/.name.upload-<id>`.
+pub(crate) fn temp_sibling(path: &str, hidden: bool) -> String {
+  let trimmed = path.trim_matches('/');
+  let (parent, name) = match trimmed.rsplit_once('/') {
+    Some((parent, name)) => (Some(parent), name),
+    None => (None, trimmed),
+  };
+  let nanos = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+  let id = format!("{:x}{:x}", nanos, std::process::id());
+  let file = if hidden {
+    format!(".{name}.upload-{id}")
+  } else {
+    format!("{name}.upload-{id}.partial")
+  };
+  match parent {
+    Some(parent) => format!("{parent}/{file}"),
+    None => file,
+  }
+}
+
+/// Whether any segment of `path` is a dotfile, which Jupyter treats as hidden.
+pub fn is_hidden_path(path: &str) -> bool {
+  path
+    .split('/')
+    .any(|segment| segment.starts_with('.') && segment != "." && segment != "..")
+}
+
 fn trim_leading_slash(path: &str) -> &str {
   let trimmed = path.trim_start_matches('/');
   if trimmed.is_empty() {
@@ -535,6 +1418,26 @@ pub enum FsError {
   InvalidPayload(String),
   Decode(base64::DecodeError),
   NotImplemented(String),
+  NoCheckpoint(String),
+  /// The server hides dotfiles (`allow_hidden` is off) and refused this path.
+  HiddenNotAllowed(String),
+  Terminal(Box<TerminalError>),
+  /// A local file system operation failed.
+  Io(std::io::Error),
+  /// A shell helper exited with a non-zero status.
+  Command { status: i32, output: String },
+  /// The server's sha256 of an uploaded file differs from the local digest.
+  ChecksumMismatch {
+    path: String,
+    expected: String,
+    actual: String,
+  },
+  /// The remote file changed after the caller last read it.
+  Conflict {
+    path: String,
+    expected: DateTime<Utc>,
+    actual: Option<DateTime<Utc>>,
+  },
 }
 
 impl fmt::Display for FsError {
@@ -547,15 +1450,53 @@ impl fmt::Display for FsError {
       FsError::InvalidPayload(reason) => write!(f, "invalid payload: {reason}"),
       FsError::Decode(err) => write!(f, "failed to decode file payload: {err}"),
       FsError::NotImplemented(feature) => write!(f, "not implemented: {feature}"),
+      FsError::NoCheckpoint(path) => write!(f, "no checkpoints exist for {path}"),
+      FsError::HiddenNotAllowed(path) => write!(f, "{path} is hidden and the server does not allow hidden files"),
+      FsError::Terminal(err) => write!(f, "terminal error: {err}"),
+      FsError::Io(err) => write!(f, "local io error: {err}"),
+      FsError::Command { status, output } => write!(f, "shell helper exited with status {status}: {output}"),
+      FsError::ChecksumMismatch { path, expected, actual } => {
+        write!(f, "sha256 mismatch for {path}: expected {expected}, got {actual}")
+      }
+      FsError::Conflict { path, expected, actual: Some(actual) } => {
+        write!(f, "{path} was modified on the server at {actual} (expected {expected})")
+      }
+      FsError::Conflict { path, .. } => write!(f, "{path} was removed from the server"),
     }
   }
 }
 
+impl FsError {
+  /// Whether the server reported that the requested path does not exist.
+  pub fn is_not_found(&self) -> bool {
+    matches!(self, FsError::Client(ClientError::Api { status, .. }) if *status == StatusCode::NOT_FOUND)
+  }
+
+  /// Whether a terminal-backed operation failed because the server would not open
+  /// a terminal: 404 when terminals are disabled, 403 when this user may not use them.
+  ///
+  /// Other terminal failures, such as timeouts, are real errors rather than a
+  /// reason to fall back to the Contents API.
+  pub fn is_terminal_unavailable(&self) -> bool {
+    let status = match self {
+      FsError::Client(ClientError::Api { status, .. }) => *status,
+      FsError::Terminal(err) => match err.as_ref() {
+        TerminalError::Client(ClientError::Api { status, .. }) => *status,
+        _ => return false,
+      },
+      _ => return false,
+    };
+    matches!(status, StatusCode::NOT_FOUND | StatusCode::FORBIDDEN)
+  }
+}
+
 impl std::error::Error for FsError {
   fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
     match self {
       FsError::Client(err) => Some(err),
       FsError::Decode(err) => Some(err),
+      FsError::Terminal(err) => Some(err.as_ref()),
+      FsError::Io(err) => Some(err),
       _ => None,
     }
   }
@@ -567,6 +1508,18 @@ impl From<ClientError> for FsError {
   }
 }
 
+impl From<TerminalError> for FsError {
+  fn from(value: TerminalError) -> Self {
+    FsError::Terminal(Box::new(value))
+  }
+}
+
+impl From<std::io::Error> for FsError {
+  fn from(value: std::io::Error) -> Self {
+    FsError::Io(value)
+  }
+}
+
 impl From<base64::DecodeError> for FsError {
   fn from(value: base64::DecodeError) -> Self {
     FsError::Decode(value)
@@ -618,6 +1571,31 @@ mod tests {
     assert_eq!(bytes, b"123");
   }
 
+  #[test]
+  fn temp_sibling_stays_in_parent_directory() {
+    let hidden = temp_sibling("/data/report.csv", true);
+    assert!(hidden.starts_with("data/.report.csv.upload-"));
+    let visible = temp_sibling("report.csv", false);
+    assert!(visible.starts_with("report.csv.upload-") && visible.ends_with(".partial"));
+  }
+
+  #[test]
+  fn only_refused_terminals_count_as_unavailable() {
+    let api = |status| ClientError::Api { status, message: String::new() };
+    assert!(FsError::from(api(StatusCode::NOT_FOUND)).is_terminal_unavailable());
+    assert!(FsError::from(TerminalError::Client(api(StatusCode::FORBIDDEN))).is_terminal_unavailable());
+    assert!(!FsError::from(api(StatusCode::INTERNAL_SERVER_ERROR)).is_terminal_unavailable());
+    assert!(!FsError::from(TerminalError::Timeout(Duration::from_secs(1))).is_terminal_unavailable());
+  }
+
+  #[test]
+  fn hidden_paths_match_any_dot_segment() {
+    assert!(is_hidden_path(".env"));
+    assert!(is_hidden_path("repo/.git/config"));
+    assert!(!is_hidden_path("./data/../report.csv"));
+    assert!(!is_hidden_path("data/report.v1.csv"));
+  }
+
   #[test]
   fn decode_text_payload_to_bytes() {
     let bytes = decode_file_bytes(Some("text"), ContentValue::Text("hello".into())).unwrap();
@@ -657,6 +1635,200 @@ mod tests {
     fs.rm("chunked.txt").await.unwrap();
   }
 
+  #[tokio::test]
+  async fn test_upload_stream() {
+    let client = crate::api::client::tests::_setup_client();
+    let fs = FsService::new(Arc::new(client));
+
+    fs.rm("streamed.txt").await.ok();
+    let data = b"The quick brown fox jumps over the lazy dog".to_vec();
+    let upload = fs.upload_stream_atomic("streamed.txt", data.as_slice(), 10).await.unwrap();
+    assert_eq!(upload.bytes, data.len() as u64);
+    assert_eq!(upload.sha256, format!("{:x}", Sha256::digest(&data)));
+    let appended = fs.append_stream("streamed.txt", &b"!!"[..], 1).await.unwrap();
+    assert_eq!(appended.entry.size, Some(data.len() as u64 + 2));
+    assert!(fs.download("streamed.txt").await.unwrap().bytes.ends_with(b"dog!!"));
+    fs.rm("streamed.txt").await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_append() {
+    let client = crate::api::client::tests::_setup_client();
+    let fs = FsService::new(Arc::new(client));
+
+    fs.rm("append.txt").await.ok();
+    fs.upload("append.txt", "head-").await.unwrap();
+    let entry = fs.append("append.txt", "and the tail", Some(4)).await.unwrap();
+    assert_eq!(entry.size, Some(17));
+    let download = fs.download("append.txt").await.unwrap();
+    assert_eq!(download.bytes, b"head-and the tail");
+    fs.rm("append.txt").await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_upload_atomic_replaces_existing() {
+    let client = crate::api::client::tests::_setup_client();
+    let fs = FsService::new(Arc::new(client));
+
+    fs.rm("atomic.txt").await.ok();
+    fs.upload_atomic("atomic.txt", "first", None).await.unwrap();
+    fs.upload_atomic("atomic.txt", "second version", Some(4)).await.unwrap();
+    let download = fs.download("atomic.txt").await.unwrap();
+    assert_eq!(download.bytes, b"second version");
+    let names: Vec<String> = fs.ls("/").await.unwrap().into_iter().map(|e| e.name).collect();
+    assert!(!names.iter().any(|name| name.contains(".upload-")));
+    fs.rm("atomic.txt").await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_upload_stream_atomic_overwrites_without_hidden_files() {
+    let client = crate::api::client::tests::_setup_client();
+    let fs = FsService::new(Arc::new(client));
+
+    // Jupyter disallows hidden files by default; the backup must not need them.
+    fs.rmdir("atomic_dir", true).await.ok();
+    fs.mkdir_all("atomic_dir").await.unwrap();
+    fs.upload("atomic_dir/data.csv", "a,b\n1,2\n").await.unwrap();
+    let upload = fs.upload_stream_atomic("atomic_dir/data.csv", &b"a,b\n3,4\n"[..], 4).await.unwrap();
+    assert_eq!(upload.bytes, 8);
+    assert_eq!(fs.download("atomic_dir/data.csv").await.unwrap().bytes, b"a,b\n3,4\n");
+    let names: Vec<String> = fs.ls("atomic_dir").await.unwrap().into_iter().map(|e| e.name).collect();
+    assert_eq!(names, ["data.csv"]);
+    fs.rmdir("atomic_dir", true).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_copy() {
+    let client = crate::api::client::tests::_setup_client();
+    let fs = FsService::new(Arc::new(client));
+
+    fs.rmdir("copy_dir", true).await.ok();
+    fs.mkdir_all("copy_dir/nested").await.unwrap();
+    fs.upload("copy_dir/source.txt", "copied bytes").await.unwrap();
+    let copy = fs.copy("copy_dir/source.txt", "copy_dir/nested/target.txt").await.unwrap();
+    assert_eq!(copy.path, "copy_dir/nested/target.txt");
+    assert_eq!(fs.download("copy_dir/nested/target.txt").await.unwrap().bytes, b"copied bytes");
+    assert_eq!(fs.download("copy_dir/source.txt").await.unwrap().bytes, b"copied bytes");
+
+    // A failed rename must not leave the generated copy behind.
+    fs.copy("copy_dir/source.txt", "copy_dir/nested/target.txt").await.unwrap_err();
+    let names: Vec<String> = fs.ls("copy_dir/nested").await.unwrap().into_iter().map(|e| e.name).collect();
+    assert_eq!(names, ["target.txt"]);
+
+    fs.upload("copy_dir/other.txt", "other bytes").await.unwrap();
+    let copy = fs.copy_atomic("copy_dir/other.txt", "copy_dir/nested/target.txt").await.unwrap();
+    assert_eq!(copy.path, "copy_dir/nested/target.txt");
+    assert_eq!(fs.download("copy_dir/nested/target.txt").await.unwrap().bytes, b"other bytes");
+    let names: Vec<String> = fs.ls("copy_dir/nested").await.unwrap().into_iter().map(|e| e.name).collect();
+    assert_eq!(names, ["target.txt"]);
+    fs.rmdir("copy_dir", true).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_aborted_upload_pipe_removes_its_temp_file() {
+    let client = crate::api::client::tests::_setup_client();
+    let fs = FsService::new(Arc::new(client));
+
+    fs.rmdir("pipe_dir", true).await.ok();
+    fs.mkdir_all("pipe_dir").await.unwrap();
+    let mut pipe = fs.upload_pipe("pipe_dir/data.bin", 4);
+    pipe.write(b"partial data").await.unwrap();
+    let names = async || -> Vec<String> { fs.ls("pipe_dir").await.unwrap().into_iter().map(|e| e.name).collect() };
+    for _ in 0..50 {
+      if !names().await.is_empty() {
+        break;
+      }
+      tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(names().await.len(), 1);
+    pipe.abort();
+    // The upload task cleans up in the background.
+    for _ in 0..50 {
+      if names().await.is_empty() {
+        break;
+      }
+      tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(names().await, Vec::<String>::new());
+    fs.rmdir("pipe_dir", true).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_upload_if_unmodified_detects_conflict() {
+    let client = crate::api::client::tests::_setup_client();
+    let fs = FsService::new(Arc::new(client));
+
+    fs.rm("conflict.txt").await.ok();
+    let first = fs.upload("conflict.txt", "one").await.unwrap();
+    let seen = first.last_modified.unwrap();
+    fs.upload_if_unmodified("conflict.txt", "two", seen).await.unwrap();
+
+    let stale = seen - chrono::TimeDelta::seconds(10);
+    let err = fs.upload_if_unmodified("conflict.txt", "three", stale).await.unwrap_err();
+    assert!(matches!(err, FsError::Conflict { .. }));
+    assert_eq!(fs.download("conflict.txt").await.unwrap().bytes, b"two");
+    fs.rm("conflict.txt").await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_check_unmodified_bypasses_cache() {
+    let cached = FsService::new(Arc::new(crate::api::client::tests::_setup_client())).with_cache(Duration::from_secs(60));
+    let other = FsService::new(Arc::new(crate::api::client::tests::_setup_client()));
+
+    other.rm("cached_conflict.txt").await.ok();
+    other.upload("cached_conflict.txt", "one").await.unwrap();
+    let seen = cached.metadata("cached_conflict.txt").await.unwrap().last_modified.unwrap();
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    other.upload("cached_conflict.txt", "edited elsewhere").await.unwrap();
+
+    let err = cached.check_unmodified("cached_conflict.txt", seen).await.unwrap_err();
+    assert!(matches!(err, FsError::Conflict { .. }));
+    other.rm("cached_conflict.txt").await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_checkpoint_snapshot_and_restore() {
+    let client = crate::api::client::tests::_setup_client();
+    let fs = FsService::new(Arc::new(client));
+
+    fs.rm("checkpointed.txt").await.ok();
+    fs.upload("checkpointed.txt", "original").await.unwrap();
+    let (_, checkpoint) = fs.upload_with_checkpoint("checkpointed.txt", "replacement", None).await.unwrap();
+    assert!(checkpoint.is_some());
+    assert_eq!(fs.download("checkpointed.txt").await.unwrap().bytes, b"replacement");
+
+    fs.restore_latest("checkpointed.txt").await.unwrap();
+    assert_eq!(fs.download("checkpointed.txt").await.unwrap().bytes, b"original");
+    fs.prune("checkpointed.txt", 0).await.unwrap();
+    assert!(fs.checkpoints("checkpointed.txt").await.unwrap().is_empty());
+    fs.rm("checkpointed.txt").await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_shell_fallback_chmod_and_symlink() {
+    let client = crate::api::client::tests::_setup_client();
+    let fs = FsService::new(Arc::new(client));
+    assert!(matches!(fs.stat("shell_target.txt").await, Err(FsError::NotImplemented(_))));
+
+    let fs = fs.with_shell_fallback();
+    fs.rm("shell_link.txt").await.ok();
+    fs.upload("shell_target.txt", "target").await.unwrap();
+    fs.chmod("shell_target.txt", 0o600).await.unwrap();
+    assert_eq!(fs.stat("shell_target.txt").await.unwrap().mode, 0o600);
+
+    let stamp = DateTime::from_timestamp(1_600_000_000, 0).unwrap();
+    fs.touch(&[("shell_target.txt".to_string(), stamp)]).await.unwrap();
+    assert_eq!(fs.metadata("shell_target.txt").await.unwrap().last_modified, Some(stamp));
+
+    fs.symlink("shell_target.txt", "shell_link.txt").await.unwrap();
+    let link = fs.stat("shell_link.txt").await.unwrap();
+    assert_eq!(link.kind, RemoteFileType::Symlink);
+    assert_eq!(link.symlink_target.as_deref(), Some("shell_target.txt"));
+
+    fs.rm("shell_link.txt").await.unwrap();
+    fs.rm("shell_target.txt").await.unwrap();
+  }
+
   #[tokio::test]
   async fn test_dir() {
     let client = crate::api::client::tests::_setup_client();
@@ -680,4 +1852,21 @@ mod tests {
     fs.rm("test_dir/file.txt").await.unwrap();
     fs.rmdir("test_dir", false).await.unwrap(); // should succeed now
   }
+
+  #[tokio::test]
+  async fn test_mkdir_all_and_rmdir_recursive() {
+    let client = crate::api::client::tests::_setup_client();
+    let fs = FsService::new(Arc::new(client));
+
+    fs.rmdir("nested_dir", true).await.ok();
+    let leaf = fs.mkdir_all("nested_dir/a/b").await.unwrap();
+    assert!(leaf.kind.is_directory());
+    fs.mkdir_all("nested_dir/a/b").await.unwrap(); // already exists
+    fs.upload("nested_dir/a/one.txt", "1").await.unwrap();
+    fs.upload("nested_dir/a/b/two.txt", "2").await.unwrap();
+
+    fs.rmdir("nested_dir", false).await.unwrap_err();
+    fs.rmdir("nested_dir", true).await.unwrap();
+    assert!(fs.metadata("nested_dir").await.unwrap_err().is_not_found());
+  }
 }
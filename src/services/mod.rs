@@ -1,2 +1,7 @@
 pub mod fs;
 pub mod terminal;
+pub mod walk;
+pub mod cache;
+pub mod shell;
+pub mod bulk;
+pub mod search;
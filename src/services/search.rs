@@ -0,0 +1,334 @@
+use chrono::{DateTime, Utc};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use regex::Regex;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::{
+  fs::{Entry, FsError, FsService},
+  shell::{parse_stat_line, shell_path, shell_quote, STAT_FORMAT},
+  walk::{match_segment, WalkOptions},
+};
+
+/// Number of files searched at once by the client-side [`FsService::grep`] fallback.
+const SEARCH_CONCURRENCY: usize = 4;
+
+/// A line matched by [`FsService::grep`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrepMatch {
+  pub path: String,
+  /// 1-based line number.
+  pub line: u64,
+  pub text: String,
+}
+
+/// Which kind of entry [`FsService::find`] should return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FindKind {
+  File,
+  Directory,
+}
+
+/// Conditions an entry must meet to be returned by [`FsService::find`].
+#[derive(Debug, Clone, Default)]
+pub struct FindPredicates {
+  name: Option<String>,
+  kind: Option<FindKind>,
+  min_size: Option<u64>,
+  max_size: Option<u64>,
+  max_depth: Option<usize>,
+  modified_after: Option<DateTime<Utc>>,
+}
+
+impl FindPredicates {
+  /// Match entry names against a shell-style pattern such as `*.csv`.
+  pub fn name(mut self, pattern: impl Into<String>) -> Self {
+    self.name = Some(pattern.into());
+    self
+  }
+
+  pub fn kind(mut self, kind: FindKind) -> Self {
+    self.kind = Some(kind);
+    self
+  }
+
+  /// Only files of at least `bytes` bytes.
+  pub fn min_size(mut self, bytes: u64) -> Self {
+    self.min_size = Some(bytes);
+    self
+  }
+
+  /// Only files of at most `bytes` bytes.
+  pub fn max_size(mut self, bytes: u64) -> Self {
+    self.max_size = Some(bytes);
+    self
+  }
+
+  /// Limit how deep the search descends; `1` considers only the direct children of the root.
+  pub fn max_depth(mut self, depth: usize) -> Self {
+    self.max_depth = Some(depth);
+    self
+  }
+
+  pub fn modified_after(mut self, stamp: DateTime<Utc>) -> Self {
+    self.modified_after = Some(stamp);
+    self
+  }
+
+  /// Equivalent `find` arguments, placed after the starting point.
+  fn to_find_args(&self) -> String {
+    let mut args = vec!["-mindepth 1".to_string()];
+    if let Some(depth) = self.max_depth {
+      args.push(format!("-maxdepth {depth}"));
+    }
+    match self.kind {
+      Some(FindKind::File) => args.push("-type f".into()),
+      Some(FindKind::Directory) => args.push("-type d".into()),
+      None => {}
+    }
+    if let Some(name) = &self.name {
+      args.push(format!("-name {}", shell_quote(name)));
+    }
+    if let Some(min) = self.min_size.filter(|min| *min > 0) {
+      args.push(format!("-size +{}c", min - 1));
+    }
+    if let Some(max) = self.max_size {
+      args.push(format!("-size -{}c", max + 1));
+    }
+    if let Some(stamp) = self.modified_after {
+      args.push(format!("-newermt @{}", stamp.timestamp()));
+    }
+    args.join(" ")
+  }
+
+  fn matches(&self, entry: &Entry) -> bool {
+    let kind_ok = match self.kind {
+      Some(FindKind::File) => entry.kind.is_file_like(),
+      Some(FindKind::Directory) => entry.kind.is_directory(),
+      None => true,
+    };
+    let size = entry.size.unwrap_or(0);
+    kind_ok
+      && self.name.as_deref().is_none_or(|pattern| match_segment(pattern, &entry.name))
+      && self.min_size.is_none_or(|min| !entry.kind.is_directory() && size >= min)
+      && self.max_size.is_none_or(|max| !entry.kind.is_directory() && size <= max)
+      && self
+        .modified_after
+        .is_none_or(|stamp| entry.last_modified.is_some_and(|modified| modified > stamp))
+  }
+}
+
+impl FsService {
+  /// Search files below `root` for lines matching the regular expression `pattern`.
+  ///
+  /// `pattern` uses the Perl-style syntax of the [`regex`] crate (`\d`, `\w`,
+  /// `\b`, `(?i)`, `a{2,3}`, ...); lookaround and backreferences are rejected.
+  /// It runs as `grep -rnIP` in a Jupyter terminal, so both sides read it the
+  /// same way. When terminals are disabled on the server, or its `grep` lacks
+  /// `-P`, files are streamed and searched client-side instead (hidden files are
+  /// then skipped, as the Contents API does not list them). Matches are sorted by
+  /// path and line.
+  #[tracing::instrument(skip(self), fields(pattern = %pattern, root = %root))]
+  pub async fn grep(&self, pattern: &str, root: &str) -> Result<Vec<GrepMatch>, FsError> {
+    debug!("fs: grep {} {}", pattern, root);
+    let regex = Regex::new(pattern)
+      .map_err(|err| FsError::InvalidPayload(format!("invalid pattern {pattern:?}: {err}")))?;
+    let mut matches = match self._grep_shell(pattern, root).await {
+      Ok(Some(matches)) => matches,
+      Ok(None) => {
+        debug!("grep on the server lacks -P; searching client-side");
+        self._grep_streamed(&regex, root).await?
+      }
+      Err(err) if err.is_terminal_unavailable() => {
+        debug!(error = %err, "terminals unavailable; searching client-side");
+        self._grep_streamed(&regex, root).await?
+      }
+      Err(err) => return Err(err),
+    };
+    matches.sort_by(|a, b| a.path.cmp(&b.path).then(a.line.cmp(&b.line)));
+    trace!(matches = matches.len(), "grep finished");
+    Ok(matches)
+  }
+
+  /// List entries below `root` that satisfy `predicates`, sorted by path.
+  ///
+  /// Runs `find` in a Jupyter terminal, falling back to a concurrent Contents API
+  /// walk when terminals are disabled.
+  #[tracing::instrument(skip(self), fields(root = %root))]
+  pub async fn find(&self, root: &str, predicates: &FindPredicates) -> Result<Vec<Entry>, FsError> {
+    debug!("fs: find {} {:?}", root, predicates);
+    let script = format!(
+      "find {} {} -printf '{STAT_FORMAT}'",
+      shell_path(root),
+      predicates.to_find_args()
+    );
+    let mut entries: Vec<Entry> = match self._shell_or_default().run_checked(&script).await {
+      Ok(output) => output
+        .lines()
+        .filter_map(parse_stat_line)
+        .map(|stat| Entry::from_stat(&stat))
+        .collect(),
+      Err(err) if err.is_terminal_unavailable() => {
+        debug!(error = %err, "terminals unavailable; walking the Contents API");
+        let mut options = WalkOptions::default();
+        if let Some(depth) = predicates.max_depth {
+          options = options.max_depth(depth);
+        }
+        self
+          .walk_with(root, options)
+          .try_filter(|entry| futures_util::future::ready(predicates.matches(entry)))
+          .try_collect()
+          .await?
+      }
+      Err(err) => return Err(err),
+    };
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+  }
+
+  /// Returns `None` when the server's `grep` cannot run Perl-style patterns.
+  async fn _grep_shell(&self, pattern: &str, root: &str) -> Result<Option<Vec<GrepMatch>>, FsError> {
+    let script = format!("grep -rnIP --null -e {} -- {}", shell_quote(pattern), shell_path(root));
+    let output = self._shell_or_default().run(&script).await?;
+    let matches = parse_grep_output(&output.output);
+    // grep exits with 1 when nothing matched and 2 on errors such as unreadable files.
+    if output.status > 1 && matches.is_empty() {
+      if lacks_perl_regex(&output.output) {
+        return Ok(None);
+      }
+      return Err(FsError::Command { status: output.status, output: output.output.trim().to_string() });
+    }
+    Ok(Some(matches))
+  }
+
+  async fn _grep_streamed(&self, regex: &Regex, root: &str) -> Result<Vec<GrepMatch>, FsError> {
+    let entry = self.metadata(root).await?;
+    let files: Vec<Entry> = if entry.kind.is_directory() {
+      self
+        .walk(root)
+        .try_filter(|entry| futures_util::future::ready(entry.kind.is_file_like()))
+        .try_collect()
+        .await?
+    } else {
+      vec![entry]
+    };
+    let per_file: Vec<Vec<GrepMatch>> = stream::iter(files)
+      .map(|entry| async move { self._grep_file(regex, &entry.path).await })
+      .buffer_unordered(SEARCH_CONCURRENCY)
+      .try_collect()
+      .await?;
+    Ok(per_file.into_iter().flatten().collect())
+  }
+
+  /// Stream one file line by line, skipping it entirely once it looks binary (like `grep -I`).
+  async fn _grep_file(&self, regex: &Regex, path: &str) -> Result<Vec<GrepMatch>, FsError> {
+    let download = self.download_reader(path).await?;
+    let mut reader = BufReader::new(download.reader);
+    let mut matches = Vec::new();
+    let mut buf = Vec::new();
+    let mut line = 0u64;
+    loop {
+      buf.clear();
+      if reader.read_until(b'\n', &mut buf).await? == 0 {
+        break;
+      }
+      if buf.contains(&0) {
+        trace!(%path, "skipping binary file");
+        return Ok(Vec::new());
+      }
+      line += 1;
+      let text = String::from_utf8_lossy(&buf);
+      let text = text.trim_end_matches(['\n', '\r']);
+      if regex.is_match(text) {
+        matches.push(GrepMatch { path: path.trim_start_matches('/').to_string(), line, text: text.to_string() });
+      }
+    }
+    Ok(matches)
+  }
+}
+
+/// Parse `grep -n --null` output (`path\0line:text`) into matches relative to the server root.
+fn parse_grep_output(output: &str) -> Vec<GrepMatch> {
+  output
+    .lines()
+    .filter_map(|record| {
+      let (path, rest) = record.split_once('\0')?;
+      let (line, text) = rest.split_once(':')?;
+      Some(GrepMatch {
+        path: path.trim_start_matches("./").to_string(),
+        line: line.parse().ok()?,
+        text: text.to_string(),
+      })
+    })
+    .collect()
+}
+
+/// Whether grep's error output says `-P` is not available, as with BusyBox, BSD
+/// grep, or GNU grep built without PCRE.
+fn lacks_perl_regex(output: &str) -> bool {
+  output.lines().any(|line| {
+    line.contains("-P option")
+      || line.contains("option -- P")
+      || line.contains("option: P")
+      || line.contains("option -- 'P'")
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn grep_output_splits_on_null_and_first_colon() {
+    let matches = parse_grep_output("./data/a.txt\u{0}3:key: value\nnoise without separator\n./b:c.txt\u{0}10:x\n");
+    assert_eq!(
+      matches,
+      vec![
+        GrepMatch { path: "data/a.txt".into(), line: 3, text: "key: value".into() },
+        GrepMatch { path: "b:c.txt".into(), line: 10, text: "x".into() },
+      ]
+    );
+  }
+
+  #[test]
+  fn grep_without_perl_regex_support_is_detected() {
+    assert!(lacks_perl_regex("grep: support for the -P option is not compiled into this --disable-perl-regexp binary"));
+    assert!(lacks_perl_regex("grep: unrecognized option: P\nBusyBox v1.36.1 multi-call binary."));
+    assert!(lacks_perl_regex("grep: invalid option -- P\nusage: grep [-abcdDEFGHhIiJLlMmnOopqRSsUVvwXxZz]"));
+    assert!(!lacks_perl_regex("grep: data/secret.txt: Permission denied"));
+  }
+
+  #[test]
+  fn find_predicates_translate_to_find_arguments() {
+    let predicates = FindPredicates::default()
+      .name("*.csv")
+      .kind(FindKind::File)
+      .min_size(10)
+      .max_size(100)
+      .max_depth(2);
+    assert_eq!(
+      predicates.to_find_args(),
+      "-mindepth 1 -maxdepth 2 -type f -name '*.csv' -size +9c -size -101c"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_grep_and_find() {
+    let client = crate::api::client::tests::_setup_client();
+    let fs = FsService::new(std::sync::Arc::new(client));
+
+    fs.rmdir("search_dir", true).await.ok();
+    fs.mkdir_all("search_dir/nested").await.unwrap();
+    fs.upload("search_dir/a.txt", "alpha\nneedle here\n").await.unwrap();
+    fs.upload("search_dir/nested/b.csv", "needle,1\n").await.unwrap();
+
+    let matches = fs.grep("needle", "search_dir").await.unwrap();
+    assert_eq!(matches.len(), 2);
+    assert_eq!(matches[0], GrepMatch { path: "search_dir/a.txt".into(), line: 2, text: "needle here".into() });
+
+    let csv = fs.find("search_dir", &FindPredicates::default().name("*.csv")).await.unwrap();
+    assert_eq!(csv.len(), 1);
+    assert_eq!(csv[0].path, "search_dir/nested/b.csv");
+
+    fs.rmdir("search_dir", true).await.unwrap();
+  }
+}
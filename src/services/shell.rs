@@ -0,0 +1,424 @@
+use std::{sync::Arc, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt, stream};
+
+use crate::{
+  api::{client::JupyterLabClient, jupyter::JupyterApi},
+  fs::FsError,
+  services::terminal::{InputMessage, OutputMessage, TerminalError, TerminalService},
+};
+
+const DEFAULT_SHELL_TIMEOUT: Duration = Duration::from_secs(30);
+const SENTINEL: &str = "__JUPYTER_SHELL";
+/// Paths updated per terminal session by [`ShellHelper::touch`].
+const TOUCH_BATCH: usize = 256;
+
+/// `find -printf` format shared by [`ShellHelper::stat`] and [`ShellHelper::list`]:
+/// mode, uid, gid, owner, group, size, mtime, type, link target, path.
+pub(crate) const STAT_FORMAT: &str = r"%m\t%U\t%G\t%u\t%g\t%s\t%T@\t%y\t%l\t%p\n";
+
+/// Runs POSIX shell helpers in short-lived Jupyter terminals.
+///
+/// Used as a fallback for what the Contents API cannot express: real modes and
+/// owners, symlinks, `chmod`, and hidden files when `allow_hidden` is off.
+/// Each call opens its own terminal and deletes it afterwards. Paths are taken
+/// relative to the terminal's starting directory, which is the server root.
+#[derive(Clone)]
+pub struct ShellHelper {
+  client: Arc<JupyterLabClient>,
+  timeout: Duration,
+}
+
+/// Output of a script run through [`ShellHelper::run`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShellOutput {
+  pub status: i32,
+  /// Combined stdout and stderr with terminal line endings normalized to `\n`.
+  pub output: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteFileType {
+  File,
+  Directory,
+  Symlink,
+  Other(char),
+}
+
+/// File metadata reported by the remote shell rather than the Contents API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteStat {
+  pub path: String,
+  pub kind: RemoteFileType,
+  pub mode: u32,
+  pub uid: u32,
+  pub gid: u32,
+  pub owner: String,
+  pub group: String,
+  pub size: u64,
+  pub modified: Option<DateTime<Utc>>,
+  pub symlink_target: Option<String>,
+}
+
+impl RemoteStat {
+  pub fn name(&self) -> &str {
+    self.path.rsplit('/').next().unwrap_or(&self.path)
+  }
+}
+
+impl ShellHelper {
+  pub fn new(client: Arc<JupyterLabClient>) -> Self {
+    Self { client, timeout: DEFAULT_SHELL_TIMEOUT }
+  }
+
+  pub fn timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    self
+  }
+
+  /// Run `script` in a fresh terminal and capture its output and exit status.
+  ///
+  /// The script output is framed by sentinel lines so prompts, the echoed command
+  /// line and shell startup noise can be told apart from the actual output.
+  #[tracing::instrument(skip(self, script))]
+  pub async fn run(&self, script: &str) -> Result<ShellOutput, FsError> {
+    let id = frame_id();
+    let command = frame_script(script, &id);
+    trace!(script = %script, "running shell helper");
+
+    let terminal = self.client.create_terminal(None).await?;
+    let service = TerminalService::connect((*self.client).clone(), &terminal.name, false).await;
+    let result = match service {
+      Ok(service) => service.call(&command, Some(self.timeout)).await,
+      Err(err) => Err(err),
+    };
+    self.client.delete_terminal(&terminal.name).await.ok();
+    let stdout = result?.stdout;
+    parse_framed_output(&stdout, &id)
+      .ok_or_else(|| FsError::InvalidPayload(format!("shell helper output was not framed as expected: {stdout:?}")))
+  }
+
+  /// Run `script` and type `input` into its stdin, followed by end-of-file.
+  ///
+  /// Echo is disabled and input is only sent once the script is running, so the
+  /// chunks never show up in the captured output. Every chunk must consist of
+  /// complete lines shorter than the terminal's 4096-byte line limit.
+  #[tracing::instrument(skip(self, script, input))]
+  pub async fn run_with_input<I>(&self, script: &str, input: I) -> Result<ShellOutput, FsError>
+  where
+    I: IntoIterator<Item = String>,
+  {
+    self.run_with_input_stream(script, stream::iter(input.into_iter().map(Ok))).await
+  }
+
+  /// Like [`ShellHelper::run_with_input`], but types each chunk as `input` yields it,
+  /// so the input never has to be held in memory as a whole.
+  ///
+  /// An error from `input` stops the script; the terminal is closed without
+  /// sending end-of-file.
+  #[tracing::instrument(skip(self, script, input))]
+  pub async fn run_with_input_stream<S>(&self, script: &str, input: S) -> Result<ShellOutput, FsError>
+  where
+    S: Stream<Item = Result<String, FsError>> + Unpin,
+  {
+    let id = frame_id();
+    let command = format!("stty -echo; {}", frame_script(script, &id));
+    trace!(script = %script, "running shell helper with input");
+
+    let terminal = self.client.create_terminal(None).await?;
+    let result = self._stream_input(&terminal.name, &command, &id, input).await;
+    self.client.delete_terminal(&terminal.name).await.ok();
+    let stdout = result?;
+    parse_framed_output(&stdout, &id)
+      .ok_or_else(|| FsError::InvalidPayload(format!("shell helper output was not framed as expected: {stdout:?}")))
+  }
+
+  async fn _stream_input<S>(&self, terminal: &str, command: &str, id: &str, mut input: S) -> Result<String, FsError>
+  where
+    S: Stream<Item = Result<String, FsError>> + Unpin,
+  {
+    let mut service = TerminalService::connect((*self.client).clone(), terminal, false).await?;
+    service.send_message(InputMessage::Stdin(format!("{command}\n"))).await?;
+
+    let begin = format!("{SENTINEL}_BEGIN_{id}");
+    let mut stdout = String::new();
+    let wait_for_begin = async {
+      while !stdout.contains(&begin) {
+        match service.read_message().await? {
+          Some(OutputMessage::Stdout(data)) => stdout.push_str(&data),
+          Some(OutputMessage::Init {}) => {}
+          Some(OutputMessage::Disconnect(_)) | None => break,
+        }
+      }
+      Ok::<(), TerminalError>(())
+    };
+    tokio::time::timeout(self.timeout, wait_for_begin)
+      .await
+      .map_err(|_| TerminalError::Timeout(self.timeout))??;
+
+    let mut chunks = 0usize;
+    while let Some(chunk) = input.next().await {
+      service.send_message(InputMessage::Stdin(chunk?)).await?;
+      chunks += 1;
+    }
+    trace!(chunks, "sent shell helper input");
+    // Ctrl-D at the start of a line ends the script's stdin; `call` then exits the shell.
+    let rest = service.call("\u{4}", Some(self.timeout)).await?;
+    stdout.push_str(&rest.stdout);
+    Ok(stdout)
+  }
+
+  /// Run `script` and pass each line of its output to `on_line` as it arrives.
+  ///
+  /// For output too large to capture whole, such as an encoded archive. Lines are
+  /// stripped of terminal line endings; the script's exit status is returned.
+  #[tracing::instrument(skip(self, script, on_line))]
+  pub async fn run_with_output_lines(
+    &self,
+    script: &str,
+    on_line: impl AsyncFnMut(&str) -> Result<(), FsError>,
+  ) -> Result<i32, FsError> {
+    let id = frame_id();
+    let command = format!("stty -echo; {}\n", frame_script(script, &id));
+    trace!(script = %script, "running shell helper with streamed output");
+
+    let terminal = self.client.create_terminal(None).await?;
+    let result = tokio::time::timeout(self.timeout, self._stream_output(&terminal.name, &command, &id, on_line)).await;
+    self.client.delete_terminal(&terminal.name).await.ok();
+    match result {
+      Ok(result) => result,
+      Err(_) => Err(TerminalError::Timeout(self.timeout).into()),
+    }
+  }
+
+  async fn _stream_output(
+    &self,
+    terminal: &str,
+    command: &str,
+    id: &str,
+    mut on_line: impl AsyncFnMut(&str) -> Result<(), FsError>,
+  ) -> Result<i32, FsError> {
+    let mut service = TerminalService::connect((*self.client).clone(), terminal, false).await?;
+    service.send_message(InputMessage::Stdin(command.to_string())).await?;
+
+    let begin = format!("{SENTINEL}_BEGIN_{id}");
+    let end = format!("{SENTINEL}_END_{id}_");
+    let mut pending = String::new();
+    let mut started = false;
+    loop {
+      match service.read_message().await? {
+        Some(OutputMessage::Stdout(data)) => pending.push_str(&data),
+        Some(OutputMessage::Init {}) => continue,
+        Some(OutputMessage::Disconnect(_)) | None => {
+          return Err(FsError::InvalidPayload("the terminal closed before the shell helper finished".into()));
+        }
+      }
+      while let Some(newline) = pending.find('\n') {
+        let line: String = pending.drain(..=newline).collect();
+        let line = line.trim_end_matches(['\r', '\n']);
+        if !started {
+          // Skip the echoed command line and any shell startup noise; the marker can
+          // follow control sequences such as bash's bracketed-paste toggles.
+          started = line.ends_with(&begin);
+        } else if let Some((output, status)) = line.split_once(&end) {
+          // Output without a trailing newline shares the line with the marker.
+          if !output.is_empty() {
+            on_line(output).await?;
+          }
+          return status
+            .trim()
+            .parse()
+            .map_err(|_| FsError::InvalidPayload(format!("unexpected shell helper status {status:?}")));
+        } else {
+          on_line(line).await?;
+        }
+      }
+    }
+  }
+
+  /// Like [`ShellHelper::run`], but treats a non-zero exit status as an error.
+  pub async fn run_checked(&self, script: &str) -> Result<String, FsError> {
+    let output = self.run(script).await?;
+    if output.status != 0 {
+      return Err(FsError::Command {
+        status: output.status,
+        output: output.output.trim().to_string(),
+      });
+    }
+    Ok(output.output)
+  }
+
+  /// Stat a single path without following symlinks.
+  #[tracing::instrument(skip(self), fields(path = %path))]
+  pub async fn stat(&self, path: &str) -> Result<RemoteStat, FsError> {
+    let script = format!("find {} -maxdepth 0 -printf '{STAT_FORMAT}'", shell_path(path));
+    let output = self.run_checked(&script).await?;
+    output
+      .lines()
+      .find_map(parse_stat_line)
+      .ok_or_else(|| FsError::InvalidPayload(format!("could not parse stat output for {path}")))
+  }
+
+  /// List a directory including hidden entries, like `ls -la`.
+  #[tracing::instrument(skip(self), fields(path = %path))]
+  pub async fn list(&self, path: &str) -> Result<Vec<RemoteStat>, FsError> {
+    let script = format!("find {} -mindepth 1 -maxdepth 1 -printf '{STAT_FORMAT}'", shell_path(path));
+    let output = self.run_checked(&script).await?;
+    Ok(output.lines().filter_map(parse_stat_line).collect())
+  }
+
+  #[tracing::instrument(skip(self), fields(path = %path, mode = format!("{mode:o}")))]
+  pub async fn chmod(&self, path: &str, mode: u32) -> Result<(), FsError> {
+    self.run_checked(&format!("chmod {mode:o} {}", shell_path(path))).await?;
+    Ok(())
+  }
+
+  /// Set the modification time of each path, like `touch -m`.
+  ///
+  /// Paths are batched into a few terminal sessions, one `touch` per line.
+  #[tracing::instrument(skip(self, stamps), fields(count = stamps.len()))]
+  pub async fn touch(&self, stamps: &[(String, DateTime<Utc>)]) -> Result<(), FsError> {
+    for batch in stamps.chunks(TOUCH_BATCH) {
+      let mut script = String::from("rc=0\n");
+      for (path, stamp) in batch {
+        script.push_str(&format!(
+          "touch -m -d @{}.{:09} -- {} || rc=$?\n",
+          stamp.timestamp(),
+          stamp.timestamp_subsec_nanos(),
+          shell_path(path)
+        ));
+      }
+      script.push_str("[ \"$rc\" -eq 0 ]");
+      self.run_checked(&script).await?;
+    }
+    Ok(())
+  }
+
+  /// Create a symlink at `link` pointing to `target` (`target` is stored verbatim).
+  #[tracing::instrument(skip(self), fields(target = %target, link = %link))]
+  pub async fn symlink(&self, target: &str, link: &str) -> Result<(), FsError> {
+    self
+      .run_checked(&format!("ln -s -- {} {}", shell_quote(target), shell_path(link)))
+      .await?;
+    Ok(())
+  }
+
+  /// Read a file through the terminal, bypassing the Contents API's hidden-file checks.
+  #[tracing::instrument(skip(self), fields(path = %path))]
+  pub async fn read(&self, path: &str) -> Result<Vec<u8>, FsError> {
+    let output = self.run_checked(&format!("base64 < {}", shell_path(path))).await?;
+    let encoded: String = output.split_whitespace().collect();
+    Ok(STANDARD.decode(encoded)?)
+  }
+}
+
+pub(crate) fn frame_id() -> String {
+  format!("{:x}", Utc::now().timestamp_nanos_opt().unwrap_or_default())
+}
+
+/// Wrap `script` in sentinel lines; the markers are assembled by `printf` so the
+/// echoed command line never contains them verbatim.
+pub(crate) fn frame_script(script: &str, id: &str) -> String {
+  format!("printf '%s_%s\\n' {SENTINEL}_BEGIN {id}; {{ {script}\n}} 2>&1; printf '%s_%s_%s\\n' {SENTINEL}_END {id} $?")
+}
+
+/// Quote `value` for a POSIX shell.
+pub fn shell_quote(value: &str) -> String {
+  format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Quote a Jupyter path relative to the server root, guarding against leading dashes.
+pub fn shell_path(path: &str) -> String {
+  let trimmed = path.trim_matches('/');
+  if trimmed.is_empty() {
+    ".".to_string()
+  } else {
+    shell_quote(&format!("./{trimmed}"))
+  }
+}
+
+pub(crate) fn parse_framed_output(stdout: &str, id: &str) -> Option<ShellOutput> {
+  let normalized = stdout.replace("\r\n", "\n").replace('\r', "");
+  let begin = format!("{SENTINEL}_BEGIN_{id}\n");
+  let end = format!("{SENTINEL}_END_{id}_");
+  let start = normalized.find(&begin)? + begin.len();
+  let rest = &normalized[start..];
+  let stop = rest.find(&end)?;
+  let status = rest[stop + end.len()..]
+    .lines()
+    .next()?
+    .trim()
+    .parse()
+    .ok()?;
+  Some(ShellOutput { status, output: rest[..stop].to_string() })
+}
+
+pub(crate) fn parse_stat_line(line: &str) -> Option<RemoteStat> {
+  let mut fields = line.splitn(10, '\t');
+  let mode = u32::from_str_radix(fields.next()?, 8).ok()?;
+  let uid = fields.next()?.parse().ok()?;
+  let gid = fields.next()?.parse().ok()?;
+  let owner = fields.next()?.to_string();
+  let group = fields.next()?.to_string();
+  let size = fields.next()?.parse().ok()?;
+  let modified = fields
+    .next()?
+    .parse::<f64>()
+    .ok()
+    .and_then(|secs| DateTime::from_timestamp_millis((secs * 1000.0) as i64));
+  let kind = match fields.next()? {
+    "f" => RemoteFileType::File,
+    "d" => RemoteFileType::Directory,
+    "l" => RemoteFileType::Symlink,
+    other => RemoteFileType::Other(other.chars().next().unwrap_or('?')),
+  };
+  let target = fields.next()?;
+  let path = fields.next()?.trim_start_matches("./").to_string();
+  Some(RemoteStat {
+    path: if path == "." { String::new() } else { path },
+    kind,
+    mode,
+    uid,
+    gid,
+    owner,
+    group,
+    size,
+    modified,
+    symlink_target: (!target.is_empty()).then(|| target.to_string()),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn framed_output_ignores_echo_and_prompts() {
+    let stdout = "$ printf '%s_%s\\n' __JUPYTER_SHELL_BEGIN ab; ls\r\n__JUPYTER_SHELL_BEGIN_ab\r\nfile.txt\r\n__JUPYTER_SHELL_END_ab_0\r\n$ exit\r\n";
+    let output = parse_framed_output(stdout, "ab").unwrap();
+    assert_eq!(output.status, 0);
+    assert_eq!(output.output, "file.txt\n");
+    assert!(parse_framed_output(stdout, "cd").is_none());
+  }
+
+  #[test]
+  fn stat_line_parses_modes_and_links() {
+    let stat = parse_stat_line("777\t1000\t100\tjovyan\tusers\t9\t1700000000.5\tl\ttarget.txt\t./data/link").unwrap();
+    assert_eq!(stat.kind, RemoteFileType::Symlink);
+    assert_eq!(stat.mode, 0o777);
+    assert_eq!(stat.owner, "jovyan");
+    assert_eq!(stat.symlink_target.as_deref(), Some("target.txt"));
+    assert_eq!(stat.path, "data/link");
+    assert_eq!(stat.name(), "link");
+    assert!(parse_stat_line("garbage").is_none());
+  }
+
+  #[test]
+  fn shell_paths_are_quoted_relative_to_root() {
+    assert_eq!(shell_path("/"), ".");
+    assert_eq!(shell_path("/-rf"), "'./-rf'");
+    assert_eq!(shell_quote("it's"), "'it'\\''s'");
+  }
+}
@@ -89,7 +89,7 @@ pub struct TerminalCallResult {
 pub enum InputMessage {
   /// stdin,$0
   Stdin(String),
-  /// set_size,$0,$1,??,??
+  /// set_size,rows,cols,height,width (terminado takes rows first)
   Resize { cols: u16, rows: u16 },
 }
 
@@ -99,7 +99,7 @@ impl TryFrom<InputMessage> for serde_json::Value {
   fn try_from(value: InputMessage) -> Result<Self, Self::Error> {
     match value {
       InputMessage::Stdin(data) => Ok(json!(["stdin", data])),
-      InputMessage::Resize { cols, rows } => Ok(json!(["set_size", cols, rows, 800, 600])),
+      InputMessage::Resize { cols, rows } => Ok(json!(["set_size", rows, cols, 600, 800])),
     }
   }
 }
@@ -297,7 +297,13 @@ mod tests {
     jupyter::JupyterApi,
   };
 
-  use super::TerminalService;
+  use super::{InputMessage, TerminalService};
+
+  #[test]
+  fn resize_sends_rows_before_cols() {
+    let message = serde_json::Value::try_from(InputMessage::Resize { cols: 120, rows: 40 }).unwrap();
+    assert_eq!(message, serde_json::json!(["set_size", 40, 120, 600, 800]));
+  }
 
   #[tokio::test]
   async fn test_terminal_service_get_force_create() {
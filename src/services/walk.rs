@@ -0,0 +1,402 @@
+use std::{
+  collections::{BTreeMap, VecDeque},
+  fmt,
+  sync::Arc,
+};
+
+use futures_util::{
+  future::BoxFuture,
+  stream::{self, FuturesUnordered},
+  Stream, StreamExt, TryStreamExt,
+};
+
+use crate::fs::{Entry, FsError, FsService};
+
+/// Default number of directory listings kept in flight by [`FsService::walk`].
+const WALK_CONCURRENCY: usize = 4;
+
+type EntryFilter = Arc<dyn Fn(&Entry) -> bool + Send + Sync>;
+
+/// Options controlling a recursive [`FsService::walk_with`] traversal.
+#[derive(Clone)]
+pub struct WalkOptions {
+  max_depth: Option<usize>,
+  concurrency: usize,
+  filter: Option<EntryFilter>,
+}
+
+impl Default for WalkOptions {
+  fn default() -> Self {
+    Self {
+      max_depth: None,
+      concurrency: WALK_CONCURRENCY,
+      filter: None,
+    }
+  }
+}
+
+impl fmt::Debug for WalkOptions {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("WalkOptions")
+      .field("max_depth", &self.max_depth)
+      .field("concurrency", &self.concurrency)
+      .field("filter", &self.filter.is_some())
+      .finish()
+  }
+}
+
+impl WalkOptions {
+  /// Limit how deep the walk descends; `1` yields only the direct children of the root.
+  pub fn max_depth(mut self, depth: usize) -> Self {
+    self.max_depth = Some(depth);
+    self
+  }
+
+  /// Number of directory listings that may be in flight at once.
+  pub fn concurrency(mut self, concurrency: usize) -> Self {
+    self.concurrency = concurrency.max(1);
+    self
+  }
+
+  /// Skip entries for which `filter` returns false. Rejected directories are not descended into.
+  pub fn filter(mut self, filter: impl Fn(&Entry) -> bool + Send + Sync + 'static) -> Self {
+    self.filter = Some(Arc::new(filter));
+    self
+  }
+
+  fn accepts(&self, entry: &Entry) -> bool {
+    self.filter.as_ref().is_none_or(|filter| filter(entry))
+  }
+
+  fn descends_below(&self, depth: usize) -> bool {
+    self.max_depth.is_none_or(|max| depth < max)
+  }
+}
+
+type Listing = (usize, Result<Vec<Entry>, FsError>);
+
+struct WalkState {
+  fs: FsService,
+  options: WalkOptions,
+  pending: VecDeque<(String, usize)>,
+  in_flight: FuturesUnordered<BoxFuture<'static, Listing>>,
+  ready: VecDeque<Result<Entry, FsError>>,
+}
+
+impl WalkState {
+  fn fill(&mut self) {
+    while self.in_flight.len() < self.options.concurrency {
+      let Some((path, depth)) = self.pending.pop_front() else {
+        break;
+      };
+      let fs = self.fs.clone();
+      self.in_flight.push(Box::pin(async move { (depth, fs.ls(&path).await) }));
+    }
+  }
+
+  fn absorb(&mut self, depth: usize, listing: Result<Vec<Entry>, FsError>) {
+    let entries = match listing {
+      Ok(entries) => entries,
+      Err(err) => {
+        self.ready.push_back(Err(err));
+        return;
+      }
+    };
+    let depth = depth + 1;
+    for entry in entries {
+      if !self.options.accepts(&entry) {
+        continue;
+      }
+      if entry.kind.is_directory() && self.options.descends_below(depth) {
+        self.pending.push_back((entry.path.clone(), depth));
+      }
+      self.ready.push_back(Ok(entry));
+    }
+  }
+}
+
+impl FsService {
+  /// Recursively list everything below `root` (the root itself is not yielded).
+  pub fn walk(&self, root: &str) -> impl Stream<Item = Result<Entry, FsError>> + Send + 'static {
+    self.walk_with(root, WalkOptions::default())
+  }
+
+  /// Recursively list everything below `root`, listing directories concurrently.
+  ///
+  /// Entries are yielded as their parent listing completes, so the order is not stable;
+  /// a directory is always yielded before its own children.
+  #[tracing::instrument(skip(self), fields(root = %root))]
+  pub fn walk_with(&self, root: &str, options: WalkOptions) -> impl Stream<Item = Result<Entry, FsError>> + Send + 'static {
+    debug!("fs: walk {}", root);
+    let state = WalkState {
+      fs: self.clone(),
+      options,
+      pending: VecDeque::from([(root.to_string(), 0)]),
+      in_flight: FuturesUnordered::new(),
+      ready: VecDeque::new(),
+    };
+    stream::unfold(state, |mut state| async move {
+      loop {
+        if let Some(item) = state.ready.pop_front() {
+          return Some((item, state));
+        }
+        state.fill();
+        let (depth, listing) = state.in_flight.next().await?;
+        state.absorb(depth, listing);
+      }
+    })
+  }
+
+  /// Expand a shell-style pattern such as `data/**/*.csv` against the remote tree.
+  ///
+  /// Supports `*`, `?`, `[...]` within a path segment and `**` across segments.
+  /// Matches are returned sorted by path.
+  #[tracing::instrument(skip(self), fields(pattern = %pattern))]
+  pub async fn glob(&self, pattern: &str) -> Result<Vec<Entry>, FsError> {
+    debug!("fs: glob {}", pattern);
+    let segments: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty() && *s != ".").collect();
+    let literal = segments.iter().take_while(|s| !has_wildcard(s)).count();
+    let (prefix, rest) = segments.split_at(literal);
+    let root = prefix.join("/");
+
+    if rest.is_empty() {
+      return match self.metadata(&root).await {
+        Ok(entry) => Ok(vec![entry]),
+        Err(err) if err.is_not_found() => Ok(Vec::new()),
+        Err(err) => Err(err),
+      };
+    }
+
+    let mut options = WalkOptions::default();
+    if !rest.contains(&"**") {
+      options = options.max_depth(rest.len());
+    }
+    let skip = if root.is_empty() { 0 } else { root.len() + 1 };
+    let mut matches: Vec<Entry> = self
+      .walk_with(&root, options)
+      .try_filter(|entry| {
+        let relative = entry.path.trim_start_matches('/').get(skip..).unwrap_or("");
+        let parts: Vec<&str> = relative.split('/').collect();
+        futures_util::future::ready(match_segments(rest, &parts))
+      })
+      .try_collect()
+      .await?;
+    matches.sort_by(|a, b| a.path.cmp(&b.path));
+    trace!(matches = matches.len(), "glob expanded");
+    Ok(matches)
+  }
+}
+
+/// Recursive size summary produced by [`FsService::du`].
+///
+/// Paths are relative to the root that was summarized; the root itself is `""`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiskUsage {
+  pub total_bytes: u64,
+  pub files: usize,
+  /// Recursive size of every directory, including the root.
+  pub directories: BTreeMap<String, u64>,
+  /// Size of every file.
+  pub file_sizes: BTreeMap<String, u64>,
+}
+
+impl DiskUsage {
+  fn add_file(&mut self, relative: &str, size: u64) {
+    self.total_bytes += size;
+    self.files += 1;
+    self.file_sizes.insert(relative.to_string(), size);
+    let mut parent = relative;
+    while let Some((dir, _)) = parent.rsplit_once('/') {
+      *self.directories.entry(dir.to_string()).or_default() += size;
+      parent = dir;
+    }
+    *self.directories.entry(String::new()).or_default() += size;
+  }
+
+  /// The `n` largest files and directories below the root, biggest first.
+  pub fn largest(&self, n: usize) -> Vec<(&str, u64)> {
+    let mut sizes: Vec<(&str, u64)> = self
+      .file_sizes
+      .iter()
+      .chain(self.directories.iter().filter(|(path, _)| !path.is_empty()))
+      .map(|(path, size)| (path.as_str(), *size))
+      .collect();
+    sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    sizes.truncate(n);
+    sizes
+  }
+
+  /// Directories at most `depth` levels below the root (the root is depth 0), by path.
+  pub fn directories_within(&self, depth: usize) -> impl Iterator<Item = (&str, u64)> {
+    self
+      .directories
+      .iter()
+      .filter(move |(path, _)| path.is_empty() || path.split('/').count() <= depth)
+      .map(|(path, size)| (path.as_str(), *size))
+  }
+}
+
+impl FsService {
+  /// Sum file sizes below `root` per subdirectory, listing directories concurrently.
+  ///
+  /// Sizes come from `Entry::size`; entries the server reports without a size count as zero.
+  #[tracing::instrument(skip(self), fields(root = %root))]
+  pub async fn du(&self, root: &str) -> Result<DiskUsage, FsError> {
+    debug!("fs: du {}", root);
+    let base = root.trim_matches('/');
+    let skip = if base.is_empty() { 0 } else { base.len() + 1 };
+    let mut usage = DiskUsage::default();
+    let entry = self.metadata(root).await?;
+    if !entry.kind.is_directory() {
+      usage.total_bytes = entry.size.unwrap_or(0);
+      usage.files = 1;
+      usage.file_sizes.insert(String::new(), usage.total_bytes);
+      return Ok(usage);
+    }
+    usage.directories.insert(String::new(), 0);
+    let mut entries = Box::pin(self.walk(root));
+    while let Some(entry) = entries.try_next().await? {
+      let relative = entry.path.trim_matches('/').get(skip..).unwrap_or("").to_string();
+      if entry.kind.is_directory() {
+        usage.directories.entry(relative).or_default();
+      } else {
+        usage.add_file(&relative, entry.size.unwrap_or(0));
+      }
+    }
+    trace!(files = usage.files, bytes = usage.total_bytes, "disk usage summarized");
+    Ok(usage)
+  }
+}
+
+/// Whether `value` contains characters that [`FsService::glob`] treats as wildcards.
+pub fn has_wildcard(value: &str) -> bool {
+  value.contains(['*', '?', '['])
+}
+
+fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+  match pattern.split_first() {
+    None => path.is_empty(),
+    Some((&"**", rest)) => (0..=path.len()).any(|skip| match_segments(rest, &path[skip..])),
+    Some((segment, rest)) => match path.split_first() {
+      Some((name, tail)) => match_segment(segment, name) && match_segments(rest, tail),
+      None => false,
+    },
+  }
+}
+
+/// Match a single path segment, keeping dotfiles hidden unless the pattern names the dot.
+pub(crate) fn match_segment(pattern: &str, name: &str) -> bool {
+  if name.starts_with('.') && !pattern.starts_with('.') {
+    return false;
+  }
+  let pattern: Vec<char> = pattern.chars().collect();
+  let name: Vec<char> = name.chars().collect();
+  match_chars(&pattern, &name)
+}
+
+fn match_chars(pattern: &[char], name: &[char]) -> bool {
+  match pattern.first() {
+    None => name.is_empty(),
+    Some('*') => (0..=name.len()).any(|skip| match_chars(&pattern[1..], &name[skip..])),
+    Some('?') => !name.is_empty() && match_chars(&pattern[1..], &name[1..]),
+    Some('[') => {
+      let Some(close) = pattern.iter().skip(2).position(|c| *c == ']').map(|idx| idx + 2) else {
+        return name.first() == Some(&'[') && match_chars(&pattern[1..], &name[1..]);
+      };
+      let Some(ch) = name.first() else {
+        return false;
+      };
+      let mut class = &pattern[1..close];
+      let negated = matches!(class.first(), Some('!' | '^'));
+      if negated {
+        class = &class[1..];
+      }
+      let mut hit = false;
+      let mut idx = 0;
+      while idx < class.len() {
+        if idx + 2 < class.len() && class[idx + 1] == '-' {
+          hit |= (class[idx]..=class[idx + 2]).contains(ch);
+          idx += 3;
+        } else {
+          hit |= class[idx] == *ch;
+          idx += 1;
+        }
+      }
+      hit != negated && match_chars(&pattern[close + 1..], &name[1..])
+    }
+    Some(c) => name.first() == Some(c) && match_chars(&pattern[1..], &name[1..]),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    match_segments(&pattern, &path)
+  }
+
+  #[test]
+  fn glob_segment_wildcards() {
+    assert!(match_segment("*.txt", "notes.txt"));
+    assert!(!match_segment("*.txt", "notes.csv"));
+    assert!(match_segment("log?.txt", "log1.txt"));
+    assert!(match_segment("[a-c]x", "bx"));
+    assert!(!match_segment("[!a-c]x", "bx"));
+    assert!(!match_segment("*", ".hidden"));
+    assert!(match_segment(".*", ".hidden"));
+  }
+
+  #[test]
+  fn glob_double_star_spans_directories() {
+    assert!(matches("**/*.csv", "a.csv"));
+    assert!(matches("**/*.csv", "x/y/a.csv"));
+    assert!(matches("x/**", "x/y/z"));
+    assert!(!matches("*/*.csv", "x/y/a.csv"));
+  }
+
+  #[test]
+  fn disk_usage_rolls_sizes_up_to_ancestors() {
+    let mut usage = DiskUsage::default();
+    usage.add_file("a.bin", 10);
+    usage.add_file("data/b.bin", 5);
+    usage.add_file("data/nested/c.bin", 20);
+    assert_eq!(usage.total_bytes, 35);
+    assert_eq!(usage.directories[""], 35);
+    assert_eq!(usage.directories["data"], 25);
+    assert_eq!(usage.directories["data/nested"], 20);
+    assert_eq!(usage.largest(2), vec![("data", 25), ("data/nested", 20)]);
+    let shallow: Vec<&str> = usage.directories_within(1).map(|(path, _)| path).collect();
+    assert_eq!(shallow, vec!["", "data"]);
+  }
+
+  #[tokio::test]
+  async fn test_walk_and_glob() {
+    let client = crate::api::client::tests::_setup_client();
+    let fs = FsService::new(Arc::new(client));
+
+    fs.rmdir("walk_dir", true).await.ok();
+    fs.mkdir_all("walk_dir/data/nested").await.unwrap();
+    fs.upload("walk_dir/data/a.csv", "a").await.unwrap();
+    fs.upload("walk_dir/data/nested/b.csv", "b").await.unwrap();
+    fs.upload("walk_dir/data/nested/c.txt", "c").await.unwrap();
+
+    let all: Vec<Entry> = fs.walk("walk_dir").try_collect().await.unwrap();
+    assert_eq!(all.len(), 5);
+    let shallow: Vec<Entry> = fs.walk_with("walk_dir", WalkOptions::default().max_depth(1)).try_collect().await.unwrap();
+    assert_eq!(shallow.len(), 1);
+
+    let csv = fs.glob("walk_dir/**/*.csv").await.unwrap();
+    let names: Vec<&str> = csv.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(names, vec!["a.csv", "b.csv"]);
+
+    let usage = fs.du("walk_dir").await.unwrap();
+    assert_eq!(usage.files, 3);
+    assert_eq!(usage.total_bytes, 3);
+    assert_eq!(usage.directories["data/nested"], 2);
+
+    fs.rmdir("walk_dir", true).await.unwrap();
+  }
+}
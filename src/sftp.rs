@@ -0,0 +1,451 @@
+use std::{
+  collections::{BTreeMap, HashMap},
+  path::{Component, Path},
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode as HttpStatus;
+use russh_sftp::protocol::{
+  Attrs, Data, File, FileAttributes, FileMode, Handle, Name, OpenFlags, Status, StatusCode, Version,
+};
+use tokio::{
+  io::{AsyncRead, AsyncReadExt, AsyncWrite},
+  sync::oneshot,
+};
+
+use crate::{
+  api::client::ClientError,
+  fs::{Entry, FsError, FsService, UploadPipe},
+  sshd::{Session, SessionHandler, SessionRequest},
+};
+
+/// Bytes buffered per Contents API save while a client writes a file.
+const UPLOAD_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Serve the SFTP protocol on `stream` until the client disconnects.
+///
+/// `stream` carries raw SFTP packets: the channel of an SSH `sftp` subsystem,
+/// either stdin/stdout when started by OpenSSH's `Subsystem` directive or a
+/// [`Session`] of the embedded server. Authentication is left to whatever
+/// provides the stream.
+pub async fn serve<S>(fs: FsService, stream: S)
+where
+  S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+  let (done, finished) = oneshot::channel();
+  russh_sftp::server::run(stream, SftpSession::new(fs, done)).await;
+  // The session is dropped, closing `done`, once the client disconnects.
+  finished.await.ok();
+}
+
+/// Serves the `sftp` subsystem of the embedded SSH server and refuses shells and commands.
+#[derive(Clone)]
+pub struct SftpSubsystem {
+  fs: FsService,
+}
+
+impl SftpSubsystem {
+  pub fn new(fs: FsService) -> Self {
+    Self { fs }
+  }
+}
+
+#[async_trait::async_trait]
+impl SessionHandler for SftpSubsystem {
+  fn accepts(&self, request: &SessionRequest) -> bool {
+    matches!(request, SessionRequest::Subsystem(name) if name == "sftp")
+  }
+
+  async fn run(&self, _request: SessionRequest, session: Session) -> u32 {
+    serve(self.fs.clone(), session).await;
+    0
+  }
+}
+
+/// SFTP request handler backed by a [`FsService`]; one per client connection.
+pub struct SftpSession {
+  fs: FsService,
+  handles: HashMap<String, OpenHandle>,
+  next_handle: u64,
+  _done: oneshot::Sender<()>,
+}
+
+enum OpenHandle {
+  Read(ReadHandle),
+  Write(WriteHandle),
+  Dir { path: String, entries: Option<Vec<Entry>> },
+}
+
+struct ReadHandle {
+  path: String,
+  size: u64,
+  /// Open download and its position; reads at any other offset reopen it.
+  reader: Option<(Box<dyn AsyncRead + Unpin + Send + Sync>, u64)>,
+}
+
+struct WriteHandle {
+  path: String,
+  flags: OpenFlags,
+  /// Where a resumed upload starts: the size of the existing file, if any.
+  existing_size: Option<u64>,
+  upload: Option<PendingUpload>,
+}
+
+/// Data must reach the pipe in order, so writes past the current offset are
+/// parked until the gap is filled.
+struct PendingUpload {
+  pipe: UploadPipe,
+  /// File offset of the next byte the pipe expects.
+  offset: u64,
+  parked: BTreeMap<u64, Vec<u8>>,
+}
+
+impl SftpSession {
+  fn new(fs: FsService, done: oneshot::Sender<()>) -> Self {
+    Self { fs, handles: HashMap::new(), next_handle: 0, _done: done }
+  }
+
+  fn insert(&mut self, handle: OpenHandle) -> String {
+    self.next_handle += 1;
+    let id = self.next_handle.to_string();
+    self.handles.insert(id.clone(), handle);
+    id
+  }
+
+  async fn attrs(&self, path: &str) -> Result<FileAttributes, StatusCode> {
+    let entry = self.fs.metadata(&normalize_path(path)).await.map_err(map_fs_error)?;
+    Ok(entry_attributes(&entry))
+  }
+
+  async fn read_at(&mut self, handle: &str, offset: u64, len: u32) -> Result<Vec<u8>, StatusCode> {
+    let fs = self.fs.clone();
+    let Some(OpenHandle::Read(file)) = self.handles.get_mut(handle) else {
+      return Err(StatusCode::Failure);
+    };
+    if offset >= file.size {
+      return Err(StatusCode::Eof);
+    }
+    let mut reader = match file.reader.take() {
+      Some((reader, position)) if position == offset => reader,
+      _ => {
+        trace!(path = %file.path, offset, "SFTP read opening download");
+        fs.download_reader_from(&file.path, offset).await.map_err(map_fs_error)?.reader
+      }
+    };
+    let mut data = Vec::with_capacity(len as usize);
+    if let Err(err) = (&mut reader).take(len as u64).read_to_end(&mut data).await {
+      debug!(error = %err, path = %file.path, "SFTP read failed");
+      return Err(StatusCode::Failure);
+    }
+    if data.is_empty() {
+      return Err(StatusCode::Eof);
+    }
+    // Keep the download open so sequential reads continue where this one stopped.
+    file.reader = Some((reader, offset + data.len() as u64));
+    Ok(data)
+  }
+}
+
+impl WriteHandle {
+  /// Feed `data` written at `offset` into the upload, starting it on the first write.
+  async fn write(&mut self, fs: &FsService, offset: u64, data: Vec<u8>) -> Result<(), StatusCode> {
+    if self.upload.is_none() {
+      self.upload = Some(self.start(fs, offset)?);
+    }
+    let upload = self.upload.as_mut().expect("upload started above");
+    if offset < upload.offset {
+      debug!(path = %self.path, offset, expected = upload.offset, "SFTP write rewinds; not supported");
+      return Err(StatusCode::OpUnsupported);
+    }
+    upload.parked.insert(offset, data);
+    while let Some(chunk) = upload.parked.remove(&upload.offset) {
+      if upload.pipe.write(&chunk).await.is_err() {
+        // The upload task stopped early; its error is reported on close.
+        return Err(StatusCode::Failure);
+      }
+      upload.offset += chunk.len() as u64;
+    }
+    Ok(())
+  }
+
+  /// Start uploading from `offset`: replace the file when writing from zero, or
+  /// append when resuming at its end.
+  ///
+  /// Rewriting part of an existing file is not supported; a write at zero
+  /// without `TRUNCATE` still replaces the whole file.
+  fn start(&self, fs: &FsService, offset: u64) -> Result<PendingUpload, StatusCode> {
+    let existing = self.existing_size.filter(|_| !self.flags.contains(OpenFlags::TRUNCATE));
+    let append = match existing {
+      // Offsets are meaningless in append mode, so the first write sets the sequence.
+      Some(size) if size > 0 => self.flags.contains(OpenFlags::APPEND) || offset == size,
+      _ => false,
+    };
+    if !append && offset != 0 {
+      debug!(path = %self.path, offset, "SFTP write does not start at 0 or the end of the file");
+      return Err(StatusCode::OpUnsupported);
+    }
+    debug!(path = %self.path, append, "SFTP upload started");
+    let pipe = if append {
+      fs.append_pipe(&self.path, UPLOAD_CHUNK_SIZE)
+    } else {
+      fs.upload_pipe(&self.path, UPLOAD_CHUNK_SIZE)
+    };
+    Ok(PendingUpload { pipe, offset, parked: BTreeMap::new() })
+  }
+
+  /// Finish the upload; a file opened for writing but never written is created empty.
+  async fn finish(self, fs: &FsService) -> Result<(), StatusCode> {
+    let Some(upload) = self.upload else {
+      if self.existing_size.is_none() || self.flags.contains(OpenFlags::TRUNCATE) {
+        fs.upload(&self.path, b"").await.map_err(map_fs_error)?;
+      }
+      return Ok(());
+    };
+    if !upload.parked.is_empty() {
+      debug!(path = %self.path, "SFTP upload closed with gaps; discarding");
+      upload.pipe.abort();
+      return Err(StatusCode::Failure);
+    }
+    let done = upload.pipe.finish().await.map_err(map_fs_error)?;
+    debug!(path = %self.path, bytes = done.bytes, "SFTP upload completed");
+    Ok(())
+  }
+}
+
+impl russh_sftp::server::Handler for SftpSession {
+  type Error = StatusCode;
+
+  fn unimplemented(&self) -> Self::Error {
+    StatusCode::OpUnsupported
+  }
+
+  async fn init(&mut self, version: u32, _extensions: HashMap<String, String>) -> Result<Version, Self::Error> {
+    debug!(version, "SFTP session started");
+    Ok(Version::new())
+  }
+
+  async fn open(&mut self, id: u32, filename: String, pflags: OpenFlags, _attrs: FileAttributes) -> Result<Handle, Self::Error> {
+    let path = normalize_path(&filename);
+    debug!(%path, flags = ?pflags, "SFTP open");
+    let existing = match self.fs.metadata(&path).await {
+      Ok(entry) if entry.kind.is_directory() => return Err(StatusCode::Failure),
+      Ok(entry) => Some(entry),
+      Err(err) if err.is_not_found() => None,
+      Err(err) => return Err(map_fs_error(err)),
+    };
+    let handle = if pflags.intersects(OpenFlags::WRITE | OpenFlags::APPEND | OpenFlags::CREATE | OpenFlags::TRUNCATE) {
+      if existing.is_some() && pflags.contains(OpenFlags::CREATE | OpenFlags::EXCLUDE) {
+        return Err(StatusCode::Failure);
+      }
+      if existing.is_none() && !pflags.contains(OpenFlags::CREATE) {
+        return Err(StatusCode::NoSuchFile);
+      }
+      OpenHandle::Write(WriteHandle {
+        path,
+        flags: pflags,
+        existing_size: existing.map(|entry| entry.size.unwrap_or(0)),
+        upload: None,
+      })
+    } else {
+      let entry = existing.ok_or(StatusCode::NoSuchFile)?;
+      OpenHandle::Read(ReadHandle { path, size: entry.size.unwrap_or(0), reader: None })
+    };
+    Ok(Handle { id, handle: self.insert(handle) })
+  }
+
+  async fn close(&mut self, id: u32, handle: String) -> Result<Status, Self::Error> {
+    match self.handles.remove(&handle) {
+      Some(OpenHandle::Write(file)) => file.finish(&self.fs).await?,
+      Some(_) => {}
+      None => return Err(StatusCode::Failure),
+    }
+    Ok(ok_status(id))
+  }
+
+  async fn read(&mut self, id: u32, handle: String, offset: u64, len: u32) -> Result<Data, Self::Error> {
+    let data = self.read_at(&handle, offset, len).await?;
+    Ok(Data { id, data })
+  }
+
+  async fn write(&mut self, id: u32, handle: String, offset: u64, data: Vec<u8>) -> Result<Status, Self::Error> {
+    let fs = self.fs.clone();
+    let Some(OpenHandle::Write(file)) = self.handles.get_mut(&handle) else {
+      return Err(StatusCode::Failure);
+    };
+    file.write(&fs, offset, data).await?;
+    Ok(ok_status(id))
+  }
+
+  async fn lstat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+    Ok(Attrs { id, attrs: self.attrs(&path).await? })
+  }
+
+  async fn stat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+    Ok(Attrs { id, attrs: self.attrs(&path).await? })
+  }
+
+  async fn fstat(&mut self, id: u32, handle: String) -> Result<Attrs, Self::Error> {
+    let path = match self.handles.get(&handle) {
+      Some(OpenHandle::Read(file)) => file.path.clone(),
+      Some(OpenHandle::Write(file)) => file.path.clone(),
+      Some(OpenHandle::Dir { path, .. }) => path.clone(),
+      None => return Err(StatusCode::Failure),
+    };
+    Ok(Attrs { id, attrs: self.attrs(&path).await? })
+  }
+
+  async fn setstat(&mut self, id: u32, path: String, attrs: FileAttributes) -> Result<Status, Self::Error> {
+    self.set_mtime(&normalize_path(&path), &attrs).await;
+    Ok(ok_status(id))
+  }
+
+  async fn fsetstat(&mut self, id: u32, handle: String, attrs: FileAttributes) -> Result<Status, Self::Error> {
+    let path = match self.handles.get(&handle) {
+      Some(OpenHandle::Read(file)) => file.path.clone(),
+      Some(OpenHandle::Write(file)) => file.path.clone(),
+      Some(OpenHandle::Dir { path, .. }) => path.clone(),
+      None => return Err(StatusCode::Failure),
+    };
+    self.set_mtime(&path, &attrs).await;
+    Ok(ok_status(id))
+  }
+
+  async fn opendir(&mut self, id: u32, path: String) -> Result<Handle, Self::Error> {
+    let path = normalize_path(&path);
+    debug!(%path, "SFTP opendir");
+    let entry = self.fs.metadata(&path).await.map_err(map_fs_error)?;
+    if !entry.kind.is_directory() {
+      return Err(StatusCode::Failure);
+    }
+    let entries = self.fs.ls(&path).await.map_err(map_fs_error)?;
+    Ok(Handle { id, handle: self.insert(OpenHandle::Dir { path, entries: Some(entries) }) })
+  }
+
+  async fn readdir(&mut self, id: u32, handle: String) -> Result<Name, Self::Error> {
+    let Some(OpenHandle::Dir { entries, .. }) = self.handles.get_mut(&handle) else {
+      return Err(StatusCode::Failure);
+    };
+    // The whole listing is returned by the first call; the next one reports the end.
+    let entries = entries.take().ok_or(StatusCode::Eof)?;
+    let files = entries
+      .iter()
+      .map(|entry| File::new(entry.name.clone(), entry_attributes(entry)))
+      .collect();
+    Ok(Name { id, files })
+  }
+
+  async fn remove(&mut self, id: u32, filename: String) -> Result<Status, Self::Error> {
+    let path = normalize_path(&filename);
+    debug!(%path, "SFTP remove");
+    self.fs.rm(&path).await.map_err(map_fs_error)?;
+    Ok(ok_status(id))
+  }
+
+  async fn mkdir(&mut self, id: u32, path: String, _attrs: FileAttributes) -> Result<Status, Self::Error> {
+    let path = normalize_path(&path);
+    debug!(%path, "SFTP mkdir");
+    self.fs.mkdir(&path).await.map_err(map_fs_error)?;
+    Ok(ok_status(id))
+  }
+
+  async fn rmdir(&mut self, id: u32, path: String) -> Result<Status, Self::Error> {
+    let path = normalize_path(&path);
+    debug!(%path, "SFTP rmdir");
+    self.fs.rmdir(&path, false).await.map_err(map_fs_error)?;
+    Ok(ok_status(id))
+  }
+
+  async fn realpath(&mut self, id: u32, path: String) -> Result<Name, Self::Error> {
+    Ok(Name { id, files: vec![File::dummy(normalize_path(&path))] })
+  }
+
+  async fn rename(&mut self, id: u32, oldpath: String, newpath: String) -> Result<Status, Self::Error> {
+    let (from, to) = (normalize_path(&oldpath), normalize_path(&newpath));
+    debug!(%from, %to, "SFTP rename");
+    self.fs.rename(&from, &to).await.map_err(map_fs_error)?;
+    Ok(ok_status(id))
+  }
+}
+
+impl SftpSession {
+  /// Apply a modification time from `SETSTAT`; other attributes are ignored.
+  ///
+  /// Needs the shell fallback. Failures are logged rather than reported so that
+  /// clients preserving times (`sftp -p`, WinSCP) still finish their transfers.
+  async fn set_mtime(&self, path: &str, attrs: &FileAttributes) {
+    let Some(stamp) = attrs.mtime.and_then(|secs| DateTime::<Utc>::from_timestamp(secs as i64, 0)) else {
+      return;
+    };
+    if let Err(err) = self.fs.touch(&[(path.to_string(), stamp)]).await {
+      debug!(error = %err, %path, "SFTP setstat could not set the modification time");
+    }
+  }
+}
+
+/// Resolve an SFTP path against the server root, clamping `..` at `/`.
+fn normalize_path(path: &str) -> String {
+  let mut components = Vec::new();
+  for component in Path::new(path).components() {
+    match component {
+      Component::RootDir | Component::Prefix(_) | Component::CurDir => {}
+      Component::ParentDir => {
+        components.pop();
+      }
+      Component::Normal(part) => components.push(part.to_string_lossy().into_owned()),
+    }
+  }
+  format!("/{}", components.join("/"))
+}
+
+fn entry_attributes(entry: &Entry) -> FileAttributes {
+  let (kind, mode) = if entry.kind.is_directory() {
+    (FileMode::DIR, if entry.writable { 0o755 } else { 0o555 })
+  } else {
+    (FileMode::REG, if entry.writable { 0o644 } else { 0o444 })
+  };
+  let mtime = entry
+    .last_modified
+    .or(entry.created)
+    .map(|stamp| stamp.timestamp().clamp(0, u32::MAX as i64) as u32);
+  FileAttributes {
+    size: entry.size.or(Some(0)),
+    uid: Some(0),
+    user: None,
+    gid: Some(0),
+    group: None,
+    permissions: Some(kind.bits() | mode),
+    atime: mtime,
+    mtime,
+  }
+}
+
+fn ok_status(id: u32) -> Status {
+  Status { id, status_code: StatusCode::Ok, error_message: "Ok".into(), language_tag: "en-US".into() }
+}
+
+fn map_fs_error(err: FsError) -> StatusCode {
+  debug!(error = %err, "FsService error surfaced to SFTP client");
+  match err {
+    FsError::Client(ClientError::Api { status, .. }) => match status {
+      HttpStatus::NOT_FOUND => StatusCode::NoSuchFile,
+      HttpStatus::FORBIDDEN | HttpStatus::UNAUTHORIZED => StatusCode::PermissionDenied,
+      _ => StatusCode::Failure,
+    },
+    FsError::HiddenNotAllowed(_) => StatusCode::PermissionDenied,
+    FsError::NotImplemented(_) => StatusCode::OpUnsupported,
+    _ => StatusCode::Failure,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn normalize_clamps_to_root() {
+    assert_eq!(normalize_path("."), "/");
+    assert_eq!(normalize_path(""), "/");
+    assert_eq!(normalize_path("data/./a.csv"), "/data/a.csv");
+    assert_eq!(normalize_path("/data/../../etc"), "/etc");
+  }
+}
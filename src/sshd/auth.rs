@@ -0,0 +1,133 @@
+//! User authentication (RFC 4252) with public keys or a password.
+
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::{ServerConfig, SshError, keys::PublicKey, transport::Transport, wire::*};
+
+/// Failed attempts allowed before the client is disconnected, as OpenSSH's `MaxAuthTries`.
+const MAX_AUTH_ATTEMPTS: usize = 6;
+
+/// Runs the `ssh-userauth` service until the client logs in, returning its user name.
+pub(crate) async fn authenticate<S>(transport: &mut Transport<S>, config: &ServerConfig) -> Result<String, SshError>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  let request = next_message(transport).await?;
+  let mut r = Reader::new(&request[1..]);
+  if request[0] != MSG_SERVICE_REQUEST || r.string()? != "ssh-userauth" {
+    transport.disconnect(DISCONNECT_PROTOCOL_ERROR, "expected ssh-userauth").await;
+    return Err(SshError::Protocol("expected an ssh-userauth service request"));
+  }
+  transport
+    .write_payload(&Writer::message(MSG_SERVICE_ACCEPT).string("ssh-userauth").finish())
+    .await?;
+
+  let mut methods = Vec::new();
+  if !config.authorized_keys.is_empty() {
+    methods.push("publickey");
+  }
+  if config.password.is_some() {
+    methods.push("password");
+  }
+  let failure = Writer::message(MSG_USERAUTH_FAILURE).name_list(&methods).bool(false).finish();
+
+  let mut failures = 0;
+  loop {
+    let request = next_message(transport).await?;
+    if request[0] != MSG_USERAUTH_REQUEST {
+      return Err(SshError::Protocol("expected a user authentication request"));
+    }
+    let mut r = Reader::new(&request[1..]);
+    let user = r.string()?;
+    let service = r.string()?;
+    let method = r.string()?;
+    if service != "ssh-connection" {
+      transport.disconnect(DISCONNECT_PROTOCOL_ERROR, "unknown service").await;
+      return Err(SshError::Protocol("authentication requested for an unknown service"));
+    }
+    let user_allowed = config.user.as_deref().is_none_or(|allowed| allowed == user);
+
+    let accepted = match method {
+      "publickey" => {
+        let signed = r.bool()?;
+        let algorithm = r.string()?;
+        let blob = r.bytes()?;
+        let key = PublicKey::from_blob(blob)
+          .ok()
+          .filter(|key| user_allowed && key.supports(algorithm) && config.authorized_keys.contains(key));
+        match (key, signed) {
+          (Some(_), false) => {
+            // The client asks whether this key would be accepted before signing with it.
+            let ok = Writer::message(MSG_USERAUTH_PK_OK).string(algorithm).bytes(blob).finish();
+            transport.write_payload(&ok).await?;
+            continue;
+          }
+          (Some(key), true) => {
+            let signature = r.bytes()?;
+            let data = Writer::new()
+              .bytes(transport.session_id())
+              .raw(&request[..request.len() - 4 - signature.len()])
+              .finish();
+            let verified = key.verify(algorithm, &data, signature);
+            if verified {
+              info!(user, key = %key.fingerprint(), "SSH client authenticated with a public key");
+            }
+            verified
+          }
+          (None, _) => false,
+        }
+      }
+      "password" => {
+        r.bool()?;
+        let password = r.bytes()?;
+        let matches = config.password.as_deref().is_some_and(|expected| same_secret(expected.as_bytes(), password));
+        if matches && user_allowed {
+          info!(user, "SSH client authenticated with a password");
+          true
+        } else {
+          false
+        }
+      }
+      // "none" and unsupported methods only learn which methods are available.
+      _ => {
+        transport.write_payload(&failure).await?;
+        continue;
+      }
+    };
+
+    if accepted {
+      transport.write_payload(&[MSG_USERAUTH_SUCCESS]).await?;
+      return Ok(user.to_string());
+    }
+    failures += 1;
+    warn!(user, method, failures, "SSH authentication failed");
+    if failures >= MAX_AUTH_ATTEMPTS {
+      transport
+        .disconnect(DISCONNECT_NO_MORE_AUTH_METHODS_AVAILABLE, "too many authentication failures")
+        .await;
+      return Err(SshError::AuthFailed);
+    }
+    transport.write_payload(&failure).await?;
+  }
+}
+
+/// Reads the next message, re-keying if the client asks to.
+async fn next_message<S>(transport: &mut Transport<S>) -> Result<Vec<u8>, SshError>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  loop {
+    let payload = transport.read_payload().await?;
+    if payload[0] != MSG_KEXINIT {
+      return Ok(payload);
+    }
+    transport.rekey(&payload).await?;
+  }
+}
+
+/// Compares secrets in time independent of where they differ.
+fn same_secret(expected: &[u8], given: &[u8]) -> bool {
+  let (expected, given) = (Sha256::digest(expected), Sha256::digest(given));
+  expected.iter().zip(given.iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
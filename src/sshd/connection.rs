@@ -0,0 +1,510 @@
+//! Connection protocol (RFC 4254): session channels multiplexed over one transport.
+
+use std::{
+  collections::HashMap,
+  io,
+  pin::Pin,
+  sync::Arc,
+  task::{Context, Poll, Waker, ready},
+};
+
+use parking_lot::Mutex;
+use tokio::{
+  io::{AsyncRead, AsyncWrite, ReadBuf},
+  sync::mpsc,
+};
+
+use super::{Pty, SessionHandler, SessionRequest, SshError, transport::Transport, wire::*};
+
+/// Bytes a client may send on a channel before it has to wait for a window adjustment.
+const LOCAL_WINDOW: u32 = 2 * 1024 * 1024;
+const LOCAL_MAX_PACKET: u32 = 32 * 1024;
+/// Largest data packet sent, whatever the client allows.
+const MAX_DATA_PACKET: usize = 32 * 1024;
+/// Most channels open at once on one connection.
+const MAX_CHANNELS: usize = 16;
+
+/// Sent by [`Session`]s to the task that owns the transport.
+enum Outgoing {
+  Data { id: u32, data: Vec<u8> },
+  Consumed { id: u32, len: usize },
+  Eof { id: u32 },
+  Finished { id: u32, status: u32 },
+}
+
+/// Bytes the client is still willing to receive on a channel; `None` once the channel is gone.
+struct Window {
+  state: Mutex<(Option<u64>, Vec<Waker>)>,
+}
+
+impl Window {
+  fn new(credit: u32) -> Self {
+    Self { state: Mutex::new((Some(credit.into()), Vec::new())) }
+  }
+
+  fn grant(&self, bytes: u32) {
+    let mut state = self.state.lock();
+    if let Some(credit) = &mut state.0 {
+      *credit = (*credit + u64::from(bytes)).min(u32::MAX.into());
+    }
+    state.1.drain(..).for_each(Waker::wake);
+  }
+
+  fn close(&self) {
+    let mut state = self.state.lock();
+    state.0 = None;
+    state.1.drain(..).for_each(Waker::wake);
+  }
+
+  /// Ready once the channel is gone.
+  fn poll_closed(&self, cx: &mut Context<'_>) -> Poll<()> {
+    let mut state = self.state.lock();
+    if state.0.is_none() {
+      return Poll::Ready(());
+    }
+    register(&mut state.1, cx);
+    Poll::Pending
+  }
+
+  /// Takes up to `max` bytes of credit, waiting while there is none.
+  fn poll_reserve(&self, cx: &mut Context<'_>, max: usize) -> Poll<Option<usize>> {
+    let mut state = self.state.lock();
+    match &mut state.0 {
+      None => Poll::Ready(None),
+      Some(0) => {
+        register(&mut state.1, cx);
+        Poll::Pending
+      }
+      Some(credit) => {
+        let n = (*credit).min(max as u64);
+        *credit -= n;
+        Poll::Ready(Some(n as usize))
+      }
+    }
+  }
+}
+
+/// Adds the task of `cx` to `wakers` unless it is already waiting.
+fn register(wakers: &mut Vec<Waker>, cx: &Context<'_>) {
+  if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+    wakers.push(cx.waker().clone());
+  }
+}
+
+/// One session channel as seen by a [`SessionHandler`].
+///
+/// Reading yields what the client sends until it signals end of input, and
+/// writing sends to the client's stdout, waiting while the client's window is
+/// full. Shutting the writer down sends EOF.
+pub struct Session {
+  id: u32,
+  user: Arc<str>,
+  pty: Option<Pty>,
+  input: mpsc::UnboundedReceiver<Vec<u8>>,
+  resizes: Option<mpsc::UnboundedReceiver<(u32, u32)>>,
+  pending: Vec<u8>,
+  offset: usize,
+  window: Arc<Window>,
+  max_packet: usize,
+  out: mpsc::UnboundedSender<Outgoing>,
+  eof_sent: bool,
+}
+
+impl Session {
+  /// The user name the client logged in with.
+  pub fn user(&self) -> &str {
+    &self.user
+  }
+
+  /// The terminal requested with `pty-req`, if any.
+  pub fn pty(&self) -> Option<&Pty> {
+    self.pty.as_ref()
+  }
+
+  /// Resolves once the client closed the channel or disconnected.
+  pub fn closed(&self) -> impl Future<Output = ()> + Send + 'static {
+    let window = self.window.clone();
+    std::future::poll_fn(move |cx| window.poll_closed(cx))
+  }
+
+  /// Takes the stream of `(cols, rows)` sizes sent in `window-change` requests.
+  pub fn take_resizes(&mut self) -> Option<mpsc::UnboundedReceiver<(u32, u32)>> {
+    self.resizes.take()
+  }
+}
+
+impl AsyncRead for Session {
+  fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+    let this = self.get_mut();
+    if this.offset == this.pending.len() {
+      let Some(data) = ready!(this.input.poll_recv(cx)) else {
+        return Poll::Ready(Ok(()));
+      };
+      this.out.send(Outgoing::Consumed { id: this.id, len: data.len() }).ok();
+      this.pending = data;
+      this.offset = 0;
+    }
+    let n = buf.remaining().min(this.pending.len() - this.offset);
+    buf.put_slice(&this.pending[this.offset..this.offset + n]);
+    this.offset += n;
+    Poll::Ready(Ok(()))
+  }
+}
+
+impl AsyncWrite for Session {
+  fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+    if buf.is_empty() {
+      return Poll::Ready(Ok(0));
+    }
+    let closed = || io::Error::from(io::ErrorKind::BrokenPipe);
+    let Some(n) = ready!(self.window.poll_reserve(cx, buf.len().min(self.max_packet))) else {
+      return Poll::Ready(Err(closed()));
+    };
+    let data = buf[..n].to_vec();
+    self.out.send(Outgoing::Data { id: self.id, data }).map_err(|_| closed())?;
+    Poll::Ready(Ok(n))
+  }
+
+  fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    let this = self.get_mut();
+    if !this.eof_sent {
+      this.eof_sent = true;
+      this.out.send(Outgoing::Eof { id: this.id }).ok();
+    }
+    Poll::Ready(Ok(()))
+  }
+}
+
+/// Client input and window changes, waiting for the [`Session`] they belong to.
+type SessionReceivers = (mpsc::UnboundedReceiver<Vec<u8>>, mpsc::UnboundedReceiver<(u32, u32)>);
+
+/// Server side of a session channel.
+struct Channel {
+  remote_id: u32,
+  window: Arc<Window>,
+  max_packet: usize,
+  /// Bytes the client may still send, and bytes read since the last adjustment.
+  local_window: u32,
+  consumed: u32,
+  input: Option<mpsc::UnboundedSender<Vec<u8>>>,
+  resizes: mpsc::UnboundedSender<(u32, u32)>,
+  /// Handed to the [`Session`] once a shell, command or subsystem is started.
+  receivers: Option<SessionReceivers>,
+  pty: Option<Pty>,
+  eof_sent: bool,
+  close_sent: bool,
+}
+
+impl Drop for Channel {
+  fn drop(&mut self) {
+    // Wakes writers waiting for window space so they fail instead of hanging.
+    self.window.close();
+  }
+}
+
+/// Serves session channels until the client disconnects.
+pub(crate) async fn run<S, H>(mut transport: Transport<S>, user: String, handler: Arc<H>) -> Result<(), SshError>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+  H: SessionHandler,
+{
+  let (out, mut outgoing) = mpsc::unbounded_channel();
+  let mut connection = Connection {
+    user: user.into(),
+    handler,
+    channels: HashMap::new(),
+    next_id: 0,
+    out,
+  };
+  loop {
+    tokio::select! {
+      payload = transport.read_payload() => {
+        let payload = match payload {
+          Ok(payload) => payload,
+          Err(SshError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+          Err(SshError::Disconnected(_)) => return Ok(()),
+          Err(e) => return Err(e),
+        };
+        if payload[0] == MSG_KEXINIT {
+          transport.rekey(&payload).await?;
+        } else {
+          connection.handle(&mut transport, &payload).await?;
+        }
+      }
+      Some(message) = outgoing.recv() => connection.send(&mut transport, message).await?,
+    }
+  }
+}
+
+struct Connection<H> {
+  user: Arc<str>,
+  handler: Arc<H>,
+  channels: HashMap<u32, Channel>,
+  next_id: u32,
+  out: mpsc::UnboundedSender<Outgoing>,
+}
+
+impl<H: SessionHandler> Connection<H> {
+  async fn handle<S>(&mut self, transport: &mut Transport<S>, payload: &[u8]) -> Result<(), SshError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    let mut r = Reader::new(&payload[1..]);
+    match payload[0] {
+      MSG_GLOBAL_REQUEST => {
+        let name = r.string()?;
+        trace!(name, "refusing SSH global request");
+        if r.bool()? {
+          transport.write_payload(&[MSG_REQUEST_FAILURE]).await?;
+        }
+      }
+      MSG_CHANNEL_OPEN => {
+        let kind = r.string()?;
+        let remote_id = r.u32()?;
+        let window = r.u32()?;
+        let max_packet = r.u32()? as usize;
+        let refusal = if kind != "session" {
+          Some((OPEN_UNKNOWN_CHANNEL_TYPE, "only session channels are supported"))
+        } else if self.channels.len() >= MAX_CHANNELS {
+          Some((OPEN_ADMINISTRATIVELY_PROHIBITED, "too many channels"))
+        } else {
+          None
+        };
+        if let Some((reason, description)) = refusal {
+          debug!(kind, description, "refusing SSH channel");
+          let failure = Writer::message(MSG_CHANNEL_OPEN_FAILURE)
+            .u32(remote_id)
+            .u32(reason)
+            .string(description)
+            .string("")
+            .finish();
+          return transport.write_payload(&failure).await;
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let (input, input_rx) = mpsc::unbounded_channel();
+        let (resizes, resizes_rx) = mpsc::unbounded_channel();
+        self.channels.insert(
+          id,
+          Channel {
+            remote_id,
+            window: Arc::new(Window::new(window)),
+            max_packet: max_packet.clamp(1, MAX_DATA_PACKET),
+            local_window: LOCAL_WINDOW,
+            consumed: 0,
+            input: Some(input),
+            resizes,
+            receivers: Some((input_rx, resizes_rx)),
+            pty: None,
+            eof_sent: false,
+            close_sent: false,
+          },
+        );
+        let confirmation = Writer::message(MSG_CHANNEL_OPEN_CONFIRMATION)
+          .u32(remote_id)
+          .u32(id)
+          .u32(LOCAL_WINDOW)
+          .u32(LOCAL_MAX_PACKET)
+          .finish();
+        transport.write_payload(&confirmation).await?;
+      }
+      MSG_CHANNEL_WINDOW_ADJUST => {
+        let id = r.u32()?;
+        let bytes = r.u32()?;
+        if let Some(channel) = self.channels.get(&id) {
+          channel.window.grant(bytes);
+        }
+      }
+      MSG_CHANNEL_DATA => {
+        let id = r.u32()?;
+        let data = r.bytes()?;
+        let Some(channel) = self.channels.get_mut(&id) else {
+          return Ok(());
+        };
+        channel.local_window = channel
+          .local_window
+          .checked_sub(data.len() as u32)
+          .ok_or(SshError::Protocol("client overran the channel window"))?;
+        match &channel.input {
+          Some(input) if !data.is_empty() => {
+            input.send(data.to_vec()).ok();
+          }
+          // Nobody will read this, so the window is given back right away.
+          _ => self.consume(transport, id, data.len()).await?,
+        }
+      }
+      MSG_CHANNEL_EXTENDED_DATA => {
+        let id = r.u32()?;
+        r.u32()?;
+        let data = r.bytes()?;
+        if let Some(channel) = self.channels.get_mut(&id) {
+          channel.local_window = channel.local_window.saturating_sub(data.len() as u32);
+          self.consume(transport, id, data.len()).await?;
+        }
+      }
+      MSG_CHANNEL_EOF => {
+        if let Some(channel) = self.channels.get_mut(&r.u32()?) {
+          channel.input = None;
+        }
+      }
+      MSG_CHANNEL_CLOSE => {
+        if let Some(channel) = self.channels.remove(&r.u32()?)
+          && !channel.close_sent
+        {
+          transport
+            .write_payload(&Writer::message(MSG_CHANNEL_CLOSE).u32(channel.remote_id).finish())
+            .await?;
+        }
+      }
+      MSG_CHANNEL_REQUEST => {
+        let id = r.u32()?;
+        let kind = r.string()?;
+        let want_reply = r.bool()?;
+        let Some(channel) = self.channels.get_mut(&id) else {
+          return Ok(());
+        };
+        let remote_id = channel.remote_id;
+        let mut start = None;
+        let accepted = match kind {
+          "pty-req" => {
+            let term = r.string()?.to_string();
+            let cols = r.u32()?;
+            let rows = r.u32()?;
+            channel.pty = Some(Pty { term, cols, rows });
+            true
+          }
+          "window-change" => {
+            let size = (r.u32()?, r.u32()?);
+            if let Some(pty) = &mut channel.pty {
+              (pty.cols, pty.rows) = size;
+            }
+            channel.resizes.send(size).ok();
+            true
+          }
+          "shell" | "exec" | "subsystem" if channel.receivers.is_some() => {
+            let request = match kind {
+              "shell" => SessionRequest::Shell,
+              "exec" => SessionRequest::Exec(String::from_utf8_lossy(r.bytes()?).into_owned()),
+              _ => SessionRequest::Subsystem(r.string()?.to_string()),
+            };
+            let accepted = self.handler.accepts(&request);
+            if accepted {
+              start = Some(request);
+            }
+            accepted
+          }
+          _ => false,
+        };
+        debug!(kind, accepted, "SSH channel request");
+        if want_reply {
+          let reply = if accepted { MSG_CHANNEL_SUCCESS } else { MSG_CHANNEL_FAILURE };
+          transport.write_payload(&Writer::message(reply).u32(remote_id).finish()).await?;
+        }
+        if let Some(request) = start {
+          self.start(id, request);
+        }
+      }
+      // Authentication requests after success are ignored (RFC 4252 §5.1).
+      MSG_USERAUTH_REQUEST => {}
+      _ => {
+        let unimplemented = Writer::message(MSG_UNIMPLEMENTED).u32(transport.last_seq()).finish();
+        transport.write_payload(&unimplemented).await?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Hands an accepted request to the handler on its own task.
+  fn start(&mut self, id: u32, request: SessionRequest) {
+    let channel = self.channels.get_mut(&id).expect("channel exists");
+    let (input, resizes) = channel.receivers.take().expect("channel not started yet");
+    let session = Session {
+      id,
+      user: self.user.clone(),
+      pty: channel.pty.clone(),
+      input,
+      resizes: Some(resizes),
+      pending: Vec::new(),
+      offset: 0,
+      window: channel.window.clone(),
+      max_packet: channel.max_packet,
+      out: self.out.clone(),
+      eof_sent: false,
+    };
+    let handler = self.handler.clone();
+    let out = self.out.clone();
+    tokio::spawn(async move {
+      let status = handler.run(request, session).await;
+      out.send(Outgoing::Finished { id, status }).ok();
+    });
+  }
+
+  async fn send<S>(&mut self, transport: &mut Transport<S>, message: Outgoing) -> Result<(), SshError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    match message {
+      Outgoing::Data { id, data } => {
+        if let Some(channel) = self.channels.get(&id).filter(|c| !c.eof_sent && !c.close_sent) {
+          let packet = Writer::message(MSG_CHANNEL_DATA).u32(channel.remote_id).bytes(&data).finish();
+          transport.write_payload(&packet).await?;
+        }
+      }
+      Outgoing::Consumed { id, len } => self.consume(transport, id, len).await?,
+      Outgoing::Eof { id } => {
+        if let Some(channel) = self.channels.get_mut(&id).filter(|c| !c.eof_sent && !c.close_sent) {
+          channel.eof_sent = true;
+          transport
+            .write_payload(&Writer::message(MSG_CHANNEL_EOF).u32(channel.remote_id).finish())
+            .await?;
+        }
+      }
+      Outgoing::Finished { id, status } => {
+        let Some(channel) = self.channels.get_mut(&id).filter(|c| !c.close_sent) else {
+          return Ok(());
+        };
+        let remote_id = channel.remote_id;
+        if !channel.eof_sent {
+          channel.eof_sent = true;
+          transport.write_payload(&Writer::message(MSG_CHANNEL_EOF).u32(remote_id).finish()).await?;
+        }
+        channel.close_sent = true;
+        let exit_status = Writer::message(MSG_CHANNEL_REQUEST)
+          .u32(remote_id)
+          .string("exit-status")
+          .bool(false)
+          .u32(status)
+          .finish();
+        transport.write_payload(&exit_status).await?;
+        transport.write_payload(&Writer::message(MSG_CHANNEL_CLOSE).u32(remote_id).finish()).await?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Gives `len` read bytes back to the client's window, in batches.
+  async fn consume<S>(&mut self, transport: &mut Transport<S>, id: u32, len: usize) -> Result<(), SshError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    let Some(channel) = self.channels.get_mut(&id).filter(|c| !c.close_sent) else {
+      return Ok(());
+    };
+    channel.consumed += len as u32;
+    if channel.consumed >= LOCAL_WINDOW / 2 {
+      let adjust = Writer::message(MSG_CHANNEL_WINDOW_ADJUST)
+        .u32(channel.remote_id)
+        .u32(channel.consumed)
+        .finish();
+      channel.local_window += channel.consumed;
+      channel.consumed = 0;
+      transport.write_payload(&adjust).await?;
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,140 @@
+//! Embedded SSH server.
+//!
+//! Implements enough of SSH-2 for stock OpenSSH clients, offering
+//! curve25519-sha256, ssh-ed25519 host keys and chacha20-poly1305@openssh.com
+//! (OpenSSH 6.5 and later). Clients log in with a key from an `authorized_keys`
+//! file or a password; each session channel that asks for a shell, a command or
+//! a subsystem is handed to a [`SessionHandler`]. Port forwarding, agent
+//! forwarding and X11 are refused.
+
+use std::{io, sync::Arc, time::Duration};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+mod auth;
+mod connection;
+mod keys;
+mod terminal;
+mod transport;
+mod wire;
+
+pub use connection::Session;
+pub use keys::{HostKey, PublicKey};
+pub use terminal::TerminalSessions;
+use transport::Transport;
+
+/// Time a client has to finish key exchange and log in, as OpenSSH's `LoginGraceTime`.
+const LOGIN_GRACE_TIME: Duration = Duration::from_secs(120);
+
+#[derive(Debug, thiserror::Error)]
+pub enum SshError {
+  #[error("I/O error: {0}")]
+  Io(#[from] io::Error),
+  #[error("protocol error: {0}")]
+  Protocol(&'static str),
+  #[error("no common {0} algorithm")]
+  NoCommonAlgorithm(&'static str),
+  #[error("key exchange failed")]
+  KeyExchange,
+  #[error("packet authentication failed")]
+  Mac,
+  #[error("client disconnected: {0}")]
+  Disconnected(String),
+  #[error("too many authentication failures")]
+  AuthFailed,
+  #[error("login timed out")]
+  LoginTimeout,
+  #[error("{0}")]
+  Key(String),
+}
+
+/// Host key and credentials of an SSH server.
+#[derive(Debug)]
+pub struct ServerConfig {
+  host_key: Arc<HostKey>,
+  authorized_keys: Vec<PublicKey>,
+  password: Option<String>,
+  user: Option<String>,
+}
+
+impl ServerConfig {
+  /// A server that lets nobody in until keys or a password are added.
+  pub fn new(host_key: HostKey) -> Self {
+    Self {
+      host_key: Arc::new(host_key),
+      authorized_keys: Vec::new(),
+      password: None,
+      user: None,
+    }
+  }
+
+  /// Accepts clients holding one of `keys`.
+  pub fn with_authorized_keys(mut self, keys: Vec<PublicKey>) -> Self {
+    self.authorized_keys = keys;
+    self
+  }
+
+  /// Accepts clients sending `password`.
+  pub fn with_password(mut self, password: String) -> Self {
+    self.password = Some(password);
+    self
+  }
+
+  /// Only lets `user` log in; any user name is accepted otherwise.
+  pub fn with_user(mut self, user: String) -> Self {
+    self.user = Some(user);
+    self
+  }
+
+  /// Whether any client could log in.
+  pub fn has_credentials(&self) -> bool {
+    !self.authorized_keys.is_empty() || self.password.is_some()
+  }
+
+  pub fn host_key(&self) -> &HostKey {
+    &self.host_key
+  }
+}
+
+/// What a session channel asked to run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SessionRequest {
+  Shell,
+  Exec(String),
+  Subsystem(String),
+}
+
+/// Terminal requested by a client with `pty-req`.
+#[derive(Clone, Debug)]
+pub struct Pty {
+  pub term: String,
+  pub cols: u32,
+  pub rows: u32,
+}
+
+/// Runs the shells, commands and subsystems requested on session channels.
+#[async_trait::async_trait]
+pub trait SessionHandler: Send + Sync + 'static {
+  /// Whether `request` is served; refused requests fail without opening anything.
+  fn accepts(&self, request: &SessionRequest) -> bool;
+
+  /// Serves an accepted request until it finishes, returning its exit status.
+  async fn run(&self, request: SessionRequest, session: Session) -> u32;
+}
+
+/// Serves one SSH connection on `stream` until the client disconnects.
+pub async fn serve<S, H>(stream: S, config: Arc<ServerConfig>, handler: Arc<H>) -> Result<(), SshError>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+  H: SessionHandler,
+{
+  let login = async {
+    let mut transport = Transport::accept(stream, config.host_key.clone()).await?;
+    let user = auth::authenticate(&mut transport, &config).await?;
+    Ok::<_, SshError>((transport, user))
+  };
+  let (transport, user) = tokio::time::timeout(LOGIN_GRACE_TIME, login)
+    .await
+    .map_err(|_| SshError::LoginTimeout)??;
+  connection::run(transport, user, handler).await
+}
@@ -0,0 +1,212 @@
+//! Session handler that proxies shells and commands to Jupyter terminals.
+
+use std::{sync::Arc, time::Duration};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::{Session, SessionHandler, SessionRequest};
+use crate::{
+  api::{client::JupyterLabClient, jupyter::JupyterApi},
+  services::{
+    shell::{frame_id, frame_script, parse_framed_output},
+    terminal::{InputMessage, OutputMessage, TerminalError, TerminalService},
+  },
+};
+#[cfg(feature = "sftp")]
+use crate::{fs::FsService, sftp::SftpSubsystem};
+
+/// Exit status reported when a request could not be run at all, as OpenSSH does.
+const FAILED_STATUS: u32 = 255;
+/// End-of-transmission, typed into the terminal when a client with a pty closes its input.
+const EOT: &str = "\x04";
+
+/// Runs every SSH session in a Jupyter terminal of its own.
+///
+/// Shells are attached to the terminal until it exits, with `window-change`
+/// requests passed on as resizes. Commands (`ssh host cmd`) are run through
+/// [`TerminalService::call`], so their output arrives once they finish, they
+/// cannot read stdin, and stderr is merged into stdout. The terminal is
+/// deleted when the session ends.
+#[derive(Clone)]
+pub struct TerminalSessions {
+  client: Arc<JupyterLabClient>,
+  exec_timeout: Option<Duration>,
+  #[cfg(feature = "sftp")]
+  sftp: Option<SftpSubsystem>,
+}
+
+impl TerminalSessions {
+  pub fn new(client: Arc<JupyterLabClient>) -> Self {
+    Self {
+      client,
+      exec_timeout: None,
+      #[cfg(feature = "sftp")]
+      sftp: None,
+    }
+  }
+
+  /// Gives up on commands still running after `timeout`.
+  pub fn with_exec_timeout(mut self, timeout: Duration) -> Self {
+    self.exec_timeout = Some(timeout);
+    self
+  }
+
+  /// Also serves the `sftp` subsystem from `fs`, which `sftp`, `scp` and `sshfs` use.
+  #[cfg(feature = "sftp")]
+  pub fn with_sftp(mut self, fs: FsService) -> Self {
+    self.sftp = Some(SftpSubsystem::new(fs));
+    self
+  }
+
+  async fn shell(&self, mut session: Session) -> Result<u32, TerminalError> {
+    let terminal = self.client.create_terminal(None).await.map_err(TerminalError::Client)?;
+    info!(terminal = %terminal.name, user = session.user(), "SSH shell attached to Jupyter terminal");
+    let result = self.attach(&terminal.name, &mut session).await;
+    self.client.delete_terminal(&terminal.name).await.ok();
+    result.map(|()| 0)
+  }
+
+  async fn attach(&self, name: &str, session: &mut Session) -> Result<(), TerminalError> {
+    let mut service = TerminalService::connect((*self.client).clone(), name, false).await?;
+    if let Some(pty) = session.pty() {
+      service.send_message(resize(pty.cols, pty.rows)).await?;
+    }
+    let mut resizes = session.take_resizes();
+    let closed = session.closed();
+    tokio::pin!(closed);
+    let mut input = vec![0; 32 * 1024];
+    let mut pending = Vec::new();
+    let mut input_open = true;
+    loop {
+      tokio::select! {
+        read = session.read(&mut input), if input_open => match read {
+          Ok(0) | Err(_) => {
+            // A script piped into `ssh -T` ends the shell like stdin ending would;
+            // with a terminal, end-of-file is typed like Ctrl-D.
+            input_open = false;
+            let end = if session.pty().is_some() { EOT } else { "exit\n" };
+            service.send_message(InputMessage::Stdin(end.to_string())).await?;
+          }
+          Ok(n) => {
+            pending.extend_from_slice(&input[..n]);
+            let text = take_utf8(&mut pending);
+            if !text.is_empty() {
+              service.send_message(InputMessage::Stdin(text)).await?;
+            }
+          }
+        },
+        Some((cols, rows)) = async { resizes.as_mut()?.recv().await } => {
+          service.send_message(resize(cols, rows)).await?;
+        }
+        message = service.read_message() => match message? {
+          Some(OutputMessage::Stdout(text)) => {
+            if session.write_all(text.as_bytes()).await.is_err() {
+              return Ok(());
+            }
+          }
+          Some(OutputMessage::Init {}) => {}
+          Some(OutputMessage::Disconnect(_)) | None => return Ok(()),
+        },
+        () = &mut closed => return Ok(()),
+      }
+    }
+  }
+
+  async fn exec(&self, command: &str, mut session: Session) -> Result<u32, TerminalError> {
+    let terminal = self.client.create_terminal(None).await.map_err(TerminalError::Client)?;
+    info!(terminal = %terminal.name, user = session.user(), command, "SSH exec request sent to Jupyter terminal");
+    let id = frame_id();
+    let call = async {
+      let service = TerminalService::connect((*self.client).clone(), &terminal.name, false).await?;
+      // Echo is off so the `exit` typed after the command stays out of its output,
+      // and a subshell keeps `exit` in the command from skipping the closing frame.
+      let script = format!("stty -echo; {}", frame_script(&format!("(\n{command}\n)"), &id));
+      service.call(script, self.exec_timeout).await
+    };
+    let result = tokio::select! {
+      result = call => Some(result),
+      () = session.closed() => None,
+    };
+    self.client.delete_terminal(&terminal.name).await.ok();
+    let Some(result) = result else {
+      return Ok(FAILED_STATUS);
+    };
+
+    let stdout = result?.stdout;
+    let Some(output) = parse_framed_output(&stdout, &id) else {
+      warn!(command, "SSH exec output was not framed as expected");
+      return Ok(FAILED_STATUS);
+    };
+    session.write_all(output.output.as_bytes()).await.ok();
+    Ok(output.status as u32)
+  }
+}
+
+#[async_trait::async_trait]
+impl SessionHandler for TerminalSessions {
+  fn accepts(&self, request: &SessionRequest) -> bool {
+    match request {
+      SessionRequest::Shell | SessionRequest::Exec(_) => true,
+      #[cfg(feature = "sftp")]
+      SessionRequest::Subsystem(name) => name == "sftp" && self.sftp.is_some(),
+      #[cfg(not(feature = "sftp"))]
+      SessionRequest::Subsystem(_) => false,
+    }
+  }
+
+  async fn run(&self, request: SessionRequest, session: Session) -> u32 {
+    let result = match request {
+      SessionRequest::Shell => self.shell(session).await,
+      SessionRequest::Exec(command) => self.exec(&command, session).await,
+      #[cfg(feature = "sftp")]
+      SessionRequest::Subsystem(_) => match &self.sftp {
+        Some(sftp) => Ok(sftp.run(request, session).await),
+        None => Ok(FAILED_STATUS),
+      },
+      #[cfg(not(feature = "sftp"))]
+      SessionRequest::Subsystem(_) => Ok(FAILED_STATUS),
+    };
+    result.unwrap_or_else(|error| {
+      warn!(%error, "SSH session failed");
+      FAILED_STATUS
+    })
+  }
+}
+
+fn resize(cols: u32, rows: u32) -> InputMessage {
+  InputMessage::Resize {
+    cols: cols.clamp(1, u16::MAX.into()) as u16,
+    rows: rows.clamp(1, u16::MAX.into()) as u16,
+  }
+}
+
+/// Removes and returns the longest valid UTF-8 prefix of `pending`, keeping a
+/// character split across reads for the next call; invalid bytes are replaced.
+fn take_utf8(pending: &mut Vec<u8>) -> String {
+  let complete = match std::str::from_utf8(pending) {
+    Ok(_) => pending.len(),
+    Err(e) if e.error_len().is_none() => e.valid_up_to(),
+    Err(_) => pending.len(),
+  };
+  let text = String::from_utf8_lossy(&pending[..complete]).into_owned();
+  pending.drain(..complete);
+  text
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn take_utf8_keeps_split_characters_for_the_next_read() {
+    let mut pending = "é".as_bytes()[..1].to_vec();
+    assert_eq!(take_utf8(&mut pending), "");
+    pending.extend_from_slice(&"é".as_bytes()[1..]);
+    pending.extend_from_slice(b"x");
+    assert_eq!(take_utf8(&mut pending), "éx");
+    assert!(pending.is_empty());
+
+    pending.extend_from_slice(b"\xffa");
+    assert_eq!(take_utf8(&mut pending), "\u{fffd}a");
+  }
+}
@@ -0,0 +1,398 @@
+//! SSH transport layer (RFC 4253): version exchange, binary packets and key exchange.
+//!
+//! One algorithm is offered per slot: curve25519-sha256 key exchange, an
+//! ssh-ed25519 host key and chacha20-poly1305@openssh.com, which every OpenSSH
+//! release since 6.5 supports. Strict key exchange (the Terrapin countermeasure)
+//! is enabled when the client offers it.
+
+use std::sync::Arc;
+
+use ring::{
+  aead::chacha20_poly1305_openssh::{KEY_LEN, OpeningKey, PACKET_LENGTH_LEN, SealingKey, TAG_LEN},
+  agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, X25519},
+  rand::{SecureRandom, SystemRandom},
+};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::{
+  SshError,
+  keys::{CLIENT_SIGNATURE_ALGORITHMS, HostKey},
+  wire::*,
+};
+
+const SERVER_VERSION: &str = concat!("SSH-2.0-jupyter_shell_", env!("CARGO_PKG_VERSION"));
+/// Largest packet accepted, as in OpenSSH.
+const MAX_PACKET_LEN: usize = 256 * 1024;
+/// Longest identification line, and most lines a client may send before it.
+const MAX_VERSION_LINE: usize = 255;
+const MAX_BANNER_LINES: usize = 32;
+
+const KEX_ALGORITHMS: &[&str] = &["curve25519-sha256", "curve25519-sha256@libssh.org"];
+const STRICT_KEX_SERVER: &str = "kex-strict-s-v00@openssh.com";
+const STRICT_KEX_CLIENT: &str = "kex-strict-c-v00@openssh.com";
+const EXT_INFO_CLIENT: &str = "ext-info-c";
+const CIPHER: &str = "chacha20-poly1305@openssh.com";
+/// Never used with an AEAD cipher, but some clients refuse an empty MAC list.
+const MAC: &str = "hmac-sha2-256";
+
+/// An established, encrypted SSH connection.
+pub(crate) struct Transport<S> {
+  stream: S,
+  rbuf: Vec<u8>,
+  rng: SystemRandom,
+  host_key: Arc<HostKey>,
+  client_version: Vec<u8>,
+  session_id: Option<Vec<u8>>,
+  opening: Option<OpeningKey>,
+  sealing: Option<SealingKey>,
+  recv_seq: u32,
+  send_seq: u32,
+  /// Strict key exchange was negotiated; set during the first key exchange.
+  strict: bool,
+  /// Whether unexpected messages are being refused during the first strict key exchange.
+  in_strict_kex: bool,
+}
+
+impl<S> Transport<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  /// Exchanges versions and keys with a client that just connected.
+  pub(crate) async fn accept(stream: S, host_key: Arc<HostKey>) -> Result<Self, SshError> {
+    let mut transport = Self {
+      stream,
+      rbuf: Vec::new(),
+      rng: SystemRandom::new(),
+      host_key,
+      client_version: Vec::new(),
+      session_id: None,
+      opening: None,
+      sealing: None,
+      recv_seq: 0,
+      send_seq: 0,
+      strict: false,
+      in_strict_kex: false,
+    };
+    transport.stream.write_all(format!("{SERVER_VERSION}\r\n").as_bytes()).await?;
+    transport.client_version = transport.read_version().await?;
+    debug!(client = %String::from_utf8_lossy(&transport.client_version), "SSH client identified");
+
+    let ours = transport.kexinit()?;
+    transport.write_payload(&ours).await?;
+    // Before the first key exchange only a few transport messages may precede KEXINIT.
+    let theirs = loop {
+      let payload = transport.read_payload().await?;
+      match payload[0] {
+        MSG_KEXINIT => break payload,
+        MSG_IGNORE | MSG_DEBUG => continue,
+        _ => return Err(SshError::Protocol("expected KEXINIT")),
+      }
+    };
+    let ext_info = transport.key_exchange(&theirs, &ours).await?;
+    if ext_info {
+      let ext = Writer::message(MSG_EXT_INFO)
+        .u32(1)
+        .string("server-sig-algs")
+        .name_list(CLIENT_SIGNATURE_ALGORITHMS)
+        .finish();
+      transport.write_payload(&ext).await?;
+    }
+    Ok(transport)
+  }
+
+  /// The exchange hash of the first key exchange.
+  pub(crate) fn session_id(&self) -> &[u8] {
+    self.session_id.as_deref().unwrap_or_default()
+  }
+
+  /// Sequence number of the last packet read, for `SSH_MSG_UNIMPLEMENTED`.
+  pub(crate) fn last_seq(&self) -> u32 {
+    self.recv_seq.wrapping_sub(1)
+  }
+
+  /// Re-keys after the client sent `kexinit`. No other message may be sent until this returns.
+  pub(crate) async fn rekey(&mut self, kexinit: &[u8]) -> Result<(), SshError> {
+    let ours = self.kexinit()?;
+    self.write_payload(&ours).await?;
+    self.key_exchange(kexinit, &ours).await?;
+    Ok(())
+  }
+
+  /// Reads the next message, skipping `IGNORE`, `DEBUG` and `UNIMPLEMENTED`.
+  ///
+  /// Cancel-safe: partially received packets stay buffered for the next call.
+  pub(crate) async fn read_payload(&mut self) -> Result<Vec<u8>, SshError> {
+    loop {
+      while let Some(payload) = self.decode_packet()? {
+        match payload[0] {
+          MSG_IGNORE | MSG_DEBUG | MSG_UNIMPLEMENTED if !self.in_strict_kex => continue,
+          MSG_DISCONNECT => {
+            let mut r = Reader::new(&payload[1..]);
+            r.u32()?;
+            return Err(SshError::Disconnected(r.string().unwrap_or_default().to_string()));
+          }
+          _ => return Ok(payload),
+        }
+      }
+      self.fill().await?;
+    }
+  }
+
+  pub(crate) async fn write_payload(&mut self, payload: &[u8]) -> Result<(), SshError> {
+    // chacha20-poly1305 leaves the length field out of the block alignment.
+    let aligned = payload.len() + 1 + if self.sealing.is_some() { 0 } else { PACKET_LENGTH_LEN };
+    let mut padding = 8 - aligned % 8;
+    if padding < 4 {
+      padding += 8;
+    }
+    let len = 1 + payload.len() + padding;
+    let mut packet = Vec::with_capacity(PACKET_LENGTH_LEN + len + TAG_LEN);
+    packet.extend_from_slice(&(len as u32).to_be_bytes());
+    packet.push(padding as u8);
+    packet.extend_from_slice(payload);
+    let mut pad = [0; 16];
+    self.rng.fill(&mut pad[..padding]).map_err(|_| SshError::KeyExchange)?;
+    packet.extend_from_slice(&pad[..padding]);
+    if let Some(key) = &self.sealing {
+      let mut tag = [0; TAG_LEN];
+      key.seal_in_place(self.send_seq, &mut packet, &mut tag);
+      packet.extend_from_slice(&tag);
+    }
+    self.stream.write_all(&packet).await?;
+    self.stream.flush().await?;
+    self.send_seq = self.send_seq.wrapping_add(1);
+    Ok(())
+  }
+
+  /// Sends `SSH_MSG_DISCONNECT`, ignoring failures since the connection is going away.
+  pub(crate) async fn disconnect(&mut self, reason: u32, description: &str) {
+    let payload = Writer::message(MSG_DISCONNECT)
+      .u32(reason)
+      .string(description)
+      .string("")
+      .finish();
+    self.write_payload(&payload).await.ok();
+  }
+
+  async fn fill(&mut self) -> Result<(), SshError> {
+    self.rbuf.reserve(32 * 1024);
+    if self.stream.read_buf(&mut self.rbuf).await? == 0 {
+      return Err(SshError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(())
+  }
+
+  async fn read_version(&mut self) -> Result<Vec<u8>, SshError> {
+    for _ in 0..MAX_BANNER_LINES {
+      let end = loop {
+        if let Some(end) = self.rbuf.iter().position(|b| *b == b'\n') {
+          break end;
+        }
+        if self.rbuf.len() > MAX_VERSION_LINE {
+          return Err(SshError::Protocol("identification line too long"));
+        }
+        self.fill().await?;
+      };
+      let mut line: Vec<u8> = self.rbuf.drain(..=end).collect();
+      line.pop();
+      if line.last() == Some(&b'\r') {
+        line.pop();
+      }
+      if line.starts_with(b"SSH-") {
+        if !line.starts_with(b"SSH-2.0-") && !line.starts_with(b"SSH-1.99-") {
+          return Err(SshError::Protocol("unsupported SSH protocol version"));
+        }
+        return Ok(line);
+      }
+    }
+    Err(SshError::Protocol("no SSH identification line"))
+  }
+
+  fn decode_packet(&mut self) -> Result<Option<Vec<u8>>, SshError> {
+    if self.rbuf.len() < PACKET_LENGTH_LEN {
+      return Ok(None);
+    }
+    let mut len_bytes = [0; PACKET_LENGTH_LEN];
+    len_bytes.copy_from_slice(&self.rbuf[..PACKET_LENGTH_LEN]);
+    if let Some(key) = &self.opening {
+      len_bytes = key.decrypt_packet_length(self.recv_seq, len_bytes);
+    }
+    let len = u32::from_be_bytes(len_bytes) as usize;
+    if !(5..=MAX_PACKET_LEN).contains(&len) {
+      return Err(SshError::Protocol("bad packet length"));
+    }
+    let tag_len = if self.opening.is_some() { TAG_LEN } else { 0 };
+    let total = PACKET_LENGTH_LEN + len + tag_len;
+    if self.rbuf.len() < total {
+      return Ok(None);
+    }
+
+    let mut packet: Vec<u8> = self.rbuf.drain(..total).collect();
+    if let Some(key) = &self.opening {
+      let (body, tag) = packet.split_at_mut(PACKET_LENGTH_LEN + len);
+      let tag: &[u8; TAG_LEN] = (&*tag).try_into().expect("tag length");
+      key.open_in_place(self.recv_seq, body, tag).map_err(|_| SshError::Mac)?;
+    }
+    self.recv_seq = self.recv_seq.wrapping_add(1);
+    let padding = packet[PACKET_LENGTH_LEN] as usize;
+    if padding < 4 || padding + 1 >= len {
+      return Err(SshError::Protocol("bad packet padding"));
+    }
+    Ok(Some(packet[PACKET_LENGTH_LEN + 1..PACKET_LENGTH_LEN + len - padding].to_vec()))
+  }
+
+  fn kexinit(&self) -> Result<Vec<u8>, SshError> {
+    let mut cookie = [0; 16];
+    self.rng.fill(&mut cookie).map_err(|_| SshError::KeyExchange)?;
+    let mut kex = KEX_ALGORITHMS.to_vec();
+    if self.session_id.is_none() {
+      kex.push(STRICT_KEX_SERVER);
+    }
+    Ok(
+      Writer::message(MSG_KEXINIT)
+        .raw(&cookie)
+        .name_list(&kex)
+        .name_list(&[self.host_key.algorithm()])
+        .name_list(&[CIPHER])
+        .name_list(&[CIPHER])
+        .name_list(&[MAC])
+        .name_list(&[MAC])
+        .name_list(&["none"])
+        .name_list(&["none"])
+        .name_list(&[])
+        .name_list(&[])
+        .bool(false)
+        .u32(0)
+        .finish(),
+    )
+  }
+
+  /// Runs curve25519-sha256 after both KEXINITs were exchanged and switches to
+  /// the new keys. Returns whether the client asked for `SSH_MSG_EXT_INFO`.
+  async fn key_exchange(&mut self, theirs: &[u8], ours: &[u8]) -> Result<bool, SshError> {
+    let first = self.session_id.is_none();
+    let mut r = Reader::new(&theirs[1..]);
+    for _ in 0..16 {
+      r.u8()?;
+    }
+    let kex = r.name_list()?;
+    let host_key_algorithms = r.name_list()?;
+    let ciphers = [r.name_list()?, r.name_list()?];
+    // MACs are not negotiated with an AEAD cipher.
+    r.name_list()?;
+    r.name_list()?;
+    let compression = [r.name_list()?, r.name_list()?];
+    r.name_list()?;
+    r.name_list()?;
+    let guess_follows = r.bool()?;
+
+    let kex_algorithm = kex
+      .iter()
+      .find(|name| KEX_ALGORITHMS.contains(name))
+      .ok_or(SshError::NoCommonAlgorithm("key exchange"))?;
+    if !host_key_algorithms.contains(&self.host_key.algorithm()) {
+      return Err(SshError::NoCommonAlgorithm("host key"));
+    }
+    if !ciphers.iter().all(|list| list.contains(&CIPHER)) {
+      return Err(SshError::NoCommonAlgorithm("cipher"));
+    }
+    if !compression.iter().all(|list| list.contains(&"none")) {
+      return Err(SshError::NoCommonAlgorithm("compression"));
+    }
+    if first {
+      self.strict = kex.contains(&STRICT_KEX_CLIENT);
+      self.in_strict_kex = self.strict;
+    }
+    let ext_info = first && kex.contains(&EXT_INFO_CLIENT);
+    trace!(kex = kex_algorithm, strict = self.strict, "SSH algorithms negotiated");
+    if guess_follows && (kex.first() != Some(kex_algorithm) || host_key_algorithms.first() != Some(&self.host_key.algorithm())) {
+      // The client guessed wrong; its first key exchange packet must be ignored.
+      self.read_kex_message().await?;
+    }
+
+    let init = self.read_kex_message().await?;
+    let mut r = Reader::new(&init[1..]);
+    if init[0] != MSG_KEX_ECDH_INIT {
+      return Err(SshError::Protocol("expected KEX_ECDH_INIT"));
+    }
+    let client_public = r.bytes()?.to_vec();
+    if client_public.len() != 32 {
+      return Err(SshError::KeyExchange);
+    }
+    let private = EphemeralPrivateKey::generate(&X25519, &self.rng).map_err(|_| SshError::KeyExchange)?;
+    let server_public = private.compute_public_key().map_err(|_| SshError::KeyExchange)?;
+    let shared = agreement::agree_ephemeral(private, &UnparsedPublicKey::new(&X25519, &client_public), |k| k.to_vec())
+      .map_err(|_| SshError::KeyExchange)?;
+    if shared.iter().all(|b| *b == 0) {
+      return Err(SshError::KeyExchange);
+    }
+    let shared = Writer::new().mpint(&shared).finish();
+
+    let host_key_blob = self.host_key.blob();
+    let hash: Vec<u8> = Sha256::digest(
+      Writer::new()
+        .bytes(&self.client_version)
+        .string(SERVER_VERSION)
+        .bytes(theirs)
+        .bytes(ours)
+        .bytes(&host_key_blob)
+        .bytes(&client_public)
+        .bytes(server_public.as_ref())
+        .raw(&shared)
+        .finish(),
+    )
+    .to_vec();
+    let session_id = self.session_id.get_or_insert_with(|| hash.clone()).clone();
+
+    let reply = Writer::message(MSG_KEX_ECDH_REPLY)
+      .bytes(&host_key_blob)
+      .bytes(server_public.as_ref())
+      .bytes(&self.host_key.sign(&hash))
+      .finish();
+    self.write_payload(&reply).await?;
+    self.write_payload(&[MSG_NEWKEYS]).await?;
+    self.sealing = Some(SealingKey::new(&derive_key(&shared, &hash, b'D', &session_id)));
+    if self.strict {
+      self.send_seq = 0;
+    }
+
+    if self.read_kex_message().await?[0] != MSG_NEWKEYS {
+      return Err(SshError::Protocol("expected NEWKEYS"));
+    }
+    self.opening = Some(OpeningKey::new(&derive_key(&shared, &hash, b'C', &session_id)));
+    if self.strict {
+      self.recv_seq = 0;
+    }
+    self.in_strict_kex = false;
+    Ok(ext_info)
+  }
+
+  /// Reads a message during key exchange, refusing anything that is not part of it.
+  async fn read_kex_message(&mut self) -> Result<Vec<u8>, SshError> {
+    let payload = self.read_payload().await?;
+    if !(MSG_KEXINIT..=MSG_KEX_ECDH_REPLY).contains(&payload[0]) {
+      return Err(SshError::Protocol("unexpected message during key exchange"));
+    }
+    Ok(payload)
+  }
+}
+
+/// Derives a chacha20-poly1305@openssh.com key (RFC 4253 §7.2), extended to its 64 bytes.
+fn derive_key(shared: &[u8], hash: &[u8], letter: u8, session_id: &[u8]) -> [u8; KEY_LEN] {
+  let first = Sha256::new()
+    .chain_update(shared)
+    .chain_update(hash)
+    .chain_update([letter])
+    .chain_update(session_id)
+    .finalize();
+  let second = Sha256::new()
+    .chain_update(shared)
+    .chain_update(hash)
+    .chain_update(first)
+    .finalize();
+  let mut key = [0; KEY_LEN];
+  key[..32].copy_from_slice(&first);
+  key[32..].copy_from_slice(&second);
+  key
+}
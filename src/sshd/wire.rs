@@ -0,0 +1,160 @@
+//! SSH data types (RFC 4251 §5) and message numbers.
+
+use super::SshError;
+
+pub(crate) const MSG_DISCONNECT: u8 = 1;
+pub(crate) const MSG_IGNORE: u8 = 2;
+pub(crate) const MSG_UNIMPLEMENTED: u8 = 3;
+pub(crate) const MSG_DEBUG: u8 = 4;
+pub(crate) const MSG_SERVICE_REQUEST: u8 = 5;
+pub(crate) const MSG_SERVICE_ACCEPT: u8 = 6;
+pub(crate) const MSG_EXT_INFO: u8 = 7;
+pub(crate) const MSG_KEXINIT: u8 = 20;
+pub(crate) const MSG_NEWKEYS: u8 = 21;
+pub(crate) const MSG_KEX_ECDH_INIT: u8 = 30;
+pub(crate) const MSG_KEX_ECDH_REPLY: u8 = 31;
+pub(crate) const MSG_USERAUTH_REQUEST: u8 = 50;
+pub(crate) const MSG_USERAUTH_FAILURE: u8 = 51;
+pub(crate) const MSG_USERAUTH_SUCCESS: u8 = 52;
+pub(crate) const MSG_USERAUTH_PK_OK: u8 = 60;
+pub(crate) const MSG_GLOBAL_REQUEST: u8 = 80;
+pub(crate) const MSG_REQUEST_FAILURE: u8 = 82;
+pub(crate) const MSG_CHANNEL_OPEN: u8 = 90;
+pub(crate) const MSG_CHANNEL_OPEN_CONFIRMATION: u8 = 91;
+pub(crate) const MSG_CHANNEL_OPEN_FAILURE: u8 = 92;
+pub(crate) const MSG_CHANNEL_WINDOW_ADJUST: u8 = 93;
+pub(crate) const MSG_CHANNEL_DATA: u8 = 94;
+pub(crate) const MSG_CHANNEL_EXTENDED_DATA: u8 = 95;
+pub(crate) const MSG_CHANNEL_EOF: u8 = 96;
+pub(crate) const MSG_CHANNEL_CLOSE: u8 = 97;
+pub(crate) const MSG_CHANNEL_REQUEST: u8 = 98;
+pub(crate) const MSG_CHANNEL_SUCCESS: u8 = 99;
+pub(crate) const MSG_CHANNEL_FAILURE: u8 = 100;
+
+pub(crate) const DISCONNECT_PROTOCOL_ERROR: u32 = 2;
+pub(crate) const DISCONNECT_NO_MORE_AUTH_METHODS_AVAILABLE: u32 = 14;
+
+pub(crate) const OPEN_ADMINISTRATIVELY_PROHIBITED: u32 = 1;
+pub(crate) const OPEN_UNKNOWN_CHANNEL_TYPE: u32 = 3;
+
+/// Decodes SSH data types from the front of a buffer.
+pub(crate) struct Reader<'a> {
+  buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+  pub(crate) fn new(buf: &'a [u8]) -> Self {
+    Self { buf }
+  }
+
+  fn take(&mut self, n: usize) -> Result<&'a [u8], SshError> {
+    if self.buf.len() < n {
+      return Err(SshError::Protocol("truncated message"));
+    }
+    let (head, rest) = self.buf.split_at(n);
+    self.buf = rest;
+    Ok(head)
+  }
+
+  pub(crate) fn u8(&mut self) -> Result<u8, SshError> {
+    Ok(self.take(1)?[0])
+  }
+
+  pub(crate) fn bool(&mut self) -> Result<bool, SshError> {
+    Ok(self.u8()? != 0)
+  }
+
+  pub(crate) fn u32(&mut self) -> Result<u32, SshError> {
+    let bytes = self.take(4)?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+  }
+
+  /// An SSH `string`: arbitrary bytes behind a length prefix.
+  pub(crate) fn bytes(&mut self) -> Result<&'a [u8], SshError> {
+    let len = self.u32()? as usize;
+    self.take(len)
+  }
+
+  /// An SSH `string` that must be UTF-8 text.
+  pub(crate) fn string(&mut self) -> Result<&'a str, SshError> {
+    std::str::from_utf8(self.bytes()?).map_err(|_| SshError::Protocol("string is not UTF-8"))
+  }
+
+  /// A non-negative `mpint` as big-endian magnitude bytes without leading zeros.
+  pub(crate) fn mpint(&mut self) -> Result<&'a [u8], SshError> {
+    let bytes = self.bytes()?;
+    if bytes.first().is_some_and(|b| b & 0x80 != 0) {
+      return Err(SshError::Protocol("negative mpint"));
+    }
+    let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    Ok(&bytes[start..])
+  }
+
+  pub(crate) fn name_list(&mut self) -> Result<Vec<&'a str>, SshError> {
+    let list = self.string()?;
+    Ok(if list.is_empty() { Vec::new() } else { list.split(',').collect() })
+  }
+}
+
+/// Encodes one SSH message.
+pub(crate) struct Writer {
+  buf: Vec<u8>,
+}
+
+impl Writer {
+  /// Starts a message with its message number.
+  pub(crate) fn message(msg: u8) -> Self {
+    Self { buf: vec![msg] }
+  }
+
+  /// Starts a bare buffer, e.g. a key blob or data to be hashed.
+  pub(crate) fn new() -> Self {
+    Self { buf: Vec::new() }
+  }
+
+  pub(crate) fn u8(mut self, value: u8) -> Self {
+    self.buf.push(value);
+    self
+  }
+
+  pub(crate) fn bool(self, value: bool) -> Self {
+    self.u8(value as u8)
+  }
+
+  pub(crate) fn u32(mut self, value: u32) -> Self {
+    self.buf.extend_from_slice(&value.to_be_bytes());
+    self
+  }
+
+  pub(crate) fn raw(mut self, bytes: &[u8]) -> Self {
+    self.buf.extend_from_slice(bytes);
+    self
+  }
+
+  pub(crate) fn bytes(self, bytes: &[u8]) -> Self {
+    self.u32(bytes.len() as u32).raw(bytes)
+  }
+
+  pub(crate) fn string(self, text: &str) -> Self {
+    self.bytes(text.as_bytes())
+  }
+
+  /// A non-negative `mpint` from big-endian magnitude bytes.
+  pub(crate) fn mpint(self, magnitude: &[u8]) -> Self {
+    let start = magnitude.iter().position(|b| *b != 0).unwrap_or(magnitude.len());
+    let magnitude = &magnitude[start..];
+    if magnitude.first().is_some_and(|b| b & 0x80 != 0) {
+      self.u32(magnitude.len() as u32 + 1).u8(0).raw(magnitude)
+    } else {
+      self.bytes(magnitude)
+    }
+  }
+
+  pub(crate) fn name_list(self, names: &[&str]) -> Self {
+    self.string(&names.join(","))
+  }
+
+  pub(crate) fn finish(self) -> Vec<u8> {
+    self.buf
+  }
+}
@@ -50,6 +50,25 @@ impl<K: std::hash::Hash + Eq, V> Cached<K, V> {
   {
     self.map.read().get(key).cloned()
   }
+
+  pub fn remove<Q>(&self, key: &Q) -> Option<V>
+  where
+    Q: ?Sized + std::hash::Hash + Equivalent<K>,
+  {
+    self.map.write().remove(key)
+  }
+
+  pub fn retain(&self, keep: impl FnMut(&K, &mut V) -> bool) {
+    self.map.write().retain(keep);
+  }
+
+  pub fn len(&self) -> usize {
+    self.map.read().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.map.read().is_empty()
+  }
 }
 
 pub struct State {
@@ -136,6 +155,8 @@ use super::*;
     assert!(cache.get("key1").is_none());
     cache.insert("key1".to_string(), 42);
     assert_eq!(cache.get("key1"), Some(42));
+    assert_eq!(cache.remove("key1"), Some(42));
+    assert!(cache.get("key1").is_none());
   }
 
   #[tokio::test]
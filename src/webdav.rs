@@ -0,0 +1,388 @@
+use std::{fmt, io::SeekFrom, time::SystemTime};
+
+use bytes::{Buf, Bytes};
+use dav_server::{
+  davpath::DavPath,
+  fakels::FakeLs,
+  fs::{
+    DavDirEntry, DavFile, DavFileSystem, DavMetaData, FsError as DavError, FsFuture, FsResult, FsStream, OpenOptions,
+    ReadDirMeta,
+  },
+  DavHandler, DavMethodSet,
+};
+use futures_util::{stream, FutureExt};
+use reqwest::StatusCode;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::{
+  api::client::ClientError,
+  fs::{Entry, FsError, FsService, UploadPipe},
+};
+
+/// Bytes buffered per Contents API save while a client uploads a file.
+const UPLOAD_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Build a WebDAV request handler that serves `fs`.
+///
+/// Locks are accepted but not enforced (`LOCK` always succeeds), which is what
+/// macOS Finder and Windows Explorer need to mount a share read-write. With
+/// `read_only`, only `GET`, `HEAD`, `OPTIONS` and `PROPFIND` are allowed.
+pub fn handler(fs: FsService, read_only: bool) -> DavHandler {
+  let methods = if read_only { DavMethodSet::WEBDAV_RO } else { DavMethodSet::WEBDAV_RW };
+  DavHandler::builder()
+    .filesystem(Box::new(JupyterDavFs { fs }))
+    .locksystem(FakeLs::new())
+    .methods(methods)
+    .build_handler()
+}
+
+/// [`DavFileSystem`] backed by the Jupyter Contents API.
+#[derive(Clone)]
+pub struct JupyterDavFs {
+  fs: FsService,
+}
+
+impl JupyterDavFs {
+  pub fn new(fs: FsService) -> Self {
+    Self { fs }
+  }
+}
+
+impl DavFileSystem for JupyterDavFs {
+  fn open<'a>(&'a self, path: &'a DavPath, options: OpenOptions) -> FsFuture<'a, Box<dyn DavFile>> {
+    async move {
+      let target = jupyter_path(path);
+      let existing = match self.fs.metadata(&target).await {
+        Ok(entry) if entry.kind.is_directory() => return Err(DavError::Forbidden),
+        Ok(entry) => Some(entry),
+        Err(err) if err.is_not_found() => None,
+        Err(err) => return Err(map_fs_error(err)),
+      };
+      if !(options.write || options.append) {
+        let entry = existing.ok_or(DavError::NotFound)?;
+        trace!(path = %target, "WebDAV file opened for reading");
+        return Ok(Box::new(JupyterFile::reading(self.fs.clone(), entry)) as Box<dyn DavFile>);
+      }
+      match &existing {
+        Some(_) if options.create_new => return Err(DavError::Exists),
+        None if !options.create => return Err(DavError::NotFound),
+        _ => {}
+      }
+      debug!(path = %target, append = options.append, truncate = options.truncate, "WebDAV file opened for writing");
+      let file = JupyterFile {
+        fs: self.fs.clone(),
+        path: target,
+        entry: existing,
+        position: 0,
+        state: FileState::Write { truncate: options.truncate, upload: None },
+      };
+      Ok(Box::new(file) as Box<dyn DavFile>)
+    }
+    .boxed()
+  }
+
+  fn read_dir<'a>(&'a self, path: &'a DavPath, _meta: ReadDirMeta) -> FsFuture<'a, FsStream<Box<dyn DavDirEntry>>> {
+    async move {
+      let target = jupyter_path(path);
+      trace!(path = %target, "WebDAV directory listing");
+      let entry = self.fs.metadata(&target).await.map_err(map_fs_error)?;
+      if !entry.kind.is_directory() {
+        return Err(DavError::Forbidden);
+      }
+      let entries = self.fs.ls(&target).await.map_err(map_fs_error)?;
+      let entries = entries
+        .into_iter()
+        .map(|entry| Ok(Box::new(JupyterDirEntry(EntryMeta(entry))) as Box<dyn DavDirEntry>));
+      Ok(Box::pin(stream::iter(entries)) as FsStream<Box<dyn DavDirEntry>>)
+    }
+    .boxed()
+  }
+
+  fn metadata<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, Box<dyn DavMetaData>> {
+    async move {
+      let entry = self.fs.metadata(&jupyter_path(path)).await.map_err(map_fs_error)?;
+      Ok(Box::new(EntryMeta(entry)) as Box<dyn DavMetaData>)
+    }
+    .boxed()
+  }
+
+  fn create_dir<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+    async move {
+      let target = jupyter_path(path);
+      debug!(path = %target, "WebDAV mkcol");
+      match self.fs.metadata(&target).await {
+        Ok(_) => return Err(DavError::Exists),
+        Err(err) if err.is_not_found() => {}
+        Err(err) => return Err(map_fs_error(err)),
+      }
+      self.fs.mkdir(&target).await.map_err(map_fs_error)?;
+      Ok(())
+    }
+    .boxed()
+  }
+
+  fn remove_dir<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+    async move {
+      let target = jupyter_path(path);
+      debug!(path = %target, "WebDAV remove directory");
+      self.fs.rmdir(&target, false).await.map_err(map_fs_error)
+    }
+    .boxed()
+  }
+
+  fn remove_file<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+    async move {
+      let target = jupyter_path(path);
+      debug!(path = %target, "WebDAV delete");
+      self.fs.rm(&target).await.map_err(map_fs_error)
+    }
+    .boxed()
+  }
+
+  fn rename<'a>(&'a self, from: &'a DavPath, to: &'a DavPath) -> FsFuture<'a, ()> {
+    async move {
+      let (source, dest) = (jupyter_path(from), jupyter_path(to));
+      debug!(%source, %dest, "WebDAV move");
+      self.fs.rename(&source, &dest).await.map_err(map_fs_error)?;
+      Ok(())
+    }
+    .boxed()
+  }
+
+  fn copy<'a>(&'a self, from: &'a DavPath, to: &'a DavPath) -> FsFuture<'a, ()> {
+    async move {
+      let (source, dest) = (jupyter_path(from), jupyter_path(to));
+      debug!(%source, %dest, "WebDAV copy");
+      self.fs.copy(&source, &dest).await.map_err(map_fs_error)?;
+      Ok(())
+    }
+    .boxed()
+  }
+}
+
+#[derive(Debug, Clone)]
+struct EntryMeta(Entry);
+
+impl DavMetaData for EntryMeta {
+  fn len(&self) -> u64 {
+    self.0.size.unwrap_or(0)
+  }
+
+  fn modified(&self) -> FsResult<SystemTime> {
+    self.0.last_modified.map(SystemTime::from).ok_or(DavError::GeneralFailure)
+  }
+
+  fn created(&self) -> FsResult<SystemTime> {
+    self.0.created.map(SystemTime::from).ok_or(DavError::NotImplemented)
+  }
+
+  fn is_dir(&self) -> bool {
+    self.0.kind.is_directory()
+  }
+}
+
+struct JupyterDirEntry(EntryMeta);
+
+impl DavDirEntry for JupyterDirEntry {
+  fn name(&self) -> Vec<u8> {
+    self.0.0.name.clone().into_bytes()
+  }
+
+  fn metadata(&self) -> FsFuture<'_, Box<dyn DavMetaData>> {
+    let meta: Box<dyn DavMetaData> = Box::new(self.0.clone());
+    futures_util::future::ready(Ok(meta)).boxed()
+  }
+}
+
+/// An open WebDAV file: a download that follows `seek`, or an upload fed by `PUT`.
+struct JupyterFile {
+  fs: FsService,
+  path: String,
+  /// Metadata when the file was opened; `None` for a file that is being created.
+  entry: Option<Entry>,
+  position: u64,
+  state: FileState,
+}
+
+enum FileState {
+  Read {
+    /// Open download and the offset it will read next.
+    reader: Option<(Box<dyn AsyncRead + Unpin + Send + Sync>, u64)>,
+  },
+  Write {
+    truncate: bool,
+    upload: Option<UploadPipe>,
+  },
+}
+
+impl JupyterFile {
+  fn reading(fs: FsService, entry: Entry) -> Self {
+    Self { fs, path: entry.path.clone(), entry: Some(entry), position: 0, state: FileState::Read { reader: None } }
+  }
+
+  fn size(&self) -> u64 {
+    self.entry.as_ref().and_then(|entry| entry.size).unwrap_or(0)
+  }
+
+  async fn write_data(&mut self, data: Bytes) -> FsResult<()> {
+    let existing = self.size();
+    let FileState::Write { truncate, upload } = &mut self.state else {
+      return Err(DavError::Forbidden);
+    };
+    if upload.is_none() {
+      // Partial PUTs (Content-Range) can only continue a file at its end.
+      let append = !*truncate && existing > 0 && self.position == existing;
+      if !append && self.position != 0 {
+        debug!(path = %self.path, position = self.position, "WebDAV write inside an existing file; not supported");
+        return Err(DavError::NotImplemented);
+      }
+      *upload = Some(if append {
+        self.fs.append_pipe(&self.path, UPLOAD_CHUNK_SIZE)
+      } else {
+        self.fs.upload_pipe(&self.path, UPLOAD_CHUNK_SIZE)
+      });
+    }
+    let pipe = upload.as_mut().expect("upload started above");
+    if pipe.write(&data).await.is_err() {
+      // The upload task has stopped; flush reports its error.
+      return Err(DavError::GeneralFailure);
+    }
+    self.position += data.len() as u64;
+    Ok(())
+  }
+
+  async fn read_data(&mut self, count: usize) -> FsResult<Bytes> {
+    let position = self.position;
+    let FileState::Read { reader } = &mut self.state else {
+      return Err(DavError::Forbidden);
+    };
+    if position >= self.entry.as_ref().and_then(|entry| entry.size).unwrap_or(0) {
+      return Ok(Bytes::new());
+    }
+    let mut download = match reader.take() {
+      Some((download, offset)) if offset == position => download,
+      _ => self.fs.download_reader_from(&self.path, position).await.map_err(map_fs_error)?.reader,
+    };
+    let mut data = Vec::with_capacity(count);
+    if let Err(err) = (&mut download).take(count as u64).read_to_end(&mut data).await {
+      debug!(error = %err, path = %self.path, "WebDAV read failed");
+      return Err(DavError::GeneralFailure);
+    }
+    self.position += data.len() as u64;
+    *reader = Some((download, self.position));
+    Ok(Bytes::from(data))
+  }
+
+  async fn finish(&mut self) -> FsResult<()> {
+    let FileState::Write { truncate, upload } = &mut self.state else {
+      return Ok(());
+    };
+    match upload.take() {
+      Some(pipe) => {
+        let done = pipe.finish().await.map_err(map_fs_error)?;
+        debug!(path = %self.path, bytes = done.bytes, "WebDAV upload completed");
+        self.entry = Some(done.entry);
+      }
+      // An empty PUT still creates or truncates the file.
+      None if self.entry.is_none() || *truncate => {
+        self.entry = Some(self.fs.upload(&self.path, b"").await.map_err(map_fs_error)?);
+      }
+      None => {}
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Debug for JupyterFile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("JupyterFile")
+      .field("path", &self.path)
+      .field("position", &self.position)
+      .field("writing", &matches!(self.state, FileState::Write { .. }))
+      .finish()
+  }
+}
+
+impl DavFile for JupyterFile {
+  fn metadata(&mut self) -> FsFuture<'_, Box<dyn DavMetaData>> {
+    async move {
+      let entry = match &self.entry {
+        Some(entry) => entry.clone(),
+        None => self.fs.metadata(&self.path).await.map_err(map_fs_error)?,
+      };
+      Ok(Box::new(EntryMeta(entry)) as Box<dyn DavMetaData>)
+    }
+    .boxed()
+  }
+
+  fn write_buf(&mut self, mut buf: Box<dyn Buf + Send>) -> FsFuture<'_, ()> {
+    let data = buf.copy_to_bytes(buf.remaining());
+    self.write_data(data).boxed()
+  }
+
+  fn write_bytes(&mut self, buf: Bytes) -> FsFuture<'_, ()> {
+    self.write_data(buf).boxed()
+  }
+
+  fn read_bytes(&mut self, count: usize) -> FsFuture<'_, Bytes> {
+    self.read_data(count).boxed()
+  }
+
+  fn seek(&mut self, pos: SeekFrom) -> FsFuture<'_, u64> {
+    let target = match pos {
+      SeekFrom::Start(offset) => Some(offset),
+      SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+      SeekFrom::End(delta) => self.size().checked_add_signed(delta),
+    };
+    let writing = matches!(&self.state, FileState::Write { upload: Some(_), .. });
+    let result = match target {
+      // An upload in progress cannot move; reads reopen the download lazily.
+      Some(offset) if writing && offset != self.position => Err(DavError::NotImplemented),
+      Some(offset) => {
+        self.position = offset;
+        Ok(offset)
+      }
+      None => Err(DavError::GeneralFailure),
+    };
+    futures_util::future::ready(result).boxed()
+  }
+
+  fn flush(&mut self) -> FsFuture<'_, ()> {
+    self.finish().boxed()
+  }
+}
+
+/// The Contents API path for a WebDAV request path.
+fn jupyter_path(path: &DavPath) -> String {
+  path.as_rel_ospath().to_string_lossy().trim_end_matches('/').to_string()
+}
+
+fn map_fs_error(err: FsError) -> DavError {
+  debug!(error = %err, "FsService error surfaced to WebDAV client");
+  match err {
+    FsError::Client(ClientError::Api { status, .. }) => match status {
+      StatusCode::NOT_FOUND => DavError::NotFound,
+      StatusCode::FORBIDDEN | StatusCode::UNAUTHORIZED => DavError::Forbidden,
+      StatusCode::CONFLICT => DavError::Exists,
+      StatusCode::PAYLOAD_TOO_LARGE => DavError::TooLarge,
+      _ => DavError::GeneralFailure,
+    },
+    FsError::HiddenNotAllowed(_) => DavError::Forbidden,
+    FsError::NotImplemented(_) => DavError::NotImplemented,
+    FsError::NotAFile(_) | FsError::NotADirectory(_) => DavError::Forbidden,
+    _ => DavError::GeneralFailure,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn dav_paths_map_to_relative_contents_paths() {
+    let path = |raw: &str| jupyter_path(&DavPath::new(raw).unwrap());
+    assert_eq!(path("/"), "");
+    assert_eq!(path("/data/"), "data");
+    assert_eq!(path("/data/a%20b.csv"), "data/a b.csv");
+  }
+}